@@ -9,7 +9,7 @@ use test::Bencher;
 
 use lochnes::nes::ppu::PpuStep;
 use lochnes::nes::NesStep;
-use lochnes::{input, nes, rom, video};
+use lochnes::{audio, input, nes, rom, video};
 
 #[bench]
 fn bench_frames(b: &mut Bencher) {
@@ -22,7 +22,12 @@ fn bench_frames(b: &mut Bencher) {
     b.iter(|| {
         let video = video::NullVideo;
         let input = input::NullInput;
-        let io = nes::NesIoWith { video, input };
+        let audio = audio::NullAudio;
+        let io = nes::NesIoWith {
+            video,
+            input,
+            audio,
+        };
         let nes = nes::Nes::new(&io, rom.clone());
         let mut run_nes = nes.run();
 
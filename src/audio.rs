@@ -0,0 +1,268 @@
+use sdl2::audio::{AudioQueue, AudioSpecDesired};
+use sdl2::AudioSubsystem;
+use std::io::{self, Seek, SeekFrom, Write};
+use std::sync::RwLock;
+
+pub trait Audio {
+    // The rate (in samples per second) that the emulator should produce
+    // samples at for this audio output
+    fn sample_rate(&self) -> u32;
+    fn queue_sample(&self, sample: f32);
+}
+
+pub struct NullAudio;
+
+impl Audio for NullAudio {
+    fn sample_rate(&self) -> u32 {
+        44_100
+    }
+
+    fn queue_sample(&self, _sample: f32) {}
+}
+
+// An audio output that keeps every sample it receives, so that they can be
+// inspected later (useful for headless tests).
+pub struct BufferedAudio {
+    sample_rate: u32,
+    samples: RwLock<Vec<f32>>,
+}
+
+impl BufferedAudio {
+    pub fn new(sample_rate: u32) -> Self {
+        BufferedAudio {
+            sample_rate,
+            samples: RwLock::new(vec![]),
+        }
+    }
+
+    pub fn samples(&self) -> Vec<f32> {
+        self.samples.read().unwrap().clone()
+    }
+
+    pub fn take_samples(&self) -> Vec<f32> {
+        let mut samples = self.samples.write().unwrap();
+        samples.drain(..).collect()
+    }
+}
+
+impl Audio for BufferedAudio {
+    fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    fn queue_sample(&self, sample: f32) {
+        let mut samples = self.samples.write().unwrap();
+        samples.push(sample);
+    }
+}
+
+pub struct QueueBufferedAudio {
+    buffer: RwLock<Vec<f32>>,
+    sample_rate: u32,
+    queue: AudioQueue<f32>,
+}
+
+impl QueueBufferedAudio {
+    pub fn new(audio_subsystem: &AudioSubsystem, sample_rate: u32) -> Result<Self, String> {
+        let spec = AudioSpecDesired {
+            freq: Some(sample_rate as i32),
+            channels: Some(1),
+            samples: None,
+        };
+        let queue = audio_subsystem.open_queue::<f32, _>(None, &spec)?;
+        queue.resume();
+
+        // SDL may not give us the exact sample rate we asked for, so produce
+        // samples at whatever rate we actually got
+        let sample_rate = queue.spec().freq as u32;
+        let buffer = RwLock::new(vec![]);
+
+        Ok(QueueBufferedAudio {
+            buffer,
+            sample_rate,
+            queue,
+        })
+    }
+
+    // Send all buffered samples to the SDL audio queue. This should be
+    // called once per frame.
+    pub fn flush(&self) -> Result<(), String> {
+        let mut buffer = self.buffer.write().unwrap();
+        let queued = self.queue.queue(&buffer);
+        buffer.clear();
+
+        if queued {
+            Ok(())
+        } else {
+            Err(sdl2::get_error())
+        }
+    }
+}
+
+impl Audio for QueueBufferedAudio {
+    fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    fn queue_sample(&self, sample: f32) {
+        let mut buffer = self.buffer.write().unwrap();
+        buffer.push(sample);
+    }
+}
+
+// An audio output that writes every sample it receives to a WAV file as
+// 16-bit mono PCM. Samples are buffered in memory until `flush` is called.
+pub struct WavRecorder<W>
+where
+    W: Write + Seek,
+{
+    sample_rate: u32,
+    buffer: RwLock<Vec<f32>>,
+    wav: RwLock<WavFile<W>>,
+}
+
+struct WavFile<W> {
+    writer: W,
+    data_len: u32,
+}
+
+impl<W> WavRecorder<W>
+where
+    W: Write + Seek,
+{
+    pub fn new(mut writer: W, sample_rate: u32) -> io::Result<Self> {
+        write_wav_header(&mut writer, sample_rate, 0)?;
+
+        Ok(WavRecorder {
+            sample_rate,
+            buffer: RwLock::new(vec![]),
+            wav: RwLock::new(WavFile {
+                writer,
+                data_len: 0,
+            }),
+        })
+    }
+
+    // Write all buffered samples to the WAV file, and update the header
+    // to match. The file is left valid after each flush, so it can be
+    // used even if the emulator never exits cleanly.
+    pub fn flush(&self) -> io::Result<()> {
+        let mut buffer = self.buffer.write().unwrap();
+        let mut wav = self.wav.write().unwrap();
+
+        for &sample in buffer.iter() {
+            let sample = (sample.max(-1.0).min(1.0) * i16::max_value() as f32) as i16;
+            wav.writer.write_all(&sample.to_le_bytes())?;
+            wav.data_len += 2;
+        }
+        buffer.clear();
+
+        let data_len = wav.data_len;
+        wav.writer.seek(SeekFrom::Start(0))?;
+        write_wav_header(&mut wav.writer, self.sample_rate, data_len)?;
+        wav.writer.seek(SeekFrom::End(0))?;
+        wav.writer.flush()?;
+
+        Ok(())
+    }
+
+    pub fn into_inner(self) -> W {
+        self.wav.into_inner().unwrap().writer
+    }
+}
+
+impl<W> Audio for WavRecorder<W>
+where
+    W: Write + Seek,
+{
+    fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    fn queue_sample(&self, sample: f32) {
+        let mut buffer = self.buffer.write().unwrap();
+        buffer.push(sample);
+    }
+}
+
+fn write_wav_header(writer: &mut impl Write, sample_rate: u32, data_len: u32) -> io::Result<()> {
+    const CHANNELS: u16 = 1;
+    const BITS_PER_SAMPLE: u16 = 16;
+    const BLOCK_ALIGN: u16 = CHANNELS * BITS_PER_SAMPLE / 8;
+
+    writer.write_all(b"RIFF")?;
+    writer.write_all(&(36 + data_len).to_le_bytes())?;
+    writer.write_all(b"WAVE")?;
+
+    writer.write_all(b"fmt ")?;
+    writer.write_all(&16_u32.to_le_bytes())?;
+    writer.write_all(&1_u16.to_le_bytes())?; // PCM
+    writer.write_all(&CHANNELS.to_le_bytes())?;
+    writer.write_all(&sample_rate.to_le_bytes())?;
+    writer.write_all(&(sample_rate * BLOCK_ALIGN as u32).to_le_bytes())?;
+    writer.write_all(&BLOCK_ALIGN.to_le_bytes())?;
+    writer.write_all(&BITS_PER_SAMPLE.to_le_bytes())?;
+
+    writer.write_all(b"data")?;
+    writer.write_all(&data_len.to_le_bytes())?;
+
+    Ok(())
+}
+
+// An audio output that forwards samples to another audio output, and
+// optionally records them with a `WavRecorder` too.
+pub struct RecordingAudio<A, W>
+where
+    A: Audio,
+    W: Write + Seek,
+{
+    audio: A,
+    recorder: Option<WavRecorder<W>>,
+}
+
+impl<A, W> RecordingAudio<A, W>
+where
+    A: Audio,
+    W: Write + Seek,
+{
+    pub fn new(audio: A, recorder: Option<WavRecorder<W>>) -> Self {
+        RecordingAudio { audio, recorder }
+    }
+
+    pub fn flush_recording(&self) -> io::Result<()> {
+        match &self.recorder {
+            Some(recorder) => recorder.flush(),
+            None => Ok(()),
+        }
+    }
+}
+
+impl<A, W> Audio for RecordingAudio<A, W>
+where
+    A: Audio,
+    W: Write + Seek,
+{
+    fn sample_rate(&self) -> u32 {
+        self.audio.sample_rate()
+    }
+
+    fn queue_sample(&self, sample: f32) {
+        self.audio.queue_sample(sample);
+        if let Some(recorder) = &self.recorder {
+            recorder.queue_sample(sample);
+        }
+    }
+}
+
+impl<'a, A> Audio for &'a A
+where
+    A: Audio,
+{
+    fn sample_rate(&self) -> u32 {
+        (*self).sample_rate()
+    }
+
+    fn queue_sample(&self, sample: f32) {
+        (*self).queue_sample(sample);
+    }
+}
@@ -6,6 +6,7 @@
     generator_trait
 )]
 
+pub mod audio;
 #[macro_use]
 pub mod gen_utils;
 pub mod input;
@@ -7,22 +7,25 @@
 )]
 
 use log::{debug, info, trace};
+use nes::apu::ApuChannel;
 use nes::ppu::PpuStep;
 use nes::NesStep;
 use sdl2::controller::Button as SdlButton;
 use sdl2::event::Event as SdlEvent;
 use sdl2::keyboard::Keycode as SdlKeycode;
+use sdl2::keyboard::Mod as SdlKeymod;
 use std::fs;
-use std::io;
+use std::io::{self, Write};
 use std::ops::{Generator, GeneratorState};
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::pin::Pin;
 use std::process;
 use std::thread;
 use std::time::{Duration, Instant};
-use structopt::StructOpt;
+use structopt::{clap, StructOpt};
 
-use lochnes::{input, nes, rom, video};
+use lochnes::audio::Audio;
+use lochnes::{audio, input, nes, rom, video};
 
 fn main() {
     let opts = Options::from_args();
@@ -46,12 +49,31 @@ fn main() {
 #[derive(StructOpt, Debug)]
 #[structopt(name = "lochnes")]
 struct Options {
+    // Required, unless running a subcommand
     #[structopt(name = "ROM", parse(from_os_str))]
-    rom: PathBuf,
+    rom: Option<PathBuf>,
+
+    #[structopt(subcommand)]
+    command: Option<Command>,
 
     #[structopt(long = "scale")]
     scale: Option<u32>,
 
+    #[structopt(long = "record-audio", parse(from_os_str))]
+    record_audio: Option<PathBuf>,
+
+    #[structopt(long = "trace", parse(from_os_str))]
+    trace: Option<PathBuf>,
+
+    #[structopt(long = "headless")]
+    headless: bool,
+
+    #[structopt(long = "frames")]
+    frames: Option<u64>,
+
+    #[structopt(long = "track")]
+    track: Option<u8>,
+
     #[structopt(short = "q", long = "quiet")]
     quiet: bool,
 
@@ -59,9 +81,29 @@ struct Options {
     verbose: u8,
 }
 
+#[derive(StructOpt, Debug)]
+enum Command {
+    /// Print the disassembly of one 16KB bank of a ROM's PRG ROM
+    #[structopt(name = "disasm")]
+    Disasm {
+        #[structopt(name = "ROM", parse(from_os_str))]
+        rom: PathBuf,
+
+        #[structopt(long = "bank", default_value = "0")]
+        bank: usize,
+    },
+}
+
 fn run(opts: Options) -> Result<(), LochnesError> {
     debug!("Options: {:#?}", opts);
 
+    match opts.command {
+        Some(Command::Disasm { ref rom, bank }) => {
+            return disasm(rom, bank);
+        }
+        None => {}
+    }
+
     #[cfg(feature = "easter-egg")]
     {
         if opts.verbose == 6 {
@@ -70,25 +112,102 @@ fn run(opts: Options) -> Result<(), LochnesError> {
             let nmi = &mut bytes[0x400C];
             *nmi = nmi.wrapping_add(opts.verbose / 2);
             let rom = rom::Rom::from_bytes(bytes.into_iter())?;
-            run_rom(opts, rom)?;
+            run_rom(opts, Cartridge::Rom(rom))?;
             return Ok(());
         }
     }
 
-    let bytes = fs::read(&opts.rom)?;
-    let rom = rom::Rom::from_bytes(bytes.into_iter())?;
+    let rom_path = match opts.rom {
+        Some(ref rom_path) => rom_path,
+        None => {
+            clap::Error::with_description(
+                "The following required arguments were not provided:\n    <ROM>",
+                clap::ErrorKind::MissingRequiredArgument,
+            )
+            .exit();
+        }
+    };
+    let bytes = fs::read(rom_path)?;
+    let cartridge = if bytes.starts_with(b"NESM\x1A") {
+        let nsf = rom::Nsf::from_bytes(bytes.into_iter())?;
+
+        debug!("NSF header: {:#04X?}", nsf.header);
+
+        let track = opts.track.unwrap_or(nsf.header.starting_song);
+        if track == 0 || track > nsf.header.total_songs {
+            return Err(LochnesError::InvalidTrack(track));
+        }
+
+        info!(
+            "Playing track {}/{} of {:?} by {:?}",
+            track, nsf.header.total_songs, nsf.header.song_name, nsf.header.artist
+        );
+
+        Cartridge::Nsf {
+            nsf,
+            song: track - 1,
+        }
+    } else {
+        let rom = rom::Rom::from_bytes(bytes.into_iter())?;
+
+        debug!("ROM header: {:#04X?}", rom.header);
+
+        Cartridge::Rom(rom)
+    };
 
-    debug!("ROM header: {:#04X?}", rom.header);
+    run_rom(opts, cartridge)?;
 
-    run_rom(opts, rom)?;
+    Ok(())
+}
+
+fn disasm(rom_path: &Path, bank: usize) -> Result<(), LochnesError> {
+    let bytes = fs::read(rom_path)?;
+    let rom = rom::Rom::from_bytes(bytes.into_iter())?;
+
+    let banks: Vec<_> = rom.prg_rom.chunks(0x4000).collect();
+    let bank_bytes = banks.get(bank).ok_or(LochnesError::InvalidBank(bank))?;
+
+    // Both NROM and UxROM map the last bank to $C000, and UxROM switches
+    // the other banks in at $8000
+    let base_addr = if bank == banks.len() - 1 {
+        0xC000
+    } else {
+        0x8000
+    };
+
+    let stdout = io::stdout();
+    let mut stdout = stdout.lock();
+    for op in nes::cpu::disassemble(bank_bytes, base_addr) {
+        writeln!(stdout, "{}", op)?;
+    }
 
     Ok(())
 }
 
-fn run_rom(opts: Options, rom: rom::Rom) -> Result<(), LochnesError> {
+// Either a normal game ROM, or a song from an NSF music file
+enum Cartridge {
+    Rom(rom::Rom),
+    Nsf { nsf: rom::Nsf, song: u8 },
+}
+
+impl Cartridge {
+    fn into_nes<'a, I: nes::NesIo>(self, io: &'a I) -> nes::Nes<'a, I> {
+        match self {
+            Cartridge::Rom(rom) => nes::Nes::new(io, rom),
+            Cartridge::Nsf { nsf, song } => nes::Nes::from_nsf(io, nsf, song),
+        }
+    }
+}
+
+fn run_rom(opts: Options, cartridge: Cartridge) -> Result<(), LochnesError> {
     const NES_REFRESH_RATE: Duration = Duration::from_nanos(1_000_000_000 / 60);
     const NES_WIDTH: u32 = 256;
     const NES_HEIGHT: u32 = 240;
+    const AUDIO_SAMPLE_RATE: u32 = 44_100;
+
+    if opts.headless {
+        return run_rom_headless(opts, cartridge);
+    }
 
     let scale = opts.scale.unwrap_or(1);
 
@@ -97,6 +216,7 @@ fn run_rom(opts: Options, rom: rom::Rom) -> Result<(), LochnesError> {
 
     let sdl = sdl2::init().map_err(LochnesError::Sdl2Error)?;
     let sdl_video = sdl.video().map_err(LochnesError::Sdl2Error)?;
+    let sdl_audio = sdl.audio().map_err(LochnesError::Sdl2Error)?;
     let sdl_window = sdl_video
         .window("Lochnes", window_width, window_height)
         .opengl()
@@ -125,11 +245,25 @@ fn run_rom(opts: Options, rom: rom::Rom) -> Result<(), LochnesError> {
     let video = &video::TextureBufferedVideo::new(&sdl_texture_creator, NES_WIDTH, NES_HEIGHT)?;
     let mut input_state = input::InputState::default();
     let input = &input::SampledInput::new(input_state);
-    let io = nes::NesIoWith { video, input };
-    let nes = nes::Nes::new(&io, rom);
+    let queue_audio = &audio::QueueBufferedAudio::new(&sdl_audio, AUDIO_SAMPLE_RATE)
+        .map_err(LochnesError::Sdl2Error)?;
+    let audio_recorder = create_audio_recorder(&opts, queue_audio.sample_rate())?;
+    let audio = &audio::RecordingAudio::new(queue_audio, audio_recorder);
+    let io = nes::NesIoWith {
+        video,
+        input,
+        audio,
+    };
+    let nes = cartridge.into_nes(&io);
     let mut run_nes = nes.run();
+    let mut trace_log = create_trace_log(&opts)?;
 
+    let mut frame = 0;
     'running: loop {
+        if opts.frames.map_or(false, |frames| frame >= frames) {
+            break 'running;
+        }
+
         let frame_start = Instant::now();
         for event in sdl_event_pump.poll_iter() {
             match event {
@@ -308,6 +442,48 @@ fn run_rom(opts: Options, rom: rom::Rom) -> Result<(), LochnesError> {
                 } => {
                     input_state.joypad_1.right = false;
                 }
+                SdlEvent::KeyDown {
+                    keycode: Some(SdlKeycode::R),
+                    keymod,
+                    repeat: false,
+                    ..
+                } => {
+                    if keymod.intersects(SdlKeymod::LSHIFTMOD | SdlKeymod::RSHIFTMOD) {
+                        info!("Power cycle");
+                        nes.power_cycle();
+                    } else {
+                        info!("Reset");
+                        nes.reset();
+                    }
+                }
+                SdlEvent::KeyDown {
+                    keycode: Some(SdlKeycode::Num0),
+                    repeat: false,
+                    ..
+                } => {
+                    info!("Reset APU channel controls");
+                    nes.apu.reset_channel_controls();
+                }
+                SdlEvent::KeyDown {
+                    keycode: Some(keycode),
+                    keymod,
+                    repeat: false,
+                    ..
+                } => {
+                    // [1]-[5] toggle muting each APU channel, and
+                    // [Shift]+[1]-[5] toggle soloing each channel
+                    if let Some(channel) = apu_channel_for_keycode(keycode) {
+                        if keymod.intersects(SdlKeymod::LSHIFTMOD | SdlKeymod::RSHIFTMOD) {
+                            let solo = !nes.apu.is_solo(channel);
+                            info!("Set {:?} solo: {}", channel, solo);
+                            nes.apu.set_solo(channel, solo);
+                        } else {
+                            let muted = !nes.apu.is_muted(channel);
+                            info!("Set {:?} muted: {}", channel, muted);
+                            nes.apu.set_muted(channel, muted);
+                        }
+                    }
+                }
                 _ => {}
             }
         }
@@ -315,24 +491,15 @@ fn run_rom(opts: Options, rom: rom::Rom) -> Result<(), LochnesError> {
         input.set_state(input_state);
         debug!("Input: {:?}", input_state);
 
-        loop {
-            match Pin::new(&mut run_nes).resume(()) {
-                GeneratorState::Yielded(NesStep::Ppu(PpuStep::Vblank)) => {
-                    break;
-                }
-                GeneratorState::Yielded(NesStep::Cpu(nes::cpu::CpuStep::Op(op))) => {
-                    trace!("{:X?}", nes.cpu);
-                    trace!("${:04X}: {}", op.pc, op.op);
-                    trace!("----------");
-                }
-                GeneratorState::Yielded(_) => {}
-            }
-        }
+        run_frame(&nes, &mut run_nes, &mut trace_log)?;
+        frame += 1;
 
         video
             .copy_to(&mut sdl_canvas)
             .map_err(LochnesError::Sdl2Error)?;
         sdl_canvas.present();
+        queue_audio.flush().map_err(LochnesError::Sdl2Error)?;
+        audio.flush_recording()?;
 
         let elapsed = frame_start.elapsed();
         info!("frame time: {:5.2}ms", elapsed.as_micros() as f64 / 1_000.0);
@@ -344,11 +511,105 @@ fn run_rom(opts: Options, rom: rom::Rom) -> Result<(), LochnesError> {
     Ok(())
 }
 
+fn apu_channel_for_keycode(keycode: SdlKeycode) -> Option<ApuChannel> {
+    match keycode {
+        SdlKeycode::Num1 => Some(ApuChannel::Pulse1),
+        SdlKeycode::Num2 => Some(ApuChannel::Pulse2),
+        SdlKeycode::Num3 => Some(ApuChannel::Triangle),
+        SdlKeycode::Num4 => Some(ApuChannel::Noise),
+        SdlKeycode::Num5 => Some(ApuChannel::Dmc),
+        _ => None,
+    }
+}
+
+fn run_rom_headless(opts: Options, cartridge: Cartridge) -> Result<(), LochnesError> {
+    let audio_recorder = create_audio_recorder(&opts, audio::NullAudio.sample_rate())?;
+    let io = nes::NesIoWith {
+        video: video::NullVideo,
+        input: input::NullInput,
+        audio: audio::RecordingAudio::new(audio::NullAudio, audio_recorder),
+    };
+    let nes = cartridge.into_nes(&io);
+    let mut run_nes = nes.run();
+    let mut trace_log = create_trace_log(&opts)?;
+
+    let mut frame = 0;
+    while opts.frames.map_or(true, |frames| frame < frames) {
+        let frame_start = Instant::now();
+
+        run_frame(&nes, &mut run_nes, &mut trace_log)?;
+        frame += 1;
+
+        io.audio.flush_recording()?;
+
+        let elapsed = frame_start.elapsed();
+        info!("frame time: {:5.2}ms", elapsed.as_micros() as f64 / 1_000.0);
+    }
+
+    Ok(())
+}
+
+// Run the NES until the start of the next vblank
+fn run_frame(
+    nes: &nes::Nes<impl nes::NesIo>,
+    run_nes: &mut (impl Generator<Yield = NesStep, Return = !> + Unpin),
+    trace_log: &mut Option<io::BufWriter<fs::File>>,
+) -> Result<(), LochnesError> {
+    loop {
+        match Pin::new(&mut *run_nes).resume(()) {
+            GeneratorState::Yielded(NesStep::Ppu(PpuStep::Vblank)) => {
+                break;
+            }
+            GeneratorState::Yielded(NesStep::Cpu(nes::cpu::CpuStep::Start)) => {
+                if let Some(trace_log) = trace_log {
+                    writeln!(trace_log, "{}", nes::trace::trace_line(nes))?;
+                }
+            }
+            GeneratorState::Yielded(NesStep::Cpu(nes::cpu::CpuStep::Op(op))) => {
+                trace!("{:X?}", nes.cpu);
+                trace!("${:04X}: {}", op.pc, op.op);
+                trace!("----------");
+            }
+            GeneratorState::Yielded(_) => {}
+        }
+    }
+
+    Ok(())
+}
+
+// Open the `--trace` log, which gets a line before each instruction the
+// CPU runs
+fn create_trace_log(opts: &Options) -> Result<Option<io::BufWriter<fs::File>>, LochnesError> {
+    let trace_log = match &opts.trace {
+        Some(path) => Some(io::BufWriter::new(fs::File::create(path)?)),
+        None => None,
+    };
+
+    Ok(trace_log)
+}
+
+fn create_audio_recorder(
+    opts: &Options,
+    sample_rate: u32,
+) -> Result<Option<audio::WavRecorder<io::BufWriter<fs::File>>>, LochnesError> {
+    let recorder = match &opts.record_audio {
+        Some(path) => {
+            let file = io::BufWriter::new(fs::File::create(path)?);
+            Some(audio::WavRecorder::new(file, sample_rate)?)
+        }
+        None => None,
+    };
+
+    Ok(recorder)
+}
+
 #[derive(Debug)]
 enum LochnesError {
     IoError(io::Error),
     RomError(rom::RomError),
     Sdl2Error(String),
+    InvalidTrack(u8),
+    InvalidBank(usize),
 }
 
 impl From<io::Error> for LochnesError {
@@ -1,17 +1,23 @@
+use crate::audio::Audio;
 use crate::input::{Input, InputState};
-use crate::rom::Rom;
+use crate::rom::{Nsf, Rom, TvSystem};
 use crate::video::Video;
-use cpu::{Cpu, CpuStep};
-use mapper::Mapper;
+use apu::{Apu, ApuStep};
+use cpu::{Bus, Cpu, CpuStep};
+use dma::{Dma, DmaStep};
+use mapper::{Mapper, NsfMapper};
 use ppu::{Ppu, PpuStep};
 use std::cell::Cell;
 use std::ops::{Generator, GeneratorState};
 use std::pin::Pin;
 use std::u8;
 
+pub mod apu;
 pub mod cpu;
+pub mod dma;
 pub mod mapper;
 pub mod ppu;
+pub mod trace;
 
 #[derive(Clone)]
 pub struct Nes<'a, I>
@@ -24,6 +30,12 @@ where
     pub ram: Cell<[u8; 0x0800]>,
     pub cpu: Cpu,
     pub ppu: Ppu,
+    pub apu: Apu,
+    pub dma: Dma,
+
+    // The last value driven on the CPU's data bus. Reading from an
+    // address that nothing responds to returns this value (open bus)
+    pub open_bus: Cell<u8>,
 }
 
 impl<'a, I> Nes<'a, I>
@@ -31,10 +43,26 @@ where
     I: NesIo,
 {
     pub fn new(io: &'a I, rom: Rom) -> Self {
+        let tv_system = rom.header.tv_system;
+        let mapper = Mapper::from_rom(rom);
+
+        Nes::with_mapper(io, mapper, tv_system)
+    }
+
+    // Create a NES that plays `song` (starting at 0) from an NSF file
+    pub fn from_nsf(io: &'a I, nsf: Nsf, song: u8) -> Self {
+        let tv_system = nsf.header.tv_system;
+        let mapper = Mapper::Nsf(NsfMapper::new(nsf, song));
+
+        Nes::with_mapper(io, mapper, tv_system)
+    }
+
+    fn with_mapper(io: &'a I, mapper: Mapper, tv_system: TvSystem) -> Self {
         let ram = Cell::new([0; 0x0800]);
         let cpu = Cpu::new();
         let ppu = Ppu::new();
-        let mapper = Mapper::from_rom(rom);
+        let apu = Apu::new(tv_system);
+        let dma = Dma::new();
         let input_reader = InputReader::new(io.input());
 
         let nes = Nes {
@@ -44,15 +72,39 @@ where
             ram,
             cpu,
             ppu,
+            apu,
+            dma,
+            open_bus: Cell::new(0x00),
         };
 
-        let reset_addr = nes.read_u16(0xFFFC);
-
-        nes.cpu.pc.set(reset_addr);
+        // Start from the CPU's power-up state, so the first thing it does
+        // is run its reset sequence
+        nes.cpu.power_cycle();
 
         nes
     }
 
+    // Press the reset button. The CPU runs its reset sequence after the
+    // current instruction finishes
+    pub fn reset(&self) {
+        self.cpu.reset.set(true);
+        self.dma.reset();
+        self.ppu.reset();
+        self.apu.reset();
+        self.mapper.reset();
+    }
+
+    // Turn the NES off and on again: RAM is cleared, and every component
+    // goes back to its power-up state before the CPU runs its reset sequence
+    pub fn power_cycle(&self) {
+        self.ram.set([0; 0x0800]);
+        self.cpu.power_cycle();
+        self.ppu.power_cycle();
+        self.apu.power_cycle();
+        self.dma.reset();
+        self.mapper.power_cycle();
+    }
+
     fn ram(&self) -> &[Cell<u8>] {
         let ram: &Cell<[u8]> = &self.ram;
         ram.as_slice_of_cells()
@@ -60,46 +112,57 @@ where
 
     pub fn read_u8(&self, addr: u16) -> u8 {
         let ram = self.ram();
+        let open_bus = self.open_bus.get();
+        let addr = mirrored_addr(addr);
 
-        match addr {
+        let value = match addr {
             0x0000..=0x07FF => ram[addr as usize].get(),
             0x2002 => self.ppu.ppustatus(),
+            0x2004 => self.ppu.read_oamdata(),
             0x2007 => self.ppu.read_ppudata(self),
-            0x4000..=0x4007 => {
-                // TODO: Return APU pulse
-                0x00
-            }
-            0x4008..=0x400B => {
-                // TODO: Return APU triangle
-                0x00
-            }
-            0x400C..=0x400F => {
-                // TODO: Return APU noise
-                0x00
-            }
-            0x4010..=0x4013 => {
-                // TODO: Return APU DMC
-                0x00
+            0x2000..=0x2007 => {
+                // The PPU's write-only registers return its own latch
+                self.ppu.io_latch.get()
             }
             0x4015 => {
-                // TODO: Return APU status
-                0x00
+                // $4015 is read inside the CPU, so the value doesn't reach
+                // the data bus, and bit 5 comes from the previous value
+                let status = self.apu.read_status();
+                return (status & !0b_0010_0000) | (open_bus & 0b_0010_0000);
             }
             0x4016 => {
-                // TODO: Handle open bus behavior!
-                match self.input_reader.read_port_1_data() {
+                // Only the low bits are driven by the controller port
+                let data = match self.input_reader.read_port_1_data() {
                     true => 0b_0000_0001,
                     false => 0b_0000_0000,
-                }
+                };
+                (open_bus & 0b_1110_0000) | data
             }
             0x4017 => {
                 // TODO: Return joystick state
-                0x40
+                open_bus & 0b_1110_0000
             }
-            0x6000..=0xFFFF => self.mapper.read_u8(addr),
+            0x4020..=0xFFFF => self.mapper.read_u8(addr).unwrap_or(open_bus),
             _ => {
-                unimplemented!("Unhandled read from address: 0x{:X}", addr);
+                // Write-only registers and unused addresses
+                open_bus
             }
+        };
+
+        self.open_bus.set(value);
+        value
+    }
+
+    // Read a byte without any of the side effects of a normal read (such
+    // as clearing the vblank flag), for debugging output. Like
+    // Nintendulator, I/O registers always read as $FF
+    pub fn peek_u8(&self, addr: u16) -> u8 {
+        let ram = self.ram();
+
+        match addr {
+            0x0000..=0x1FFF => ram[addr as usize % 0x0800].get(),
+            0x2000..=0x401F => 0xFF,
+            0x4020..=0xFFFF => self.mapper.read_u8(addr).unwrap_or(0xFF),
         }
     }
 
@@ -112,6 +175,13 @@ where
 
     pub fn write_u8(&self, addr: u16, value: u8) {
         let ram = self.ram();
+        let addr = mirrored_addr(addr);
+        self.open_bus.set(value);
+
+        if let 0x2000..=0x2007 = addr {
+            // Writing to any PPU register fills the PPU's latch
+            self.ppu.io_latch.set(value);
+        }
 
         match addr {
             0x0000..=0x07FF => {
@@ -138,23 +208,71 @@ where
             0x2007 => {
                 self.ppu.write_ppudata(self, value);
             }
-            0x4000..=0x4007 => {
-                // TODO: APU pulse
+            0x4000 => {
+                self.apu.pulse_1.write_control(value);
+            }
+            0x4001 => {
+                self.apu.pulse_1.write_sweep(value);
+            }
+            0x4002 => {
+                self.apu.pulse_1.write_timer_lo(value);
+            }
+            0x4003 => {
+                self.apu.pulse_1.write_timer_hi(value);
+            }
+            0x4004 => {
+                self.apu.pulse_2.write_control(value);
+            }
+            0x4005 => {
+                self.apu.pulse_2.write_sweep(value);
+            }
+            0x4006 => {
+                self.apu.pulse_2.write_timer_lo(value);
+            }
+            0x4007 => {
+                self.apu.pulse_2.write_timer_hi(value);
+            }
+            0x4008 => {
+                self.apu.triangle.write_linear_counter(value);
+            }
+            0x4009 => {
+                // Unused
+            }
+            0x400A => {
+                self.apu.triangle.write_timer_lo(value);
+            }
+            0x400B => {
+                self.apu.triangle.write_timer_hi(value);
+            }
+            0x400C => {
+                self.apu.noise.write_control(value);
+            }
+            0x400D => {
+                // Unused
+            }
+            0x400E => {
+                self.apu.noise.write_period(value);
             }
-            0x4008..=0x400B => {
-                // TODO: APU triangle
+            0x400F => {
+                self.apu.noise.write_length(value);
             }
-            0x400C..=0x400F => {
-                // TODO: APU noise
+            0x4010 => {
+                self.apu.dmc.write_control(value);
             }
-            0x4010..=0x4013 => {
-                // TODO: APU DMC
+            0x4011 => {
+                self.apu.dmc.write_direct_load(value);
+            }
+            0x4012 => {
+                self.apu.dmc.write_sample_addr(value);
+            }
+            0x4013 => {
+                self.apu.dmc.write_sample_length(value);
             }
             0x4014 => {
-                self.copy_oam_dma(value);
+                self.dma.start_oam_dma(value);
             }
             0x4015 => {
-                // TODO: APU sound channel control
+                self.apu.write_status(value);
             }
             0x4016 => {
                 let strobe = (value & 0b_0000_0001) != 0;
@@ -165,34 +283,19 @@ where
                 }
             }
             0x4017 => {
-                // TODO: Implement APU frame counter
+                // NOTE: Reading $4017 reads the controller 2 port, but
+                // writing to it controls the APU frame counter
+                self.apu.frame_counter.write_control(value);
             }
-            0x6000..=0xFFFF => {
+            0x4020..=0xFFFF => {
                 self.mapper.write_u8(addr, value);
             }
             _ => {
-                unimplemented!("Unhandled write to address: 0x{:X}", addr);
+                // Read-only registers and unused addresses
             }
         }
     }
 
-    fn push_u8(&self, value: u8) {
-        let s = self.cpu.s.get();
-        let stack_addr = 0x0100 | s as u16;
-
-        self.write_u8(stack_addr, value);
-
-        self.cpu.s.set(s.wrapping_sub(1));
-    }
-
-    fn push_u16(&self, value: u16) {
-        let value_hi = ((0xFF00 & value) >> 8) as u8;
-        let value_lo = (0x00FF & value) as u8;
-
-        self.push_u8(value_hi);
-        self.push_u8(value_lo);
-    }
-
     pub fn read_ppu_u8(&self, addr: u16) -> u8 {
         let palette_ram = self.ppu.palette_ram();
 
@@ -207,7 +310,8 @@ where
                 palette_ram[offset].get()
             }
             0x4000..=0xFFFF => {
-                unimplemented!("Tried to read from PPU address ${:04X}", addr);
+                // The PPU's address bus is only 14 bits wide
+                self.read_ppu_u8(addr & 0x3FFF)
             }
         }
     }
@@ -236,41 +340,59 @@ where
                 palette_ram[offset].set(value);
             }
             0x4000..=0xFFFF => {
-                unimplemented!("Tried to write to PPU address ${:04X}", addr);
+                // The PPU's address bus is only 14 bits wide
+                self.write_ppu_u8(addr & 0x3FFF, value);
             }
         }
     }
 
-    fn copy_oam_dma(&self, page: u8) {
-        let target_addr_start = self.ppu.oam_addr.get() as u16;
-        let mut oam = self.ppu.oam.get();
-        for index in 0x00..=0xFF {
-            let source_addr = ((page as u16) << 8) | index;
-            let byte = self.read_u8(source_addr);
-
-            let target_addr = (target_addr_start + index) as usize % oam.len();
-            oam[target_addr] = byte;
-        }
-
-        self.ppu.oam.set(oam);
-    }
-
     pub fn run(&'a self) -> impl Generator<Yield = NesStep, Return = !> + 'a {
-        let mut run_cpu = Cpu::run(&self);
+        let mut run_cpu = Cpu::run(&self.cpu, self);
 
         let mut run_ppu = Ppu::run(&self);
 
+        let mut run_apu = Apu::run(&self);
+
+        let mut run_dma = Dma::run(&self);
+
         move || loop {
-            // TODO: Clean this up
+            let dma_step = match Pin::new(&mut run_dma).resume(()) {
+                GeneratorState::Yielded(dma_step) => dma_step,
+            };
+            match dma_step {
+                DmaStep::Cycle => {
+                    // The CPU is halted, so the CPU cycle passes without
+                    // resuming the CPU
+                    self.cpu.cycles.update(|cycles| cycles + 1);
+                    yield NesStep::Cpu(CpuStep::Cycle);
+                }
+                DmaStep::Idle => {
+                    // The CPU polls its interrupt lines before each cycle
+                    // it runs, so it doesn't poll while it's halted
+                    loop {
+                        match Pin::new(&mut run_cpu).resume(()) {
+                            GeneratorState::Yielded(cpu_step @ CpuStep::Cycle) => {
+                                yield NesStep::Cpu(cpu_step);
+                                break;
+                            }
+                            GeneratorState::Yielded(cpu_step) => {
+                                yield NesStep::Cpu(cpu_step);
+                            }
+                        }
+                    }
+                }
+            }
+
+            self.dma.end_cycle();
+
+            self.mapper.clock(self);
+
             loop {
-                match Pin::new(&mut run_cpu).resume(()) {
-                    GeneratorState::Yielded(cpu_step @ CpuStep::Cycle) => {
-                        yield NesStep::Cpu(cpu_step);
+                match Pin::new(&mut run_apu).resume(()) {
+                    GeneratorState::Yielded(apu_step @ ApuStep::Cycle) => {
+                        yield NesStep::Apu(apu_step);
                         break;
                     }
-                    GeneratorState::Yielded(cpu_step) => {
-                        yield NesStep::Cpu(cpu_step);
-                    }
                 }
             }
 
@@ -291,37 +413,72 @@ where
     }
 }
 
+impl<'a, I> Bus for Nes<'a, I>
+where
+    I: NesIo,
+{
+    fn read_u8(&self, addr: u16) -> u8 {
+        Nes::read_u8(self, addr)
+    }
+
+    fn write_u8(&self, addr: u16, value: u8) {
+        Nes::write_u8(self, addr, value)
+    }
+
+    fn peek_u8(&self, addr: u16) -> u8 {
+        Nes::peek_u8(self, addr)
+    }
+}
+
+// Internal RAM repeats every 2KB up to $1FFF, and the PPU's 8 registers
+// repeat up to $3FFF
+fn mirrored_addr(addr: u16) -> u16 {
+    match addr {
+        0x0000..=0x1FFF => addr & 0x07FF,
+        0x2000..=0x3FFF => addr & 0x2007,
+        _ => addr,
+    }
+}
+
 pub enum NesStep {
     Cpu(CpuStep),
     Ppu(PpuStep),
+    Apu(ApuStep),
 }
 
-// A trait that encapsulates NES I/O traits (`Video` and `Input`), allowing
-// code that uses `Nes` to only take or return a single generic parameter.
+// A trait that encapsulates NES I/O traits (`Video`, `Input`, and `Audio`),
+// allowing code that uses `Nes` to only take or return a single
+// generic parameter.
 pub trait NesIo {
     type Video: Video;
     type Input: Input;
+    type Audio: Audio;
 
     fn video(&self) -> &Self::Video;
     fn input(&self) -> &Self::Input;
+    fn audio(&self) -> &Self::Audio;
 }
 
-pub struct NesIoWith<V, I>
+pub struct NesIoWith<V, I, A>
 where
     V: Video,
     I: Input,
+    A: Audio,
 {
     pub video: V,
     pub input: I,
+    pub audio: A,
 }
 
-impl<V, I> NesIo for NesIoWith<V, I>
+impl<V, I, A> NesIo for NesIoWith<V, I, A>
 where
     V: Video,
     I: Input,
+    A: Audio,
 {
     type Video = V;
     type Input = I;
+    type Audio = A;
 
     fn video(&self) -> &Self::Video {
         &self.video
@@ -330,6 +487,10 @@ where
     fn input(&self) -> &Self::Input {
         &self.input
     }
+
+    fn audio(&self) -> &Self::Audio {
+        &self.audio
+    }
 }
 
 impl<'a, I> NesIo for &'a I
@@ -338,6 +499,7 @@ where
 {
     type Video = I::Video;
     type Input = I::Input;
+    type Audio = I::Audio;
 
     fn video(&self) -> &Self::Video {
         (*self).video()
@@ -346,6 +508,10 @@ where
     fn input(&self) -> &Self::Input {
         (*self).input()
     }
+
+    fn audio(&self) -> &Self::Audio {
+        (*self).audio()
+    }
 }
 
 #[derive(Clone)]
@@ -0,0 +1,1101 @@
+use crate::nes::cpu::IrqSources;
+use crate::nes::{Nes, NesIo};
+use crate::rom::TvSystem;
+use std::cell::Cell;
+use std::ops::Generator;
+
+mod mixer;
+
+pub use mixer::ChannelControl;
+use mixer::Mixer;
+
+// The rate the NTSC CPU (and therefore the APU) runs at, in Hz
+const CPU_FREQUENCY: u32 = 1_789_773;
+
+#[derive(Clone)]
+pub struct Apu {
+    pub pulse_1: Pulse,
+    pub pulse_2: Pulse,
+    pub triangle: Triangle,
+    pub noise: Noise,
+    pub dmc: Dmc,
+    pub frame_counter: FrameCounter,
+
+    mixer: Mixer,
+}
+
+impl Apu {
+    pub fn new(tv_system: TvSystem) -> Self {
+        Apu {
+            pulse_1: Pulse::new(PulseChannel::Pulse1),
+            pulse_2: Pulse::new(PulseChannel::Pulse2),
+            triangle: Triangle::new(),
+            noise: Noise::new(tv_system),
+            dmc: Dmc::new(tv_system),
+            frame_counter: FrameCounter::new(),
+            mixer: Mixer::new(),
+        }
+    }
+
+    // Pressing reset silences every channel, and restarts the frame counter
+    // as if its last value was written to $4017 again. See:
+    // - https://wiki.nesdev.com/w/index.php/CPU_power_up_state
+    pub fn reset(&self) {
+        self.write_status(0x00);
+        self.frame_counter.reset();
+    }
+
+    // At power-up, all of the APU's registers act as if they were written
+    // with 0. The channels' internal sequencers aren't reset, but their
+    // phase is unpredictable on real hardware anyway
+    pub fn power_cycle(&self) {
+        self.write_status(0x00);
+
+        for pulse in [&self.pulse_1, &self.pulse_2].iter() {
+            pulse.write_control(0x00);
+            pulse.write_sweep(0x00);
+            pulse.write_timer_lo(0x00);
+            pulse.write_timer_hi(0x00);
+        }
+
+        self.triangle.write_linear_counter(0x00);
+        self.triangle.write_timer_lo(0x00);
+        self.triangle.write_timer_hi(0x00);
+
+        self.noise.write_control(0x00);
+        self.noise.write_period(0x00);
+        self.noise.write_length(0x00);
+
+        self.dmc.write_control(0x00);
+        self.dmc.write_direct_load(0x00);
+        self.dmc.write_sample_addr(0x00);
+        self.dmc.write_sample_length(0x00);
+
+        self.frame_counter.write_control(0x00);
+        self.frame_counter.clear_interrupt_flag();
+    }
+
+    pub fn write_status(&self, value: u8) {
+        // ---D NT21: enable DMC, noise, triangle, pulse 2, pulse 1
+        self.pulse_1
+            .length_counter
+            .set_enabled(value & 0b_0000_0001 != 0);
+        self.pulse_2
+            .length_counter
+            .set_enabled(value & 0b_0000_0010 != 0);
+        self.triangle
+            .length_counter
+            .set_enabled(value & 0b_0000_0100 != 0);
+        self.noise
+            .length_counter
+            .set_enabled(value & 0b_0000_1000 != 0);
+        self.dmc.set_enabled(value & 0b_0001_0000 != 0);
+
+        self.dmc.clear_interrupt_flag();
+    }
+
+    pub fn read_status(&self) -> u8 {
+        // IF-D NT21: DMC interrupt, frame interrupt, DMC active, and
+        // length counter status for noise, triangle, pulse 2, and pulse 1
+        let mut status = 0;
+        if self.pulse_1.length_counter.counter() > 0 {
+            status |= 0b_0000_0001;
+        }
+        if self.pulse_2.length_counter.counter() > 0 {
+            status |= 0b_0000_0010;
+        }
+        if self.triangle.length_counter.counter() > 0 {
+            status |= 0b_0000_0100;
+        }
+        if self.noise.length_counter.counter() > 0 {
+            status |= 0b_0000_1000;
+        }
+        if self.dmc.bytes_remaining() > 0 {
+            status |= 0b_0001_0000;
+        }
+        if self.frame_counter.interrupt_flag() {
+            status |= 0b_0100_0000;
+        }
+        if self.dmc.interrupt_flag() {
+            status |= 0b_1000_0000;
+        }
+
+        // Reading the status clears the frame interrupt flag (but not the
+        // DMC interrupt flag)
+        self.frame_counter.clear_interrupt_flag();
+
+        status
+    }
+
+    fn clock_quarter_frame(&self) {
+        self.pulse_1.envelope.clock();
+        self.pulse_2.envelope.clock();
+        self.triangle.clock_linear_counter();
+        self.noise.envelope.clock();
+    }
+
+    fn clock_half_frame(&self) {
+        self.pulse_1.length_counter.clock();
+        self.pulse_1.clock_sweep();
+        self.pulse_2.length_counter.clock();
+        self.pulse_2.clock_sweep();
+        self.triangle.length_counter.clock();
+        self.noise.length_counter.clock();
+    }
+
+    pub fn output(&self) -> f32 {
+        self.mixer.mix(
+            self.pulse_1.output(),
+            self.pulse_2.output(),
+            self.triangle.output(),
+            self.noise.output(),
+            self.dmc.output(),
+        )
+    }
+
+    pub fn is_muted(&self, channel: ApuChannel) -> bool {
+        self.mixer.control(channel).muted
+    }
+
+    pub fn set_muted(&self, channel: ApuChannel, muted: bool) {
+        let control = self.mixer.control(channel);
+        self.mixer
+            .set_control(channel, ChannelControl { muted, ..control });
+    }
+
+    // When any channel is soloed, only soloed channels are audible
+    pub fn is_solo(&self, channel: ApuChannel) -> bool {
+        self.mixer.control(channel).solo
+    }
+
+    pub fn set_solo(&self, channel: ApuChannel, solo: bool) {
+        let control = self.mixer.control(channel);
+        self.mixer
+            .set_control(channel, ChannelControl { solo, ..control });
+    }
+
+    pub fn volume(&self, channel: ApuChannel) -> f32 {
+        self.mixer.control(channel).volume
+    }
+
+    // Scale a channel's output level before it's mixed (1.0 is unchanged)
+    pub fn set_volume(&self, channel: ApuChannel, volume: f32) {
+        let control = self.mixer.control(channel);
+        self.mixer
+            .set_control(channel, ChannelControl { volume, ..control });
+    }
+
+    pub fn reset_channel_controls(&self) {
+        for &channel in ApuChannel::ALL.iter() {
+            self.mixer.set_control(channel, ChannelControl::default());
+        }
+    }
+
+    fn queue_output_sample(nes: &Nes<impl NesIo>) {
+        nes.apu.mixer.clock(nes.apu.output(), nes.io.audio());
+    }
+
+    fn clock_frame_counter(&self, is_apu_cycle: bool) {
+        match self.frame_counter.clock(is_apu_cycle) {
+            FrameCounterClock::None => {}
+            FrameCounterClock::QuarterFrame => {
+                self.clock_quarter_frame();
+            }
+            FrameCounterClock::HalfFrame => {
+                self.clock_quarter_frame();
+                self.clock_half_frame();
+            }
+        }
+    }
+
+    // The frame counter and DMC each hold /IRQ low for as long as their
+    // interrupt flag is set
+    fn update_irq(nes: &Nes<impl NesIo>) {
+        let frame_interrupt = nes.apu.frame_counter.interrupt_flag();
+        let dmc_interrupt = nes.apu.dmc.interrupt_flag();
+
+        nes.cpu.set_irq(IrqSources::APU_FRAME, frame_interrupt);
+        nes.cpu.set_irq(IrqSources::APU_DMC, dmc_interrupt);
+    }
+
+    // The DMC's memory reader fetches the next sample byte through the DMA
+    // unit, which halts the CPU during the fetch
+    fn fill_dmc_sample_buffer(nes: &Nes<impl NesIo>) {
+        if let Some(addr) = nes.apu.dmc.pending_sample_addr() {
+            nes.dma.start_dmc_dma(addr);
+        }
+    }
+
+    pub fn run<'a>(nes: &'a Nes<impl NesIo>) -> impl Generator<Yield = ApuStep, Return = !> + 'a {
+        move || loop {
+            // The pulse channels are clocked once per APU cycle, which is
+            // every other CPU cycle. The triangle, noise, and DMC channels
+            // are clocked every CPU cycle.
+            nes.apu.pulse_1.clock_timer();
+            nes.apu.pulse_2.clock_timer();
+            nes.apu.triangle.clock_timer();
+            nes.apu.noise.clock_timer();
+            nes.apu.dmc.clock_timer();
+            Apu::fill_dmc_sample_buffer(nes);
+            nes.apu.clock_frame_counter(true);
+            Apu::update_irq(nes);
+
+            Apu::queue_output_sample(nes);
+            yield ApuStep::Cycle;
+
+            nes.apu.triangle.clock_timer();
+            nes.apu.noise.clock_timer();
+            nes.apu.dmc.clock_timer();
+            Apu::fill_dmc_sample_buffer(nes);
+            nes.apu.clock_frame_counter(false);
+            Apu::update_irq(nes);
+
+            Apu::queue_output_sample(nes);
+            yield ApuStep::Cycle;
+        }
+    }
+}
+
+pub enum ApuStep {
+    Cycle,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ApuChannel {
+    Pulse1,
+    Pulse2,
+    Triangle,
+    Noise,
+    Dmc,
+}
+
+impl ApuChannel {
+    pub const ALL: [ApuChannel; 5] = [
+        ApuChannel::Pulse1,
+        ApuChannel::Pulse2,
+        ApuChannel::Triangle,
+        ApuChannel::Noise,
+        ApuChannel::Dmc,
+    ];
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FrameCounterMode {
+    FourStep,
+    FiveStep,
+}
+
+enum FrameCounterClock {
+    None,
+    QuarterFrame,
+
+    // NOTE: Half frames also clock everything clocked by quarter frames
+    HalfFrame,
+}
+
+#[derive(Clone)]
+pub struct FrameCounter {
+    mode: Cell<FrameCounterMode>,
+    irq_inhibit: Cell<bool>,
+    interrupt_flag: Cell<bool>,
+
+    // Number of CPU cycles since the sequence started
+    cycle: Cell<u16>,
+
+    // Writes to $4017 take effect after a short delay. This holds the value
+    // that was written until the write gets picked up, then the number of
+    // CPU cycles left until the sequencer gets reset.
+    pending_write: Cell<Option<u8>>,
+    reset_delay: Cell<Option<u8>>,
+}
+
+impl FrameCounter {
+    fn new() -> Self {
+        FrameCounter {
+            mode: Cell::new(FrameCounterMode::FourStep),
+            irq_inhibit: Cell::new(false),
+            interrupt_flag: Cell::new(false),
+            cycle: Cell::new(0),
+            pending_write: Cell::new(None),
+            reset_delay: Cell::new(None),
+        }
+    }
+
+    pub fn write_control(&self, value: u8) {
+        // MI-- ----: mode, IRQ inhibit
+        let irq_inhibit = value & 0b_0100_0000 != 0;
+        self.irq_inhibit.set(irq_inhibit);
+        if irq_inhibit {
+            self.interrupt_flag.set(false);
+        }
+
+        self.pending_write.set(Some(value));
+    }
+
+    fn reset(&self) {
+        let mode = match self.mode.get() {
+            FrameCounterMode::FourStep => 0b_0000_0000,
+            FrameCounterMode::FiveStep => 0b_1000_0000,
+        };
+        let irq_inhibit = match self.irq_inhibit.get() {
+            true => 0b_0100_0000,
+            false => 0b_0000_0000,
+        };
+
+        self.interrupt_flag.set(false);
+        self.write_control(mode | irq_inhibit);
+    }
+
+    pub fn mode(&self) -> FrameCounterMode {
+        self.mode.get()
+    }
+
+    pub fn interrupt_flag(&self) -> bool {
+        self.interrupt_flag.get()
+    }
+
+    pub fn clear_interrupt_flag(&self) {
+        self.interrupt_flag.set(false);
+    }
+
+    fn set_interrupt_flag(&self) {
+        if !self.irq_inhibit.get() {
+            self.interrupt_flag.set(true);
+        }
+    }
+
+    fn clock(&self, is_apu_cycle: bool) -> FrameCounterClock {
+        if let Some(value) = self.pending_write.take() {
+            // The sequencer is reset 3 CPU cycles after a write during an
+            // APU cycle, or 4 CPU cycles after a write between APU cycles
+            let mode = match value & 0b_1000_0000 {
+                0 => FrameCounterMode::FourStep,
+                _ => FrameCounterMode::FiveStep,
+            };
+            self.mode.set(mode);
+            self.reset_delay.set(Some(if is_apu_cycle { 3 } else { 4 }));
+        }
+
+        match self.reset_delay.get() {
+            Some(0) => {
+                self.reset_delay.set(None);
+                self.cycle.set(0);
+
+                // Resetting the sequencer in 5-step mode immediately clocks
+                // a half frame
+                return match self.mode.get() {
+                    FrameCounterMode::FourStep => FrameCounterClock::None,
+                    FrameCounterMode::FiveStep => FrameCounterClock::HalfFrame,
+                };
+            }
+            Some(reset_delay) => {
+                self.reset_delay.set(Some(reset_delay - 1));
+            }
+            None => {}
+        }
+
+        let cycle = self.cycle.get() + 1;
+        self.cycle.set(cycle);
+
+        // Sequencer timing (in CPU cycles), from:
+        // - https://wiki.nesdev.com/w/index.php/APU_Frame_Counter
+        match (self.mode.get(), cycle) {
+            (_, 7457) => FrameCounterClock::QuarterFrame,
+            (_, 14913) => FrameCounterClock::HalfFrame,
+            (_, 22371) => FrameCounterClock::QuarterFrame,
+            (FrameCounterMode::FourStep, 29828) => {
+                self.set_interrupt_flag();
+                FrameCounterClock::None
+            }
+            (FrameCounterMode::FourStep, 29829) => {
+                self.set_interrupt_flag();
+                FrameCounterClock::HalfFrame
+            }
+            (FrameCounterMode::FourStep, 29830) => {
+                self.set_interrupt_flag();
+                self.cycle.set(0);
+                FrameCounterClock::None
+            }
+            (FrameCounterMode::FiveStep, 37281) => FrameCounterClock::HalfFrame,
+            (FrameCounterMode::FiveStep, 37282) => {
+                self.cycle.set(0);
+                FrameCounterClock::None
+            }
+            _ => FrameCounterClock::None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum PulseChannel {
+    Pulse1,
+    Pulse2,
+}
+
+const PULSE_DUTY_SEQUENCES: [[u8; 8]; 4] = [
+    [0, 1, 0, 0, 0, 0, 0, 0],
+    [0, 1, 1, 0, 0, 0, 0, 0],
+    [0, 1, 1, 1, 1, 0, 0, 0],
+    [1, 0, 0, 1, 1, 1, 1, 1],
+];
+
+#[derive(Clone)]
+pub struct Pulse {
+    channel: PulseChannel,
+    pub envelope: Envelope,
+    pub length_counter: LengthCounter,
+    duty: Cell<u8>,
+    sequence_step: Cell<u8>,
+    timer_period: Cell<u16>,
+    timer: Cell<u16>,
+    sweep_enabled: Cell<bool>,
+    sweep_period: Cell<u8>,
+    sweep_negate: Cell<bool>,
+    sweep_shift: Cell<u8>,
+    sweep_divider: Cell<u8>,
+    sweep_reload: Cell<bool>,
+}
+
+impl Pulse {
+    fn new(channel: PulseChannel) -> Self {
+        Pulse {
+            channel,
+            envelope: Envelope::new(),
+            length_counter: LengthCounter::new(),
+            duty: Cell::new(0),
+            sequence_step: Cell::new(0),
+            timer_period: Cell::new(0),
+            timer: Cell::new(0),
+            sweep_enabled: Cell::new(false),
+            sweep_period: Cell::new(0),
+            sweep_negate: Cell::new(false),
+            sweep_shift: Cell::new(0),
+            sweep_divider: Cell::new(0),
+            sweep_reload: Cell::new(false),
+        }
+    }
+
+    pub fn write_control(&self, value: u8) {
+        // DDLC VVVV: duty, length counter halt (and envelope loop),
+        // constant volume, volume / envelope period
+        self.duty.set((value & 0b_1100_0000) >> 6);
+        self.length_counter.set_halted(value & 0b_0010_0000 != 0);
+        self.envelope.write_control(value);
+    }
+
+    pub fn write_sweep(&self, value: u8) {
+        // EPPP NSSS: enabled, divider period, negate, shift count
+        self.sweep_enabled.set(value & 0b_1000_0000 != 0);
+        self.sweep_period.set((value & 0b_0111_0000) >> 4);
+        self.sweep_negate.set(value & 0b_0000_1000 != 0);
+        self.sweep_shift.set(value & 0b_0000_0111);
+        self.sweep_reload.set(true);
+    }
+
+    pub fn write_timer_lo(&self, value: u8) {
+        let timer_period_hi = self.timer_period.get() & 0x0700;
+        self.timer_period.set(timer_period_hi | value as u16);
+    }
+
+    pub fn write_timer_hi(&self, value: u8) {
+        // LLLL LHHH: length counter load, timer high bits
+        let timer_period_lo = self.timer_period.get() & 0x00FF;
+        let timer_period_hi = (value as u16 & 0b_0000_0111) << 8;
+        self.timer_period.set(timer_period_hi | timer_period_lo);
+        self.length_counter.load(value >> 3);
+
+        // Writing the high byte restarts the sequencer and the envelope
+        // (but not the timer's divider)
+        self.sequence_step.set(0);
+        self.envelope.restart();
+    }
+
+    fn clock_timer(&self) {
+        match self.timer.get() {
+            0 => {
+                self.timer.set(self.timer_period.get());
+                self.sequence_step.set((self.sequence_step.get() + 1) % 8);
+            }
+            timer => {
+                self.timer.set(timer - 1);
+            }
+        }
+    }
+
+    fn sweep_target_period(&self) -> u16 {
+        let timer_period = self.timer_period.get();
+        let change = timer_period >> self.sweep_shift.get();
+
+        if self.sweep_negate.get() {
+            match self.channel {
+                // Pulse 1 negates using ones' complement, so it subtracts
+                // an extra 1 compared to pulse 2
+                PulseChannel::Pulse1 => timer_period.saturating_sub(change + 1),
+                PulseChannel::Pulse2 => timer_period.saturating_sub(change),
+            }
+        } else {
+            timer_period + change
+        }
+    }
+
+    fn is_sweep_muting(&self) -> bool {
+        // The channel is muted if the current period is too low, or if the
+        // sweep unit's target period overflows (which happens even if the
+        // sweep unit is disabled)
+        self.timer_period.get() < 8 || self.sweep_target_period() > 0x07FF
+    }
+
+    fn clock_sweep(&self) {
+        let divider = self.sweep_divider.get();
+        let should_sweep = divider == 0
+            && self.sweep_enabled.get()
+            && self.sweep_shift.get() > 0
+            && !self.is_sweep_muting();
+        if should_sweep {
+            self.timer_period.set(self.sweep_target_period());
+        }
+
+        if divider == 0 || self.sweep_reload.get() {
+            self.sweep_divider.set(self.sweep_period.get());
+            self.sweep_reload.set(false);
+        } else {
+            self.sweep_divider.set(divider - 1);
+        }
+    }
+
+    pub fn output(&self) -> u8 {
+        let duty_sequence = PULSE_DUTY_SEQUENCES[self.duty.get() as usize];
+        let sequence_output = duty_sequence[self.sequence_step.get() as usize];
+
+        if sequence_output == 0 || self.length_counter.is_silenced() || self.is_sweep_muting() {
+            0
+        } else {
+            self.envelope.volume()
+        }
+    }
+}
+
+const TRIANGLE_SEQUENCE: [u8; 32] = [
+    15, 14, 13, 12, 11, 10, 9, 8, 7, 6, 5, 4, 3, 2, 1, 0, 0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12,
+    13, 14, 15,
+];
+
+#[derive(Clone)]
+pub struct Triangle {
+    pub length_counter: LengthCounter,
+    control: Cell<bool>,
+    linear_counter_reload_value: Cell<u8>,
+    linear_counter_reload: Cell<bool>,
+    linear_counter: Cell<u8>,
+    sequence_step: Cell<u8>,
+    timer_period: Cell<u16>,
+    timer: Cell<u16>,
+}
+
+impl Triangle {
+    fn new() -> Self {
+        Triangle {
+            length_counter: LengthCounter::new(),
+            control: Cell::new(false),
+            linear_counter_reload_value: Cell::new(0),
+            linear_counter_reload: Cell::new(false),
+            linear_counter: Cell::new(0),
+            sequence_step: Cell::new(0),
+            timer_period: Cell::new(0),
+            timer: Cell::new(0),
+        }
+    }
+
+    pub fn write_linear_counter(&self, value: u8) {
+        // CRRR RRRR: control flag (and length counter halt), linear counter
+        // reload value
+        let control = value & 0b_1000_0000 != 0;
+        self.control.set(control);
+        self.length_counter.set_halted(control);
+        self.linear_counter_reload_value.set(value & 0b_0111_1111);
+    }
+
+    pub fn write_timer_lo(&self, value: u8) {
+        let timer_period_hi = self.timer_period.get() & 0x0700;
+        self.timer_period.set(timer_period_hi | value as u16);
+    }
+
+    pub fn write_timer_hi(&self, value: u8) {
+        // LLLL LHHH: length counter load, timer high bits
+        let timer_period_lo = self.timer_period.get() & 0x00FF;
+        let timer_period_hi = (value as u16 & 0b_0000_0111) << 8;
+        self.timer_period.set(timer_period_hi | timer_period_lo);
+        self.length_counter.load(value >> 3);
+
+        self.linear_counter_reload.set(true);
+    }
+
+    fn clock_timer(&self) {
+        match self.timer.get() {
+            0 => {
+                let timer_period = self.timer_period.get();
+                self.timer.set(timer_period);
+
+                // The sequencer only advances while both counters are
+                // non-zero. Periods below 2 produce ultrasonic frequencies
+                // that would only be heard as popping, so we silence
+                // the channel by freezing the sequencer instead.
+                let is_counting =
+                    self.linear_counter.get() > 0 && !self.length_counter.is_silenced();
+                let is_ultrasonic = timer_period < 2;
+                if is_counting && !is_ultrasonic {
+                    self.sequence_step.set((self.sequence_step.get() + 1) % 32);
+                }
+            }
+            timer => {
+                self.timer.set(timer - 1);
+            }
+        }
+    }
+
+    fn clock_linear_counter(&self) {
+        if self.linear_counter_reload.get() {
+            self.linear_counter
+                .set(self.linear_counter_reload_value.get());
+        } else {
+            let linear_counter = self.linear_counter.get();
+            self.linear_counter.set(linear_counter.saturating_sub(1));
+        }
+
+        if !self.control.get() {
+            self.linear_counter_reload.set(false);
+        }
+    }
+
+    pub fn output(&self) -> u8 {
+        // NOTE: Unlike the other channels, the triangle channel isn't muted
+        // when its counters reach 0; it just holds its current output level
+        TRIANGLE_SEQUENCE[self.sequence_step.get() as usize]
+    }
+}
+
+// Noise channel timer periods, in CPU cycles
+const NOISE_PERIOD_TABLE_NTSC: [u16; 16] = [
+    4, 8, 16, 32, 64, 96, 128, 160, 202, 254, 380, 508, 762, 1016, 2034, 4068,
+];
+const NOISE_PERIOD_TABLE_PAL: [u16; 16] = [
+    4, 8, 14, 30, 60, 88, 118, 148, 188, 236, 354, 472, 708, 944, 1890, 3778,
+];
+
+#[derive(Clone)]
+pub struct Noise {
+    pub envelope: Envelope,
+    pub length_counter: LengthCounter,
+    period_table: &'static [u16; 16],
+    short_mode: Cell<bool>,
+    timer_period: Cell<u16>,
+    timer: Cell<u16>,
+
+    // 15-bit linear-feedback shift register
+    shift_register: Cell<u16>,
+}
+
+impl Noise {
+    fn new(tv_system: TvSystem) -> Self {
+        let period_table = match tv_system {
+            TvSystem::Ntsc | TvSystem::Dual => &NOISE_PERIOD_TABLE_NTSC,
+            TvSystem::Pal => &NOISE_PERIOD_TABLE_PAL,
+        };
+
+        Noise {
+            envelope: Envelope::new(),
+            length_counter: LengthCounter::new(),
+            period_table,
+            short_mode: Cell::new(false),
+            timer_period: Cell::new(period_table[0]),
+            timer: Cell::new(0),
+            shift_register: Cell::new(1),
+        }
+    }
+
+    pub fn write_control(&self, value: u8) {
+        // --LC VVVV: length counter halt (and envelope loop), constant
+        // volume, volume / envelope period
+        self.length_counter.set_halted(value & 0b_0010_0000 != 0);
+        self.envelope.write_control(value);
+    }
+
+    pub fn write_period(&self, value: u8) {
+        // M--- PPPP: mode, period index
+        self.short_mode.set(value & 0b_1000_0000 != 0);
+
+        let period_index = value & 0b_0000_1111;
+        self.timer_period
+            .set(self.period_table[period_index as usize]);
+    }
+
+    pub fn write_length(&self, value: u8) {
+        // LLLL L---: length counter load
+        self.length_counter.load(value >> 3);
+        self.envelope.restart();
+    }
+
+    fn clock_timer(&self) {
+        match self.timer.get() {
+            0 => {
+                // The period table is in CPU cycles, and the timer is
+                // clocked every CPU cycle, so the timer counts down from
+                // one less than the period
+                self.timer.set(self.timer_period.get() - 1);
+                self.clock_shift_register();
+            }
+            timer => {
+                self.timer.set(timer - 1);
+            }
+        }
+    }
+
+    fn clock_shift_register(&self) {
+        // The feedback bit is bit 0 XOR'd with either bit 6 (in short mode)
+        // or bit 1 (in normal mode)
+        let shift_register = self.shift_register.get();
+        let feedback_tap = if self.short_mode.get() { 6 } else { 1 };
+        let feedback = (shift_register ^ (shift_register >> feedback_tap)) & 1;
+
+        self.shift_register
+            .set((shift_register >> 1) | (feedback << 14));
+    }
+
+    pub fn output(&self) -> u8 {
+        let shift_register_bit_0 = self.shift_register.get() & 1 != 0;
+
+        if shift_register_bit_0 || self.length_counter.is_silenced() {
+            0
+        } else {
+            self.envelope.volume()
+        }
+    }
+}
+
+// DMC channel timer periods, in CPU cycles
+const DMC_RATE_TABLE_NTSC: [u16; 16] = [
+    428, 380, 340, 320, 286, 254, 226, 214, 190, 160, 142, 128, 106, 84, 72, 54,
+];
+const DMC_RATE_TABLE_PAL: [u16; 16] = [
+    398, 354, 316, 298, 276, 236, 210, 198, 176, 148, 132, 118, 98, 78, 66, 50,
+];
+
+#[derive(Clone)]
+pub struct Dmc {
+    rate_table: &'static [u16; 16],
+    irq_enabled: Cell<bool>,
+    looping: Cell<bool>,
+    timer_period: Cell<u16>,
+    timer: Cell<u16>,
+    interrupt_flag: Cell<bool>,
+
+    // Memory reader
+    sample_addr: Cell<u16>,
+    sample_length: Cell<u16>,
+    current_addr: Cell<u16>,
+    bytes_remaining: Cell<u16>,
+    sample_buffer: Cell<Option<u8>>,
+
+    // Output unit
+    shift_register: Cell<u8>,
+    bits_remaining: Cell<u8>,
+    silence: Cell<bool>,
+    output_level: Cell<u8>,
+}
+
+impl Dmc {
+    fn new(tv_system: TvSystem) -> Self {
+        let rate_table = match tv_system {
+            TvSystem::Ntsc | TvSystem::Dual => &DMC_RATE_TABLE_NTSC,
+            TvSystem::Pal => &DMC_RATE_TABLE_PAL,
+        };
+
+        Dmc {
+            rate_table,
+            irq_enabled: Cell::new(false),
+            looping: Cell::new(false),
+            timer_period: Cell::new(rate_table[0]),
+            timer: Cell::new(0),
+            interrupt_flag: Cell::new(false),
+            sample_addr: Cell::new(0xC000),
+            sample_length: Cell::new(1),
+            current_addr: Cell::new(0xC000),
+            bytes_remaining: Cell::new(0),
+            sample_buffer: Cell::new(None),
+            shift_register: Cell::new(0),
+            bits_remaining: Cell::new(8),
+            silence: Cell::new(true),
+            output_level: Cell::new(0),
+        }
+    }
+
+    pub fn write_control(&self, value: u8) {
+        // IL-- RRRR: IRQ enabled, loop, rate index
+        let irq_enabled = value & 0b_1000_0000 != 0;
+        self.irq_enabled.set(irq_enabled);
+        self.looping.set(value & 0b_0100_0000 != 0);
+
+        let rate_index = value & 0b_0000_1111;
+        self.timer_period.set(self.rate_table[rate_index as usize]);
+
+        if !irq_enabled {
+            self.interrupt_flag.set(false);
+        }
+    }
+
+    pub fn write_direct_load(&self, value: u8) {
+        // -DDD DDDD: output level
+        self.output_level.set(value & 0b_0111_1111);
+    }
+
+    pub fn write_sample_addr(&self, value: u8) {
+        // Sample address is %11AAAAAA.AA000000
+        self.sample_addr.set(0xC000 | ((value as u16) << 6));
+    }
+
+    pub fn write_sample_length(&self, value: u8) {
+        // Sample length is %LLLL.LLLL0001
+        self.sample_length.set(((value as u16) << 4) | 1);
+    }
+
+    pub fn set_enabled(&self, enabled: bool) {
+        if !enabled {
+            self.bytes_remaining.set(0);
+        } else if self.bytes_remaining.get() == 0 {
+            self.restart();
+        }
+    }
+
+    pub fn bytes_remaining(&self) -> u16 {
+        self.bytes_remaining.get()
+    }
+
+    pub fn interrupt_flag(&self) -> bool {
+        self.interrupt_flag.get()
+    }
+
+    pub fn clear_interrupt_flag(&self) {
+        self.interrupt_flag.set(false);
+    }
+
+    fn restart(&self) {
+        self.current_addr.set(self.sample_addr.get());
+        self.bytes_remaining.set(self.sample_length.get());
+    }
+
+    // Returns the address of the next byte the memory reader needs to
+    // fetch, if the sample buffer is empty and there are bytes remaining
+    fn pending_sample_addr(&self) -> Option<u16> {
+        match (self.sample_buffer.get(), self.bytes_remaining.get()) {
+            (None, bytes_remaining) if bytes_remaining > 0 => Some(self.current_addr.get()),
+            _ => None,
+        }
+    }
+
+    // Called by the DMA unit once it's read the byte at the pending
+    // sample address
+    pub fn load_sample_buffer(&self, value: u8) {
+        // The fetch is dropped if the channel was disabled in the meantime
+        if self.bytes_remaining.get() == 0 {
+            return;
+        }
+
+        self.sample_buffer.set(Some(value));
+
+        // The address wraps around to $8000 (not $0000) after $FFFF
+        let next_addr = match self.current_addr.get() {
+            0xFFFF => 0x8000,
+            current_addr => current_addr + 1,
+        };
+        self.current_addr.set(next_addr);
+
+        let bytes_remaining = self.bytes_remaining.get() - 1;
+        self.bytes_remaining.set(bytes_remaining);
+
+        if bytes_remaining == 0 {
+            if self.looping.get() {
+                self.restart();
+            } else if self.irq_enabled.get() {
+                self.interrupt_flag.set(true);
+            }
+        }
+    }
+
+    fn clock_timer(&self) {
+        match self.timer.get() {
+            0 => {
+                self.timer.set(self.timer_period.get() - 1);
+                self.clock_output_unit();
+            }
+            timer => {
+                self.timer.set(timer - 1);
+            }
+        }
+    }
+
+    fn clock_output_unit(&self) {
+        if !self.silence.get() {
+            // Bit 0 of the shift register determines if the output level
+            // should go up or down by 2 (staying in the range 0-127)
+            let output_level = self.output_level.get();
+            let new_output_level = match self.shift_register.get() & 1 {
+                0 if output_level >= 2 => output_level - 2,
+                1 if output_level <= 125 => output_level + 2,
+                _ => output_level,
+            };
+            self.output_level.set(new_output_level);
+        }
+
+        self.shift_register.set(self.shift_register.get() >> 1);
+
+        let bits_remaining = self.bits_remaining.get().saturating_sub(1);
+        if bits_remaining == 0 {
+            // Start a new output cycle, emptying the sample buffer into
+            // the shift register (or silencing the output if the sample
+            // buffer was already empty)
+            self.bits_remaining.set(8);
+            match self.sample_buffer.take() {
+                Some(sample) => {
+                    self.silence.set(false);
+                    self.shift_register.set(sample);
+                }
+                None => {
+                    self.silence.set(true);
+                }
+            }
+        } else {
+            self.bits_remaining.set(bits_remaining);
+        }
+    }
+
+    pub fn output(&self) -> u8 {
+        self.output_level.get()
+    }
+}
+
+#[derive(Clone)]
+pub struct Envelope {
+    start: Cell<bool>,
+    looping: Cell<bool>,
+    constant_volume: Cell<bool>,
+    period: Cell<u8>,
+    divider: Cell<u8>,
+    decay_level: Cell<u8>,
+}
+
+impl Envelope {
+    fn new() -> Self {
+        Envelope {
+            start: Cell::new(false),
+            looping: Cell::new(false),
+            constant_volume: Cell::new(false),
+            period: Cell::new(0),
+            divider: Cell::new(0),
+            decay_level: Cell::new(0),
+        }
+    }
+
+    fn write_control(&self, value: u8) {
+        // --LC VVVV: loop, constant volume, volume / envelope period
+        self.looping.set(value & 0b_0010_0000 != 0);
+        self.constant_volume.set(value & 0b_0001_0000 != 0);
+        self.period.set(value & 0b_0000_1111);
+    }
+
+    fn restart(&self) {
+        self.start.set(true);
+    }
+
+    pub fn clock(&self) {
+        if self.start.get() {
+            self.start.set(false);
+            self.decay_level.set(15);
+            self.divider.set(self.period.get());
+            return;
+        }
+
+        match self.divider.get() {
+            0 => {
+                self.divider.set(self.period.get());
+
+                match self.decay_level.get() {
+                    0 => {
+                        if self.looping.get() {
+                            self.decay_level.set(15);
+                        }
+                    }
+                    decay_level => {
+                        self.decay_level.set(decay_level - 1);
+                    }
+                }
+            }
+            divider => {
+                self.divider.set(divider - 1);
+            }
+        }
+    }
+
+    pub fn volume(&self) -> u8 {
+        if self.constant_volume.get() {
+            self.period.get()
+        } else {
+            self.decay_level.get()
+        }
+    }
+}
+
+const LENGTH_COUNTER_TABLE: [u8; 32] = [
+    10, 254, 20, 2, 40, 4, 80, 6, 160, 8, 60, 10, 14, 12, 26, 14, 12, 16, 24, 18, 48, 20, 96, 22,
+    192, 24, 72, 26, 16, 28, 32, 30,
+];
+
+#[derive(Clone)]
+pub struct LengthCounter {
+    enabled: Cell<bool>,
+    halted: Cell<bool>,
+    counter: Cell<u8>,
+}
+
+impl LengthCounter {
+    fn new() -> Self {
+        LengthCounter {
+            enabled: Cell::new(false),
+            halted: Cell::new(false),
+            counter: Cell::new(0),
+        }
+    }
+
+    pub fn set_enabled(&self, enabled: bool) {
+        self.enabled.set(enabled);
+        if !enabled {
+            self.counter.set(0);
+        }
+    }
+
+    fn set_halted(&self, halted: bool) {
+        self.halted.set(halted);
+    }
+
+    fn load(&self, index: u8) {
+        // The length counter can only be reloaded while the channel
+        // is enabled
+        if self.enabled.get() {
+            self.counter.set(LENGTH_COUNTER_TABLE[index as usize]);
+        }
+    }
+
+    pub fn clock(&self) {
+        let counter = self.counter.get();
+        if !self.halted.get() && counter > 0 {
+            self.counter.set(counter - 1);
+        }
+    }
+
+    pub fn counter(&self) -> u8 {
+        self.counter.get()
+    }
+
+    fn is_silenced(&self) -> bool {
+        self.counter.get() == 0
+    }
+}
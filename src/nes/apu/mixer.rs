@@ -0,0 +1,350 @@
+use super::{ApuChannel, CPU_FREQUENCY};
+use crate::audio::Audio;
+use std::cell::{Cell, RefCell};
+use std::f32::consts::PI;
+
+// Number of output samples each band-limited step is spread across
+const STEP_WIDTH: usize = 16;
+
+// Number of sub-sample positions the band-limited step kernel is
+// precomputed for
+const STEP_PHASES: usize = 64;
+
+// Size of the ring buffer holding output samples that are still having
+// steps added to them. Must be at least twice `STEP_WIDTH`.
+const BUFFER_SIZE: usize = 64;
+
+// Cutoff of the band-limited step, as a fraction of the output sample rate
+// (just below the Nyquist frequency of 0.5)
+const STEP_CUTOFF: f32 = 0.45;
+
+// Mixes the APU's channels and turns the CPU-rate output signal into
+// samples at the host's sample rate.
+//
+// The NES's output is a sum of square-ish waves that change value at
+// (at most) once per CPU cycle. Rather than filtering and decimating a
+// ~1.79MHz signal, each change in the output is added to the output
+// buffer as a band-limited step (a windowed-sinc integrated over time),
+// which doesn't alias no matter where between two output samples the
+// step lands. This is the same technique used by blargg's `blip_buf`:
+// - http://www.slack.net/~ant/bl-synth/
+#[derive(Clone)]
+pub struct Mixer {
+    tables: MixerTables,
+    controls: [Cell<ChannelControl>; 5],
+    resampler: RefCell<Resampler>,
+    filters: RefCell<FilterChain>,
+}
+
+impl Mixer {
+    pub fn new() -> Self {
+        Mixer {
+            tables: MixerTables::new(),
+            controls: Default::default(),
+            resampler: RefCell::new(Resampler::new()),
+            filters: RefCell::new(FilterChain::new(44_100)),
+        }
+    }
+
+    pub fn control(&self, channel: ApuChannel) -> ChannelControl {
+        self.controls[channel as usize].get()
+    }
+
+    pub fn set_control(&self, channel: ApuChannel, control: ChannelControl) {
+        self.controls[channel as usize].set(control);
+    }
+
+    // Combine the output levels of each channel into a single amplitude,
+    // between 0.0 and 1.0 (when no channel's volume is boosted)
+    pub fn mix(&self, pulse_1: u8, pulse_2: u8, triangle: u8, noise: u8, dmc: u8) -> f32 {
+        let is_any_solo = self.controls.iter().any(|control| control.get().solo);
+        let level = |channel: ApuChannel, level: u8| {
+            let control = self.control(channel);
+            let is_audible = if is_any_solo {
+                control.solo
+            } else {
+                !control.muted
+            };
+
+            if is_audible {
+                level as f32 * control.volume
+            } else {
+                0.0
+            }
+        };
+
+        let pulse_1 = level(ApuChannel::Pulse1, pulse_1);
+        let pulse_2 = level(ApuChannel::Pulse2, pulse_2);
+        let triangle = level(ApuChannel::Triangle, triangle);
+        let noise = level(ApuChannel::Noise, noise);
+        let dmc = level(ApuChannel::Dmc, dmc);
+
+        let pulse_index = pulse_1 + pulse_2;
+        let tnd_index = 3.0 * triangle + 2.0 * noise + dmc;
+
+        lookup(&self.tables.pulse, pulse_index) + lookup(&self.tables.tnd, tnd_index)
+    }
+
+    // Add the mixer's amplitude for the current CPU cycle, then pass any
+    // finished samples on to `audio`
+    pub fn clock(&self, amplitude: f32, audio: &impl Audio) {
+        let sample_rate = audio.sample_rate();
+        let mut resampler = self.resampler.borrow_mut();
+        let mut filters = self.filters.borrow_mut();
+
+        if filters.sample_rate != sample_rate {
+            *filters = FilterChain::new(sample_rate);
+        }
+
+        resampler.clock(amplitude, sample_rate, |sample| {
+            audio.queue_sample(filters.filter(sample));
+        });
+    }
+}
+
+// Per-channel settings that only affect the mixed output, and not the
+// emulated state of the channel
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ChannelControl {
+    pub muted: bool,
+    pub solo: bool,
+    pub volume: f32,
+}
+
+impl Default for ChannelControl {
+    fn default() -> Self {
+        ChannelControl {
+            muted: false,
+            solo: false,
+            volume: 1.0,
+        }
+    }
+}
+
+// Look up a value from one of the mixer's tables, interpolating between
+// entries for fractional indices (which happen when a channel's volume is
+// scaled). Indices past the end of the table are extrapolated from the
+// last two entries.
+fn lookup(table: &[f32], index: f32) -> f32 {
+    let index = index.max(0.0);
+    let lo = (index as usize).min(table.len() - 2);
+    let fraction = index - lo as f32;
+
+    table[lo] + (table[lo + 1] - table[lo]) * fraction
+}
+
+// Lookup tables for the APU's nonlinear mixer, from:
+// - https://wiki.nesdev.com/w/index.php/APU_Mixer
+struct MixerTables {
+    pulse: [f32; 31],
+    tnd: [f32; 203],
+}
+
+impl MixerTables {
+    fn new() -> Self {
+        let mut pulse = [0.0; 31];
+        for (n, value) in pulse.iter_mut().enumerate().skip(1) {
+            *value = 95.52 / (8128.0 / n as f32 + 100.0);
+        }
+
+        let mut tnd = [0.0; 203];
+        for (n, value) in tnd.iter_mut().enumerate().skip(1) {
+            *value = 163.67 / (24329.0 / n as f32 + 100.0);
+        }
+
+        MixerTables { pulse, tnd }
+    }
+}
+
+impl Clone for MixerTables {
+    fn clone(&self) -> Self {
+        MixerTables {
+            pulse: self.pulse,
+            tnd: self.tnd,
+        }
+    }
+}
+
+#[derive(Clone)]
+struct Resampler {
+    step_kernel: Vec<[f32; STEP_WIDTH]>,
+    buffer: Vec<f32>,
+
+    // The position of the current CPU cycle, measured in output samples
+    time: f64,
+
+    // The index of the next output sample to finish
+    next_sample: u64,
+
+    integrator: f32,
+    last_amplitude: f32,
+}
+
+impl Resampler {
+    fn new() -> Self {
+        Resampler {
+            step_kernel: step_kernel(),
+            buffer: vec![0.0; BUFFER_SIZE],
+            time: 0.0,
+            next_sample: 0,
+            integrator: 0.0,
+            last_amplitude: 0.0,
+        }
+    }
+
+    fn clock(&mut self, amplitude: f32, sample_rate: u32, mut on_sample: impl FnMut(f32)) {
+        let delta = amplitude - self.last_amplitude;
+        if delta != 0.0 {
+            self.add_step(delta);
+            self.last_amplitude = amplitude;
+        }
+
+        self.time += sample_rate as f64 / CPU_FREQUENCY as f64;
+
+        // A sample is finished once no future step can reach it (future
+        // steps start at or after the current time)
+        while self.next_sample + (STEP_WIDTH as u64) <= self.time as u64 {
+            let index = self.next_sample as usize % BUFFER_SIZE;
+            self.integrator += self.buffer[index];
+            self.buffer[index] = 0.0;
+            self.next_sample += 1;
+
+            on_sample(self.integrator);
+        }
+    }
+
+    fn add_step(&mut self, delta: f32) {
+        let start = self.time as u64;
+        let fraction = self.time - start as f64;
+        let phase = (fraction * STEP_PHASES as f64) as usize;
+
+        let kernel = &self.step_kernel[phase];
+        for (offset, weight) in kernel.iter().enumerate() {
+            let index = (start as usize + offset) % BUFFER_SIZE;
+            self.buffer[index] += delta * weight;
+        }
+    }
+}
+
+// Precompute a windowed-sinc impulse for each sub-sample phase. The impulses
+// get summed by the resampler's integrator, turning them into
+// band-limited steps.
+fn step_kernel() -> Vec<[f32; STEP_WIDTH]> {
+    let half_width = STEP_WIDTH as f32 / 2.0;
+
+    (0..STEP_PHASES)
+        .map(|phase| {
+            let fraction = phase as f32 / STEP_PHASES as f32;
+
+            let mut impulse = [0.0; STEP_WIDTH];
+            for (tap, value) in impulse.iter_mut().enumerate() {
+                let x = tap as f32 - fraction - half_width;
+                let sinc = if x == 0.0 {
+                    1.0
+                } else {
+                    let theta = 2.0 * PI * STEP_CUTOFF * x;
+                    theta.sin() / theta
+                };
+                let window = if x.abs() < half_width {
+                    0.5 * (1.0 + (PI * x / half_width).cos())
+                } else {
+                    0.0
+                };
+
+                *value = sinc * window;
+            }
+
+            // Normalize each impulse so that a full step always adds up to
+            // exactly `delta`, regardless of phase
+            let sum: f32 = impulse.iter().sum();
+            for value in impulse.iter_mut() {
+                *value /= sum;
+            }
+
+            impulse
+        })
+        .collect()
+}
+
+// The filters applied to the NES's output before it leaves the console,
+// from:
+// - https://wiki.nesdev.com/w/index.php/APU_Mixer
+#[derive(Clone)]
+struct FilterChain {
+    sample_rate: u32,
+    high_pass_90hz: HighPassFilter,
+    high_pass_440hz: HighPassFilter,
+    low_pass_14khz: LowPassFilter,
+}
+
+impl FilterChain {
+    fn new(sample_rate: u32) -> Self {
+        FilterChain {
+            sample_rate,
+            high_pass_90hz: HighPassFilter::new(90.0, sample_rate),
+            high_pass_440hz: HighPassFilter::new(440.0, sample_rate),
+            low_pass_14khz: LowPassFilter::new(14_000.0, sample_rate),
+        }
+    }
+
+    fn filter(&mut self, sample: f32) -> f32 {
+        let sample = self.high_pass_90hz.filter(sample);
+        let sample = self.high_pass_440hz.filter(sample);
+        self.low_pass_14khz.filter(sample)
+    }
+}
+
+// First-order high-pass filter
+#[derive(Clone)]
+struct HighPassFilter {
+    alpha: f32,
+    prev_input: f32,
+    prev_output: f32,
+}
+
+impl HighPassFilter {
+    fn new(cutoff: f32, sample_rate: u32) -> Self {
+        let rc = 1.0 / (2.0 * PI * cutoff);
+        let dt = 1.0 / sample_rate as f32;
+
+        HighPassFilter {
+            alpha: rc / (rc + dt),
+            prev_input: 0.0,
+            prev_output: 0.0,
+        }
+    }
+
+    fn filter(&mut self, input: f32) -> f32 {
+        let output = self.alpha * (self.prev_output + input - self.prev_input);
+        self.prev_input = input;
+        self.prev_output = output;
+
+        output
+    }
+}
+
+// First-order low-pass filter
+#[derive(Clone)]
+struct LowPassFilter {
+    alpha: f32,
+    prev_output: f32,
+}
+
+impl LowPassFilter {
+    fn new(cutoff: f32, sample_rate: u32) -> Self {
+        let rc = 1.0 / (2.0 * PI * cutoff);
+        let dt = 1.0 / sample_rate as f32;
+
+        LowPassFilter {
+            alpha: dt / (rc + dt),
+            prev_output: 0.0,
+        }
+    }
+
+    fn filter(&mut self, input: f32) -> f32 {
+        let output = self.prev_output + self.alpha * (input - self.prev_output);
+        self.prev_output = output;
+
+        output
+    }
+}
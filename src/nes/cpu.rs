@@ -1,10 +1,16 @@
-use crate::nes::{Nes, NesIo};
 use bitflags::bitflags;
 use std::cell::Cell;
 use std::fmt;
-use std::ops::Generator;
+use std::ops::{Generator, GeneratorState};
+use std::pin::Pin;
 use std::u8;
 
+mod bus;
+mod disasm;
+
+pub use bus::Bus;
+pub use disasm::{disassemble, DisasmOp, Disassembler};
+
 #[derive(Debug, Clone)]
 pub struct Cpu {
     pub pc: Cell<u16>,
@@ -14,6 +20,59 @@ pub struct Cpu {
     pub s: Cell<u8>,
     pub p: Cell<CpuFlags>,
     pub nmi: Cell<bool>,
+
+    // Number of CPU cycles since power-up, including cycles where the CPU
+    // was halted for DMA. This keeps counting across resets
+    pub cycles: Cell<u64>,
+
+    // Set when the reset button is pressed. The CPU runs its reset
+    // sequence once the current instruction finishes
+    pub reset: Cell<bool>,
+
+    // The sources currently asserting the /IRQ line. The line stays
+    // asserted until every source releases it
+    pub irq: Cell<IrqSources>,
+
+    // The results of the two most recent interrupt polls. The CPU polls
+    // for interrupts during every cycle, but only acts on the poll from
+    // the end of an instruction's second-to-last cycle
+    latest_poll: Cell<Option<Interrupt>>,
+    penultimate_poll: Cell<Option<Interrupt>>,
+
+    variant: CpuVariant,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CpuVariant {
+    // The NES's 2A03 is a 6502 with decimal mode removed: the `D` flag can
+    // still be set, but ADC and SBC ignore it
+    Ricoh2A03,
+
+    // A stock NMOS 6502, which uses decimal arithmetic when `D` is set
+    Nmos6502,
+
+    // The CMOS 65C02 adds a few instructions and the (zp) addressing mode,
+    // fixes the JMP ($xxFF) bug, sets `N` and `Z` properly in decimal
+    // mode, and clears `D` on interrupts. The NMOS's unofficial opcodes
+    // are all NOPs. See:
+    // - http://6502.org/tutorials/65c02opcodes.html
+    Cmos65C02,
+}
+
+impl CpuVariant {
+    fn opcode_set(self) -> OpcodeSet {
+        match self {
+            CpuVariant::Ricoh2A03 | CpuVariant::Nmos6502 => OpcodeSet::Nmos,
+            CpuVariant::Cmos65C02 => OpcodeSet::Cmos,
+        }
+    }
+}
+
+// The 2A03 decodes opcodes the same way as an NMOS 6502
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum OpcodeSet {
+    Nmos,
+    Cmos,
 }
 
 impl Cpu {
@@ -26,13 +85,73 @@ impl Cpu {
             s: Cell::new(0xFD),
             p: Cell::new(CpuFlags::from_bits_truncate(0x34)),
             nmi: Cell::new(false),
+            cycles: Cell::new(0),
+            reset: Cell::new(false),
+            irq: Cell::new(IrqSources::empty()),
+            latest_poll: Cell::new(None),
+            penultimate_poll: Cell::new(None),
+            variant: CpuVariant::Ricoh2A03,
         }
     }
 
+    pub fn with_variant(variant: CpuVariant) -> Self {
+        Cpu {
+            variant,
+            ..Cpu::new()
+        }
+    }
+
+    pub fn variant(&self) -> CpuVariant {
+        self.variant
+    }
+
+    // Set the CPU's registers to their power-up state, then start the
+    // reset sequence. See:
+    // - https://wiki.nesdev.com/w/index.php/CPU_power_up_state
+    pub fn power_cycle(&self) {
+        self.a.set(0);
+        self.x.set(0);
+        self.y.set(0);
+        self.s.set(0x00);
+        self.p.set(CpuFlags::from_bits_truncate(0x34));
+        self.nmi.set(false);
+        self.reset.set(true);
+    }
+
+    pub fn set_irq(&self, source: IrqSources, asserted: bool) {
+        let mut irq = self.irq.get();
+        irq.set(source, asserted);
+        self.irq.set(irq);
+    }
+
+    pub fn is_irq_asserted(&self) -> bool {
+        !self.irq.get().is_empty()
+    }
+
+    // Sample the /NMI and /IRQ lines. `Cpu::run` calls this once before
+    // every cycle where the CPU runs (but not while it's halted for DMA,
+    // so that the polls from the interrupted instruction are kept)
+    fn poll_interrupts(&self) {
+        let interrupt = if self.nmi.get() {
+            Some(Interrupt::Nmi)
+        } else if self.is_irq_asserted() && !self.contains_flags(CpuFlags::I) {
+            Some(Interrupt::Irq)
+        } else {
+            None
+        };
+
+        self.penultimate_poll.set(self.latest_poll.get());
+        self.latest_poll.set(interrupt);
+    }
+
     fn contains_flags(&self, flags: CpuFlags) -> bool {
         self.p.get().contains(flags)
     }
 
+    fn is_decimal(&self) -> bool {
+        self.variant != CpuVariant::Ricoh2A03 && self.contains_flags(CpuFlags::D)
+    }
+
     fn set_flags(&self, flags: CpuFlags, value: bool) {
         // TODO: Prevent the break (`B`) and unused (`U`) flags
         // from being changed!
@@ -49,14 +168,14 @@ impl Cpu {
         pc
     }
 
-    fn pc_fetch(nes: &Nes<impl NesIo>) -> u8 {
-        let pc = nes.cpu.pc.get();
-        nes.read_u8(pc)
+    fn pc_fetch(&self, bus: &impl Bus) -> u8 {
+        let pc = self.pc.get();
+        bus.read_u8(pc)
     }
 
-    fn pc_fetch_inc(nes: &Nes<impl NesIo>) -> u8 {
-        let pc = nes.cpu.pc_inc();
-        nes.read_u8(pc)
+    fn pc_fetch_inc(&self, bus: &impl Bus) -> u8 {
+        let pc = self.pc_inc();
+        bus.read_u8(pc)
     }
 
     fn stack_addr(&self) -> u16 {
@@ -71,685 +190,933 @@ impl Cpu {
         self.s.update(|s| s.wrapping_sub(1));
     }
 
-    pub fn run<'a>(nes: &'a Nes<impl NesIo>) -> impl Generator<Yield = CpuStep, Return = !> + 'a {
-        move || loop {
-            // TODO: Does this properly account for CPU cycles for handling NMI?
-            let nmi = nes.cpu.nmi.get();
-            if nmi {
-                nes.cpu.nmi.set(false);
+    fn push_u8(&self, bus: &impl Bus, value: u8) {
+        bus.write_u8(self.stack_addr(), value);
+        self.dec_s();
+    }
+
+    // Run the CPU against `bus`. Each `CpuStep::Cycle` is one CPU cycle,
+    // which is counted in `cycles` and reported to the bus before it's
+    // yielded
+    pub fn run<'a>(
+        cpu: &'a Cpu,
+        bus: &'a impl Bus,
+    ) -> impl Generator<Yield = CpuStep, Return = !> + 'a {
+        let mut run_instructions = Cpu::run_instructions(cpu, bus);
 
-                nes.push_u16(nes.cpu.pc.get());
-                nes.push_u8(nes.cpu.p.get().bits);
+        move || {
+            let mut is_cycle_start = true;
+
+            loop {
+                // Polling before each cycle samples the interrupt lines as
+                // they were at the end of the previous cycle
+                if is_cycle_start {
+                    cpu.poll_interrupts();
+                }
+
+                match Pin::new(&mut run_instructions).resume(()) {
+                    GeneratorState::Yielded(CpuStep::Cycle) => {
+                        is_cycle_start = true;
+                        cpu.cycles.update(|cycles| cycles + 1);
+                        bus.end_cycle();
+                        yield CpuStep::Cycle;
+                    }
+                    GeneratorState::Yielded(cpu_step) => {
+                        is_cycle_start = false;
+                        yield cpu_step;
+                    }
+                }
+            }
+        }
+    }
 
-                let nmi_vector = nes.read_u16(0xFFFA);
-                nes.cpu.pc.set(nmi_vector);
+    fn run_instructions<'a>(
+        cpu: &'a Cpu,
+        bus: &'a impl Bus,
+    ) -> impl Generator<Yield = CpuStep, Return = !> + 'a {
+        move || loop {
+            if cpu.reset.get() {
+                cpu.reset.set(false);
+                cpu.nmi.set(false);
+                yield_all! { interrupt_sequence(cpu, bus, Interrupt::Reset) };
             }
 
-            let pc = nes.cpu.pc.get();
+            yield CpuStep::Start;
 
-            let opcode = nes.read_u8(pc);
+            let cycle = cpu.cycles.get();
+            let pc = cpu.pc.get();
 
-            let instruction_with_mode = opcode_to_instruction_with_mode(opcode);
-            let op = match instruction_with_mode {
-                (Instruction::Adc, OpMode::Abs) => {
-                    yield_all! { abs_read(nes, AdcOperation) }
-                }
-                (Instruction::Adc, OpMode::AbsX) => {
-                    yield_all! { abs_x_read(nes, AdcOperation) }
-                }
-                (Instruction::Adc, OpMode::AbsY) => {
-                    yield_all! { abs_y_read(nes, AdcOperation) }
-                }
-                (Instruction::Adc, OpMode::Imm) => {
-                    yield_all! { imm_read(nes, AdcOperation) }
+            let opcode = bus.read_u8(pc);
+
+            let instruction_with_mode = decode_opcode(cpu.variant, opcode);
+            // Dispatch on the raw opcode (rather than the decoded instruction
+            // and mode) so that the compiler checks that every opcode is
+            // handled. Opcodes that the 65C02 decodes differently are matched
+            // separately for each opcode set
+            let op = match (cpu.variant.opcode_set(), opcode) {
+                (_, 0x6D) => {
+                    yield_all! { abs_read(cpu, bus, AdcOperation) }
                 }
-                (Instruction::Adc, OpMode::IndX) => {
-                    yield_all! { ind_x_read(nes, AdcOperation) }
+                (_, 0x7D) => {
+                    yield_all! { abs_x_read(cpu, bus, AdcOperation) }
                 }
-                (Instruction::Adc, OpMode::IndY) => {
-                    yield_all! { ind_y_read(nes, AdcOperation) }
+                (_, 0x79) => {
+                    yield_all! { abs_y_read(cpu, bus, AdcOperation) }
                 }
-                (Instruction::Adc, OpMode::Zero) => {
-                    yield_all! { zero_read(nes, AdcOperation) }
+                (_, 0x69) => {
+                    yield_all! { imm_read(cpu, bus, AdcOperation) }
                 }
-                (Instruction::Adc, OpMode::ZeroX) => {
-                    yield_all! { zero_x_read(nes, AdcOperation) }
+                (_, 0x61) => {
+                    yield_all! { ind_x_read(cpu, bus, AdcOperation) }
                 }
-                (Instruction::And, OpMode::Abs) => {
-                    yield_all! { abs_read(nes, AndOperation) }
+                (_, 0x71) => {
+                    yield_all! { ind_y_read(cpu, bus, AdcOperation) }
                 }
-                (Instruction::And, OpMode::AbsX) => {
-                    yield_all! { abs_x_read(nes, AndOperation) }
+                (OpcodeSet::Cmos, 0x72) => {
+                    yield_all! { zero_ind_read(cpu, bus, AdcOperation) }
                 }
-                (Instruction::And, OpMode::AbsY) => {
-                    yield_all! { abs_y_read(nes, AndOperation) }
+                (_, 0x65) => {
+                    yield_all! { zero_read(cpu, bus, AdcOperation) }
                 }
-                (Instruction::And, OpMode::Imm) => {
-                    yield_all! { imm_read(nes, AndOperation) }
+                (_, 0x75) => {
+                    yield_all! { zero_x_read(cpu, bus, AdcOperation) }
                 }
-                (Instruction::And, OpMode::IndX) => {
-                    yield_all! { ind_x_read(nes, AndOperation) }
+                (_, 0x2D) => {
+                    yield_all! { abs_read(cpu, bus, AndOperation) }
                 }
-                (Instruction::And, OpMode::IndY) => {
-                    yield_all! { ind_y_read(nes, AndOperation) }
+                (_, 0x3D) => {
+                    yield_all! { abs_x_read(cpu, bus, AndOperation) }
                 }
-                (Instruction::And, OpMode::Zero) => {
-                    yield_all! { zero_read(nes, AndOperation) }
+                (_, 0x39) => {
+                    yield_all! { abs_y_read(cpu, bus, AndOperation) }
                 }
-                (Instruction::And, OpMode::ZeroX) => {
-                    yield_all! { zero_x_read(nes, AndOperation) }
+                (_, 0x29) => {
+                    yield_all! { imm_read(cpu, bus, AndOperation) }
                 }
-                (Instruction::Asl, OpMode::Accum) => {
-                    yield_all! { accum_modify(nes, AslOperation) }
+                (_, 0x21) => {
+                    yield_all! { ind_x_read(cpu, bus, AndOperation) }
                 }
-                (Instruction::Asl, OpMode::Abs) => {
-                    yield_all! { abs_modify(nes, AslOperation) }
+                (_, 0x31) => {
+                    yield_all! { ind_y_read(cpu, bus, AndOperation) }
                 }
-                (Instruction::Asl, OpMode::AbsX) => {
-                    yield_all! { abs_x_modify(nes, AslOperation) }
+                (OpcodeSet::Cmos, 0x32) => {
+                    yield_all! { zero_ind_read(cpu, bus, AndOperation) }
                 }
-                (Instruction::Asl, OpMode::Zero) => {
-                    yield_all! { zero_modify(nes, AslOperation) }
+                (_, 0x25) => {
+                    yield_all! { zero_read(cpu, bus, AndOperation) }
                 }
-                (Instruction::Asl, OpMode::ZeroX) => {
-                    yield_all! { zero_x_modify(nes, AslOperation) }
+                (_, 0x35) => {
+                    yield_all! { zero_x_read(cpu, bus, AndOperation) }
                 }
-                (Instruction::Bcc, OpMode::Branch) => {
-                    yield_all! { branch(&nes, BccOperation) }
+                (_, 0x0A) => {
+                    yield_all! { accum_modify(cpu, bus, AslOperation) }
                 }
-                (Instruction::Bcs, OpMode::Branch) => {
-                    yield_all! { branch(&nes, BcsOperation) }
+                (_, 0x0E) => {
+                    yield_all! { abs_modify(cpu, bus, AslOperation) }
                 }
-                (Instruction::Beq, OpMode::Branch) => {
-                    yield_all! { branch(&nes, BeqOperation) }
+                (_, 0x1E) => {
+                    yield_all! { abs_x_modify(cpu, bus, AslOperation) }
                 }
-                (Instruction::Bit, OpMode::Zero) => {
-                    yield_all! { zero_read(&nes, BitOperation) }
+                (_, 0x06) => {
+                    yield_all! { zero_modify(cpu, bus, AslOperation) }
                 }
-                (Instruction::Bit, OpMode::Abs) => {
-                    yield_all! { abs_read(&nes, BitOperation) }
+                (_, 0x16) => {
+                    yield_all! { zero_x_modify(cpu, bus, AslOperation) }
                 }
-                (Instruction::Bmi, OpMode::Branch) => {
-                    yield_all! { branch(&nes, BmiOperation) }
+                (_, 0x90) => {
+                    yield_all! { branch(cpu, bus, BccOperation) }
                 }
-                (Instruction::Bne, OpMode::Branch) => {
-                    yield_all! { branch(&nes, BneOperation) }
+                (_, 0xB0) => {
+                    yield_all! { branch(cpu, bus, BcsOperation) }
                 }
-                (Instruction::Bpl, OpMode::Branch) => {
-                    yield_all! { branch(&nes, BplOperation) }
+                (_, 0xF0) => {
+                    yield_all! { branch(cpu, bus, BeqOperation) }
                 }
-                (Instruction::Brk, OpMode::Implied) => {
-                    yield_all! { brk(&nes) }
+                (_, 0x24) => {
+                    yield_all! { zero_read(cpu, bus, BitOperation) }
                 }
-                (Instruction::Bvc, OpMode::Branch) => {
-                    yield_all! { branch(&nes, BvcOperation) }
+                (_, 0x2C) => {
+                    yield_all! { abs_read(cpu, bus, BitOperation) }
                 }
-                (Instruction::Bvs, OpMode::Branch) => {
-                    yield_all! { branch(&nes, BvsOperation) }
+                (OpcodeSet::Cmos, 0x3C) => {
+                    yield_all! { abs_x_read(cpu, bus, BitOperation) }
                 }
-                (Instruction::Clc, OpMode::Implied) => {
-                    yield_all! { implied(nes, ClcOperation) }
+                (OpcodeSet::Cmos, 0x89) => {
+                    yield_all! { imm_read(cpu, bus, BitImmOperation) }
                 }
-                (Instruction::Cld, OpMode::Implied) => {
-                    yield_all! { implied(nes, CldOperation) }
+                (OpcodeSet::Cmos, 0x34) => {
+                    yield_all! { zero_x_read(cpu, bus, BitOperation) }
                 }
-                (Instruction::Cli, OpMode::Implied) => {
-                    yield_all! { implied(nes, CliOperation) }
+                (_, 0x30) => {
+                    yield_all! { branch(cpu, bus, BmiOperation) }
                 }
-                (Instruction::Clv, OpMode::Implied) => {
-                    yield_all! { implied(nes, ClvOperation) }
+                (_, 0xD0) => {
+                    yield_all! { branch(cpu, bus, BneOperation) }
                 }
-                (Instruction::Cmp, OpMode::Abs) => {
-                    yield_all! { abs_read(nes, CmpOperation) }
+                (_, 0x10) => {
+                    yield_all! { branch(cpu, bus, BplOperation) }
                 }
-                (Instruction::Cmp, OpMode::AbsX) => {
-                    yield_all! { abs_x_read(nes, CmpOperation) }
+                (OpcodeSet::Cmos, 0x80) => {
+                    yield_all! { branch(cpu, bus, BraOperation) }
                 }
-                (Instruction::Cmp, OpMode::AbsY) => {
-                    yield_all! { abs_y_read(nes, CmpOperation) }
+                (_, 0x00) => {
+                    yield_all! { brk(cpu, bus) }
                 }
-                (Instruction::Cmp, OpMode::Imm) => {
-                    yield_all! { imm_read(nes, CmpOperation) }
+                (_, 0x50) => {
+                    yield_all! { branch(cpu, bus, BvcOperation) }
                 }
-                (Instruction::Cmp, OpMode::IndX) => {
-                    yield_all! { ind_x_read(nes, CmpOperation) }
+                (_, 0x70) => {
+                    yield_all! { branch(cpu, bus, BvsOperation) }
                 }
-                (Instruction::Cmp, OpMode::IndY) => {
-                    yield_all! { ind_y_read(nes, CmpOperation) }
+                (_, 0x18) => {
+                    yield_all! { implied(cpu, bus, ClcOperation) }
                 }
-                (Instruction::Cmp, OpMode::Zero) => {
-                    yield_all! { zero_read(nes, CmpOperation) }
+                (_, 0xD8) => {
+                    yield_all! { implied(cpu, bus, CldOperation) }
                 }
-                (Instruction::Cmp, OpMode::ZeroX) => {
-                    yield_all! { zero_x_read(nes, CmpOperation) }
+                (_, 0x58) => {
+                    yield_all! { implied(cpu, bus, CliOperation) }
                 }
-                (Instruction::Cpx, OpMode::Abs) => {
-                    yield_all! { abs_read(nes, CpxOperation) }
+                (_, 0xB8) => {
+                    yield_all! { implied(cpu, bus, ClvOperation) }
                 }
-                (Instruction::Cpx, OpMode::Imm) => {
-                    yield_all! { imm_read(nes, CpxOperation) }
+                (_, 0xCD) => {
+                    yield_all! { abs_read(cpu, bus, CmpOperation) }
                 }
-                (Instruction::Cpx, OpMode::Zero) => {
-                    yield_all! { zero_read(nes, CpxOperation) }
+                (_, 0xDD) => {
+                    yield_all! { abs_x_read(cpu, bus, CmpOperation) }
                 }
-                (Instruction::Cpy, OpMode::Abs) => {
-                    yield_all! { abs_read(nes, CpyOperation) }
+                (_, 0xD9) => {
+                    yield_all! { abs_y_read(cpu, bus, CmpOperation) }
                 }
-                (Instruction::Cpy, OpMode::Imm) => {
-                    yield_all! { imm_read(nes, CpyOperation) }
+                (_, 0xC9) => {
+                    yield_all! { imm_read(cpu, bus, CmpOperation) }
                 }
-                (Instruction::Cpy, OpMode::Zero) => {
-                    yield_all! { zero_read(nes, CpyOperation) }
+                (_, 0xC1) => {
+                    yield_all! { ind_x_read(cpu, bus, CmpOperation) }
                 }
-                (Instruction::Dec, OpMode::Abs) => {
-                    yield_all! { abs_modify(nes, DecOperation) }
+                (_, 0xD1) => {
+                    yield_all! { ind_y_read(cpu, bus, CmpOperation) }
                 }
-                (Instruction::Dec, OpMode::AbsX) => {
-                    yield_all! { abs_x_modify(nes, DecOperation) }
+                (OpcodeSet::Cmos, 0xD2) => {
+                    yield_all! { zero_ind_read(cpu, bus, CmpOperation) }
                 }
-                (Instruction::Dec, OpMode::Zero) => {
-                    yield_all! { zero_modify(nes, DecOperation) }
+                (_, 0xC5) => {
+                    yield_all! { zero_read(cpu, bus, CmpOperation) }
                 }
-                (Instruction::Dec, OpMode::ZeroX) => {
-                    yield_all! { zero_x_modify(nes, DecOperation) }
+                (_, 0xD5) => {
+                    yield_all! { zero_x_read(cpu, bus, CmpOperation) }
                 }
-                (Instruction::Dex, OpMode::Implied) => {
-                    yield_all! { implied(nes, DexOperation) }
+                (_, 0xEC) => {
+                    yield_all! { abs_read(cpu, bus, CpxOperation) }
                 }
-                (Instruction::Dey, OpMode::Implied) => {
-                    yield_all! { implied(nes, DeyOperation) }
+                (_, 0xE0) => {
+                    yield_all! { imm_read(cpu, bus, CpxOperation) }
                 }
-                (Instruction::Eor, OpMode::Abs) => {
-                    yield_all! { abs_read(nes, EorOperation) }
+                (_, 0xE4) => {
+                    yield_all! { zero_read(cpu, bus, CpxOperation) }
                 }
-                (Instruction::Eor, OpMode::AbsX) => {
-                    yield_all! { abs_x_read(nes, EorOperation) }
+                (_, 0xCC) => {
+                    yield_all! { abs_read(cpu, bus, CpyOperation) }
                 }
-                (Instruction::Eor, OpMode::AbsY) => {
-                    yield_all! { abs_y_read(nes, EorOperation) }
+                (_, 0xC0) => {
+                    yield_all! { imm_read(cpu, bus, CpyOperation) }
                 }
-                (Instruction::Eor, OpMode::Imm) => {
-                    yield_all! { imm_read(nes, EorOperation) }
+                (_, 0xC4) => {
+                    yield_all! { zero_read(cpu, bus, CpyOperation) }
                 }
-                (Instruction::Eor, OpMode::IndX) => {
-                    yield_all! { ind_x_read(nes, EorOperation) }
+                (OpcodeSet::Cmos, 0x3A) => {
+                    yield_all! { accum_modify(cpu, bus, DecOperation) }
                 }
-                (Instruction::Eor, OpMode::IndY) => {
-                    yield_all! { ind_y_read(nes, EorOperation) }
+                (_, 0xCE) => {
+                    yield_all! { abs_modify(cpu, bus, DecOperation) }
                 }
-                (Instruction::Eor, OpMode::Zero) => {
-                    yield_all! { zero_read(nes, EorOperation) }
+                (_, 0xDE) => {
+                    yield_all! { abs_x_modify(cpu, bus, DecOperation) }
                 }
-                (Instruction::Eor, OpMode::ZeroX) => {
-                    yield_all! { zero_x_read(nes, EorOperation) }
+                (_, 0xC6) => {
+                    yield_all! { zero_modify(cpu, bus, DecOperation) }
                 }
-                (Instruction::Inc, OpMode::Abs) => {
-                    yield_all! { abs_modify(nes, IncOperation) }
+                (_, 0xD6) => {
+                    yield_all! { zero_x_modify(cpu, bus, DecOperation) }
                 }
-                (Instruction::Inc, OpMode::AbsX) => {
-                    yield_all! { abs_x_modify(nes, IncOperation) }
+                (_, 0xCA) => {
+                    yield_all! { implied(cpu, bus, DexOperation) }
                 }
-                (Instruction::Inc, OpMode::Zero) => {
-                    yield_all! { zero_modify(nes, IncOperation) }
+                (_, 0x88) => {
+                    yield_all! { implied(cpu, bus, DeyOperation) }
                 }
-                (Instruction::Inc, OpMode::ZeroX) => {
-                    yield_all! { zero_x_modify(nes, IncOperation) }
+                (_, 0x4D) => {
+                    yield_all! { abs_read(cpu, bus, EorOperation) }
                 }
-                (Instruction::Inx, OpMode::Implied) => {
-                    yield_all! { implied(nes, InxOperation) }
+                (_, 0x5D) => {
+                    yield_all! { abs_x_read(cpu, bus, EorOperation) }
                 }
-                (Instruction::Iny, OpMode::Implied) => {
-                    yield_all! { implied(nes, InyOperation) }
+                (_, 0x59) => {
+                    yield_all! { abs_y_read(cpu, bus, EorOperation) }
                 }
-                (Instruction::Jmp, OpMode::Abs) => {
-                    yield_all! { abs_jmp(nes) }
+                (_, 0x49) => {
+                    yield_all! { imm_read(cpu, bus, EorOperation) }
                 }
-                (Instruction::Jmp, OpMode::Ind) => {
-                    yield_all! { ind_jmp(nes) }
+                (_, 0x41) => {
+                    yield_all! { ind_x_read(cpu, bus, EorOperation) }
                 }
-                (Instruction::Jsr, OpMode::Abs) => {
-                    yield_all! { jsr(nes) }
+                (_, 0x51) => {
+                    yield_all! { ind_y_read(cpu, bus, EorOperation) }
                 }
-                (Instruction::Lda, OpMode::Abs) => {
-                    yield_all! { abs_read(nes, LdaOperation) }
+                (OpcodeSet::Cmos, 0x52) => {
+                    yield_all! { zero_ind_read(cpu, bus, EorOperation) }
                 }
-                (Instruction::Lda, OpMode::AbsX) => {
-                    yield_all! { abs_x_read(nes, LdaOperation) }
+                (_, 0x45) => {
+                    yield_all! { zero_read(cpu, bus, EorOperation) }
                 }
-                (Instruction::Lda, OpMode::AbsY) => {
-                    yield_all! { abs_y_read(nes, LdaOperation) }
+                (_, 0x55) => {
+                    yield_all! { zero_x_read(cpu, bus, EorOperation) }
                 }
-                (Instruction::Lda, OpMode::Imm) => {
-                    yield_all! { imm_read(nes, LdaOperation) }
+                (OpcodeSet::Cmos, 0x1A) => {
+                    yield_all! { accum_modify(cpu, bus, IncOperation) }
                 }
-                (Instruction::Lda, OpMode::IndX) => {
-                    yield_all! { ind_x_read(nes, LdaOperation) }
+                (_, 0xEE) => {
+                    yield_all! { abs_modify(cpu, bus, IncOperation) }
                 }
-                (Instruction::Lda, OpMode::IndY) => {
-                    yield_all! { ind_y_read(nes, LdaOperation) }
+                (_, 0xFE) => {
+                    yield_all! { abs_x_modify(cpu, bus, IncOperation) }
                 }
-                (Instruction::Lda, OpMode::Zero) => {
-                    yield_all! { zero_read(nes, LdaOperation) }
+                (_, 0xE6) => {
+                    yield_all! { zero_modify(cpu, bus, IncOperation) }
                 }
-                (Instruction::Lda, OpMode::ZeroX) => {
-                    yield_all! { zero_x_read(nes, LdaOperation) }
+                (_, 0xF6) => {
+                    yield_all! { zero_x_modify(cpu, bus, IncOperation) }
                 }
-                (Instruction::Ldx, OpMode::Abs) => {
-                    yield_all! { abs_read(nes, LdxOperation) }
+                (_, 0xE8) => {
+                    yield_all! { implied(cpu, bus, InxOperation) }
                 }
-                (Instruction::Ldx, OpMode::Imm) => {
-                    yield_all! { imm_read(nes, LdxOperation) }
+                (_, 0xC8) => {
+                    yield_all! { implied(cpu, bus, InyOperation) }
                 }
-                (Instruction::Ldx, OpMode::Zero) => {
-                    yield_all! { zero_read(nes, LdxOperation) }
+                (_, 0x4C) => {
+                    yield_all! { abs_jmp(cpu, bus) }
                 }
-                (Instruction::Ldx, OpMode::ZeroY) => {
-                    yield_all! { zero_y_read(nes, LdxOperation) }
+                (_, 0x6C) => {
+                    yield_all! { ind_jmp(cpu, bus) }
                 }
-                (Instruction::Ldy, OpMode::Abs) => {
-                    yield_all! { abs_read(nes, LdyOperation) }
+                (OpcodeSet::Cmos, 0x7C) => {
+                    yield_all! { abs_x_ind_jmp(cpu, bus) }
                 }
-                (Instruction::Ldy, OpMode::AbsX) => {
-                    yield_all! { abs_x_read(nes, LdyOperation) }
+                (_, 0x20) => {
+                    yield_all! { jsr(cpu, bus) }
                 }
-                (Instruction::Ldx, OpMode::AbsY) => {
-                    yield_all! { abs_y_read(nes, LdxOperation) }
+                (_, 0xAD) => {
+                    yield_all! { abs_read(cpu, bus, LdaOperation) }
                 }
-                (Instruction::Ldy, OpMode::Imm) => {
-                    yield_all! { imm_read(nes, LdyOperation) }
+                (_, 0xBD) => {
+                    yield_all! { abs_x_read(cpu, bus, LdaOperation) }
                 }
-                (Instruction::Ldy, OpMode::Zero) => {
-                    yield_all! { zero_read(nes, LdyOperation) }
+                (_, 0xB9) => {
+                    yield_all! { abs_y_read(cpu, bus, LdaOperation) }
                 }
-                (Instruction::Ldy, OpMode::ZeroX) => {
-                    yield_all! { zero_x_read(nes, LdyOperation) }
+                (_, 0xA9) => {
+                    yield_all! { imm_read(cpu, bus, LdaOperation) }
                 }
-                (Instruction::Lsr, OpMode::Accum) => {
-                    yield_all! { accum_modify(nes, LsrOperation) }
+                (_, 0xA1) => {
+                    yield_all! { ind_x_read(cpu, bus, LdaOperation) }
                 }
-                (Instruction::Lsr, OpMode::Abs) => {
-                    yield_all! { abs_modify(nes, LsrOperation) }
+                (_, 0xB1) => {
+                    yield_all! { ind_y_read(cpu, bus, LdaOperation) }
                 }
-                (Instruction::Lsr, OpMode::AbsX) => {
-                    yield_all! { abs_x_modify(nes, LsrOperation) }
+                (OpcodeSet::Cmos, 0xB2) => {
+                    yield_all! { zero_ind_read(cpu, bus, LdaOperation) }
                 }
-                (Instruction::Lsr, OpMode::Zero) => {
-                    yield_all! { zero_modify(nes, LsrOperation) }
+                (_, 0xA5) => {
+                    yield_all! { zero_read(cpu, bus, LdaOperation) }
                 }
-                (Instruction::Lsr, OpMode::ZeroX) => {
-                    yield_all! { zero_x_modify(nes, LsrOperation) }
+                (_, 0xB5) => {
+                    yield_all! { zero_x_read(cpu, bus, LdaOperation) }
                 }
-                (Instruction::Nop, OpMode::Implied) => {
-                    yield_all! { implied(nes, NopOperation) }
+                (_, 0xAE) => {
+                    yield_all! { abs_read(cpu, bus, LdxOperation) }
                 }
-                (Instruction::Ora, OpMode::Abs) => {
-                    yield_all! { abs_read(nes, OraOperation) }
+                (_, 0xA2) => {
+                    yield_all! { imm_read(cpu, bus, LdxOperation) }
                 }
-                (Instruction::Ora, OpMode::AbsX) => {
-                    yield_all! { abs_x_read(nes, OraOperation) }
+                (_, 0xA6) => {
+                    yield_all! { zero_read(cpu, bus, LdxOperation) }
                 }
-                (Instruction::Ora, OpMode::AbsY) => {
-                    yield_all! { abs_y_read(nes, OraOperation) }
+                (_, 0xB6) => {
+                    yield_all! { zero_y_read(cpu, bus, LdxOperation) }
                 }
-                (Instruction::Ora, OpMode::Imm) => {
-                    yield_all! { imm_read(nes, OraOperation) }
+                (_, 0xAC) => {
+                    yield_all! { abs_read(cpu, bus, LdyOperation) }
                 }
-                (Instruction::Ora, OpMode::IndX) => {
-                    yield_all! { ind_x_read(nes, OraOperation) }
+                (_, 0xBC) => {
+                    yield_all! { abs_x_read(cpu, bus, LdyOperation) }
                 }
-                (Instruction::Ora, OpMode::IndY) => {
-                    yield_all! { ind_y_read(nes, OraOperation) }
+                (_, 0xBE) => {
+                    yield_all! { abs_y_read(cpu, bus, LdxOperation) }
                 }
-                (Instruction::Ora, OpMode::Zero) => {
-                    yield_all! { zero_read(nes, OraOperation) }
+                (_, 0xA0) => {
+                    yield_all! { imm_read(cpu, bus, LdyOperation) }
                 }
-                (Instruction::Ora, OpMode::ZeroX) => {
-                    yield_all! { zero_x_read(nes, OraOperation) }
+                (_, 0xA4) => {
+                    yield_all! { zero_read(cpu, bus, LdyOperation) }
                 }
-                (Instruction::Pha, OpMode::Implied) => {
-                    yield_all! { stack_push(nes, PhaOperation) }
+                (_, 0xB4) => {
+                    yield_all! { zero_x_read(cpu, bus, LdyOperation) }
                 }
-                (Instruction::Php, OpMode::Implied) => {
-                    yield_all! { stack_push(nes, PhpOperation) }
+                (_, 0x4A) => {
+                    yield_all! { accum_modify(cpu, bus, LsrOperation) }
                 }
-                (Instruction::Pla, OpMode::Implied) => {
-                    yield_all! { stack_pull(nes, PlaOperation) }
+                (_, 0x4E) => {
+                    yield_all! { abs_modify(cpu, bus, LsrOperation) }
                 }
-                (Instruction::Plp, OpMode::Implied) => {
-                    yield_all! { stack_pull(nes, PlpOperation) }
+                (_, 0x5E) => {
+                    yield_all! { abs_x_modify(cpu, bus, LsrOperation) }
                 }
-                (Instruction::Rol, OpMode::Accum) => {
-                    yield_all! { accum_modify(nes, RolOperation) }
+                (_, 0x46) => {
+                    yield_all! { zero_modify(cpu, bus, LsrOperation) }
                 }
-                (Instruction::Rol, OpMode::Abs) => {
-                    yield_all! { abs_modify(nes, RolOperation) }
+                (_, 0x56) => {
+                    yield_all! { zero_x_modify(cpu, bus, LsrOperation) }
                 }
-                (Instruction::Rol, OpMode::AbsX) => {
-                    yield_all! { abs_x_modify(nes, RolOperation) }
+                (_, 0xEA) => {
+                    yield_all! { implied(cpu, bus, NopOperation) }
                 }
-                (Instruction::Rol, OpMode::Zero) => {
-                    yield_all! { zero_modify(nes, RolOperation) }
+                (_, 0x0D) => {
+                    yield_all! { abs_read(cpu, bus, OraOperation) }
                 }
-                (Instruction::Rol, OpMode::ZeroX) => {
-                    yield_all! { zero_x_modify(nes, RolOperation) }
+                (_, 0x1D) => {
+                    yield_all! { abs_x_read(cpu, bus, OraOperation) }
                 }
-                (Instruction::Ror, OpMode::Accum) => {
-                    yield_all! { accum_modify(nes, RorOperation) }
+                (_, 0x19) => {
+                    yield_all! { abs_y_read(cpu, bus, OraOperation) }
                 }
-                (Instruction::Ror, OpMode::Abs) => {
-                    yield_all! { abs_modify(nes, RorOperation) }
+                (_, 0x09) => {
+                    yield_all! { imm_read(cpu, bus, OraOperation) }
                 }
-                (Instruction::Ror, OpMode::AbsX) => {
-                    yield_all! { abs_x_modify(nes, RorOperation) }
+                (_, 0x01) => {
+                    yield_all! { ind_x_read(cpu, bus, OraOperation) }
                 }
-                (Instruction::Ror, OpMode::Zero) => {
-                    yield_all! { zero_modify(nes, RorOperation) }
+                (_, 0x11) => {
+                    yield_all! { ind_y_read(cpu, bus, OraOperation) }
                 }
-                (Instruction::Ror, OpMode::ZeroX) => {
-                    yield_all! { zero_x_modify(nes, RorOperation) }
+                (OpcodeSet::Cmos, 0x12) => {
+                    yield_all! { zero_ind_read(cpu, bus, OraOperation) }
                 }
-                (Instruction::Rti, OpMode::Implied) => {
-                    yield_all! { rti(nes) }
+                (_, 0x05) => {
+                    yield_all! { zero_read(cpu, bus, OraOperation) }
                 }
-                (Instruction::Rts, OpMode::Implied) => {
-                    yield_all! { rts(nes) }
+                (_, 0x15) => {
+                    yield_all! { zero_x_read(cpu, bus, OraOperation) }
                 }
-                (Instruction::Sbc, OpMode::Abs) => {
-                    yield_all! { abs_read(nes, SbcOperation) }
+                (_, 0x48) => {
+                    yield_all! { stack_push(cpu, bus, PhaOperation) }
                 }
-                (Instruction::Sbc, OpMode::AbsX) => {
-                    yield_all! { abs_x_read(nes, SbcOperation) }
+                (_, 0x08) => {
+                    yield_all! { stack_push(cpu, bus, PhpOperation) }
                 }
-                (Instruction::Sbc, OpMode::AbsY) => {
-                    yield_all! { abs_y_read(nes, SbcOperation) }
+                (OpcodeSet::Cmos, 0xDA) => {
+                    yield_all! { stack_push(cpu, bus, PhxOperation) }
                 }
-                (Instruction::Sbc, OpMode::Imm) => {
-                    yield_all! { imm_read(nes, SbcOperation) }
+                (OpcodeSet::Cmos, 0x5A) => {
+                    yield_all! { stack_push(cpu, bus, PhyOperation) }
                 }
-                (Instruction::Sbc, OpMode::IndX) => {
-                    yield_all! { ind_x_read(nes, SbcOperation) }
+                (_, 0x68) => {
+                    yield_all! { stack_pull(cpu, bus, PlaOperation) }
                 }
-                (Instruction::Sbc, OpMode::IndY) => {
-                    yield_all! { ind_y_read(nes, SbcOperation) }
+                (_, 0x28) => {
+                    yield_all! { stack_pull(cpu, bus, PlpOperation) }
                 }
-                (Instruction::Sbc, OpMode::Zero) => {
-                    yield_all! { zero_read(nes, SbcOperation) }
+                (OpcodeSet::Cmos, 0xFA) => {
+                    yield_all! { stack_pull(cpu, bus, PlxOperation) }
                 }
-                (Instruction::Sbc, OpMode::ZeroX) => {
-                    yield_all! { zero_x_read(nes, SbcOperation) }
+                (OpcodeSet::Cmos, 0x7A) => {
+                    yield_all! { stack_pull(cpu, bus, PlyOperation) }
                 }
-                (Instruction::Sec, OpMode::Implied) => {
-                    yield_all! { implied(nes, SecOperation) }
+                (_, 0x2A) => {
+                    yield_all! { accum_modify(cpu, bus, RolOperation) }
                 }
-                (Instruction::Sed, OpMode::Implied) => {
-                    yield_all! { implied(nes, SedOperation) }
+                (_, 0x2E) => {
+                    yield_all! { abs_modify(cpu, bus, RolOperation) }
                 }
-                (Instruction::Sei, OpMode::Implied) => {
-                    yield_all! { implied(nes, SeiOperation) }
+                (_, 0x3E) => {
+                    yield_all! { abs_x_modify(cpu, bus, RolOperation) }
                 }
-                (Instruction::Sta, OpMode::Abs) => {
-                    yield_all! { abs_write(nes, StaOperation) }
+                (_, 0x26) => {
+                    yield_all! { zero_modify(cpu, bus, RolOperation) }
                 }
-                (Instruction::Sta, OpMode::AbsX) => {
-                    yield_all! { abs_x_write(nes, StaOperation) }
+                (_, 0x36) => {
+                    yield_all! { zero_x_modify(cpu, bus, RolOperation) }
                 }
-                (Instruction::Sta, OpMode::AbsY) => {
-                    yield_all! { abs_y_write(nes, StaOperation) }
+                (_, 0x6A) => {
+                    yield_all! { accum_modify(cpu, bus, RorOperation) }
                 }
-                (Instruction::Sta, OpMode::IndX) => {
-                    yield_all! { ind_x_write(nes, StaOperation) }
+                (_, 0x6E) => {
+                    yield_all! { abs_modify(cpu, bus, RorOperation) }
                 }
-                (Instruction::Sta, OpMode::IndY) => {
-                    yield_all! { ind_y_write(nes, StaOperation) }
+                (_, 0x7E) => {
+                    yield_all! { abs_x_modify(cpu, bus, RorOperation) }
                 }
-                (Instruction::Sta, OpMode::Zero) => {
-                    yield_all! { zero_write(nes, StaOperation) }
+                (_, 0x66) => {
+                    yield_all! { zero_modify(cpu, bus, RorOperation) }
                 }
-                (Instruction::Sta, OpMode::ZeroX) => {
-                    yield_all! { zero_x_write(nes, StaOperation) }
+                (_, 0x76) => {
+                    yield_all! { zero_x_modify(cpu, bus, RorOperation) }
                 }
-                (Instruction::Stx, OpMode::Abs) => {
-                    yield_all! { abs_write(nes, StxOperation) }
+                (_, 0x40) => {
+                    yield_all! { rti(cpu, bus) }
                 }
-                (Instruction::Stx, OpMode::Zero) => {
-                    yield_all! { zero_write(nes, StxOperation) }
+                (_, 0x60) => {
+                    yield_all! { rts(cpu, bus) }
                 }
-                (Instruction::Stx, OpMode::ZeroY) => {
-                    yield_all! { zero_y_write(nes, StxOperation) }
+                (_, 0xED) => {
+                    yield_all! { abs_read(cpu, bus, SbcOperation) }
                 }
-                (Instruction::Sty, OpMode::Abs) => {
-                    yield_all! { abs_write(nes, StyOperation) }
+                (_, 0xFD) => {
+                    yield_all! { abs_x_read(cpu, bus, SbcOperation) }
                 }
-                (Instruction::Sty, OpMode::Zero) => {
-                    yield_all! { zero_write(nes, StyOperation) }
+                (_, 0xF9) => {
+                    yield_all! { abs_y_read(cpu, bus, SbcOperation) }
                 }
-                (Instruction::Sty, OpMode::ZeroX) => {
-                    yield_all! { zero_x_write(nes, StyOperation) }
+                (_, 0xE9) => {
+                    yield_all! { imm_read(cpu, bus, SbcOperation) }
                 }
-                (Instruction::Tax, OpMode::Implied) => {
-                    yield_all! { implied(nes, TaxOperation) }
+                (_, 0xE1) => {
+                    yield_all! { ind_x_read(cpu, bus, SbcOperation) }
                 }
-                (Instruction::Tay, OpMode::Implied) => {
-                    yield_all! { implied(nes, TayOperation) }
+                (_, 0xF1) => {
+                    yield_all! { ind_y_read(cpu, bus, SbcOperation) }
                 }
-                (Instruction::Tsx, OpMode::Implied) => {
-                    yield_all! { implied(nes, TsxOperation) }
+                (OpcodeSet::Cmos, 0xF2) => {
+                    yield_all! { zero_ind_read(cpu, bus, SbcOperation) }
                 }
-                (Instruction::Txa, OpMode::Implied) => {
-                    yield_all! { implied(nes, TxaOperation) }
+                (_, 0xE5) => {
+                    yield_all! { zero_read(cpu, bus, SbcOperation) }
                 }
-                (Instruction::Txs, OpMode::Implied) => {
-                    yield_all! { implied(nes, TxsOperation) }
+                (_, 0xF5) => {
+                    yield_all! { zero_x_read(cpu, bus, SbcOperation) }
                 }
-                (Instruction::Tya, OpMode::Implied) => {
-                    yield_all! { implied(nes, TyaOperation) }
+                (_, 0x38) => {
+                    yield_all! { implied(cpu, bus, SecOperation) }
                 }
-                (Instruction::UnofficialAnc, OpMode::Imm) => {
-                    yield_all! { imm_read(nes, UnofficialAncOperation) }
+                (_, 0xF8) => {
+                    yield_all! { implied(cpu, bus, SedOperation) }
                 }
-                (Instruction::UnofficialAlr, OpMode::Imm) => {
-                    yield_all! { imm_read(nes, UnofficialAlrOperation) }
+                (_, 0x78) => {
+                    yield_all! { implied(cpu, bus, SeiOperation) }
                 }
-                (Instruction::UnofficialArr, OpMode::Imm) => {
-                    yield_all! { imm_read(nes, UnofficialArrOperation) }
+                (_, 0x8D) => {
+                    yield_all! { abs_write(cpu, bus, StaOperation) }
                 }
-                (Instruction::UnofficialAxs, OpMode::Imm) => {
-                    yield_all! { imm_read(nes, UnofficialAxsOperation) }
+                (_, 0x9D) => {
+                    yield_all! { abs_x_write(cpu, bus, StaOperation) }
                 }
-                (Instruction::UnofficialDcp, OpMode::Abs) => {
-                    yield_all! { abs_modify(nes, UnofficialDcpOperation) }
+                (_, 0x99) => {
+                    yield_all! { abs_y_write(cpu, bus, StaOperation) }
                 }
-                (Instruction::UnofficialDcp, OpMode::AbsX) => {
-                    yield_all! { abs_x_modify(nes, UnofficialDcpOperation) }
+                (_, 0x81) => {
+                    yield_all! { ind_x_write(cpu, bus, StaOperation) }
                 }
-                (Instruction::UnofficialDcp, OpMode::AbsY) => {
-                    yield_all! { abs_y_modify(nes, UnofficialDcpOperation) }
+                (_, 0x91) => {
+                    yield_all! { ind_y_write(cpu, bus, StaOperation) }
                 }
-                (Instruction::UnofficialDcp, OpMode::IndX) => {
-                    yield_all! { ind_x_modify(nes, UnofficialDcpOperation) }
+                (OpcodeSet::Cmos, 0x92) => {
+                    yield_all! { zero_ind_write(cpu, bus, StaOperation) }
                 }
-                (Instruction::UnofficialDcp, OpMode::IndY) => {
-                    yield_all! { ind_y_modify(nes, UnofficialDcpOperation) }
+                (_, 0x85) => {
+                    yield_all! { zero_write(cpu, bus, StaOperation) }
                 }
-                (Instruction::UnofficialDcp, OpMode::Zero) => {
-                    yield_all! { zero_modify(nes, UnofficialDcpOperation) }
+                (_, 0x95) => {
+                    yield_all! { zero_x_write(cpu, bus, StaOperation) }
                 }
-                (Instruction::UnofficialDcp, OpMode::ZeroX) => {
-                    yield_all! { zero_x_modify(nes, UnofficialDcpOperation) }
+                (_, 0x8E) => {
+                    yield_all! { abs_write(cpu, bus, StxOperation) }
                 }
-                (Instruction::UnofficialIsc, OpMode::Abs) => {
-                    yield_all! { abs_modify(nes, UnofficialIscOperation) }
+                (_, 0x86) => {
+                    yield_all! { zero_write(cpu, bus, StxOperation) }
                 }
-                (Instruction::UnofficialIsc, OpMode::AbsX) => {
-                    yield_all! { abs_x_modify(nes, UnofficialIscOperation) }
+                (_, 0x96) => {
+                    yield_all! { zero_y_write(cpu, bus, StxOperation) }
                 }
-                (Instruction::UnofficialIsc, OpMode::AbsY) => {
-                    yield_all! { abs_y_modify(nes, UnofficialIscOperation) }
+                (_, 0x8C) => {
+                    yield_all! { abs_write(cpu, bus, StyOperation) }
                 }
-                (Instruction::UnofficialIsc, OpMode::IndX) => {
-                    yield_all! { ind_x_modify(nes, UnofficialIscOperation) }
+                (_, 0x84) => {
+                    yield_all! { zero_write(cpu, bus, StyOperation) }
                 }
-                (Instruction::UnofficialIsc, OpMode::IndY) => {
-                    yield_all! { ind_y_modify(nes, UnofficialIscOperation) }
+                (_, 0x94) => {
+                    yield_all! { zero_x_write(cpu, bus, StyOperation) }
                 }
-                (Instruction::UnofficialIsc, OpMode::Zero) => {
-                    yield_all! { zero_modify(nes, UnofficialIscOperation) }
+                (OpcodeSet::Cmos, 0x9C) => {
+                    yield_all! { abs_write(cpu, bus, StzOperation) }
                 }
-                (Instruction::UnofficialIsc, OpMode::ZeroX) => {
-                    yield_all! { zero_x_modify(nes, UnofficialIscOperation) }
+                (OpcodeSet::Cmos, 0x9E) => {
+                    yield_all! { abs_x_write(cpu, bus, StzOperation) }
                 }
-                (Instruction::UnofficialLax, OpMode::Abs) => {
-                    yield_all! { abs_read(nes, UnofficialLaxOperation) }
+                (OpcodeSet::Cmos, 0x64) => {
+                    yield_all! { zero_write(cpu, bus, StzOperation) }
                 }
-                (Instruction::UnofficialLax, OpMode::AbsY) => {
-                    yield_all! { abs_y_read(nes, UnofficialLaxOperation) }
+                (OpcodeSet::Cmos, 0x74) => {
+                    yield_all! { zero_x_write(cpu, bus, StzOperation) }
                 }
-                (Instruction::UnofficialLax, OpMode::Imm) => {
-                    yield_all! { imm_read(nes, UnofficialLaxOperation) }
+                (_, 0xAA) => {
+                    yield_all! { implied(cpu, bus, TaxOperation) }
                 }
-                (Instruction::UnofficialLax, OpMode::IndX) => {
-                    yield_all! { ind_x_read(nes, UnofficialLaxOperation) }
+                (_, 0xA8) => {
+                    yield_all! { implied(cpu, bus, TayOperation) }
                 }
-                (Instruction::UnofficialLax, OpMode::IndY) => {
-                    yield_all! { ind_y_read(nes, UnofficialLaxOperation) }
+                (OpcodeSet::Cmos, 0x1C) => {
+                    yield_all! { abs_modify(cpu, bus, TrbOperation) }
                 }
-                (Instruction::UnofficialLax, OpMode::Zero) => {
-                    yield_all! { zero_read(nes, UnofficialLaxOperation) }
+                (OpcodeSet::Cmos, 0x14) => {
+                    yield_all! { zero_modify(cpu, bus, TrbOperation) }
                 }
-                (Instruction::UnofficialLax, OpMode::ZeroY) => {
-                    yield_all! { zero_y_read(nes, UnofficialLaxOperation) }
+                (OpcodeSet::Cmos, 0x0C) => {
+                    yield_all! { abs_modify(cpu, bus, TsbOperation) }
                 }
-                (Instruction::UnofficialNop, OpMode::Abs) => {
-                    yield_all! { abs_read(nes, UnofficialNopOperation) }
+                (OpcodeSet::Cmos, 0x04) => {
+                    yield_all! { zero_modify(cpu, bus, TsbOperation) }
                 }
-                (Instruction::UnofficialNop, OpMode::AbsX) => {
-                    yield_all! { abs_x_read(nes, UnofficialNopOperation) }
+                (_, 0xBA) => {
+                    yield_all! { implied(cpu, bus, TsxOperation) }
                 }
-                (Instruction::UnofficialNop, OpMode::Imm) => {
-                    yield_all! { imm_read(nes, UnofficialNopOperation) }
+                (_, 0x8A) => {
+                    yield_all! { implied(cpu, bus, TxaOperation) }
                 }
-                (Instruction::UnofficialNop, OpMode::Implied) => {
-                    yield_all! { implied(nes, UnofficialNopOperation) }
+                (_, 0x9A) => {
+                    yield_all! { implied(cpu, bus, TxsOperation) }
                 }
-                (Instruction::UnofficialNop, OpMode::Zero) => {
-                    yield_all! { zero_read(nes, UnofficialNopOperation) }
+                (_, 0x98) => {
+                    yield_all! { implied(cpu, bus, TyaOperation) }
                 }
-                (Instruction::UnofficialNop, OpMode::ZeroX) => {
-                    yield_all! { zero_x_read(nes, UnofficialNopOperation) }
+                (OpcodeSet::Nmos, 0x9F) => {
+                    yield_all! { abs_y_unstable_write(cpu, bus, UnofficialAhxOperation) }
                 }
-                (Instruction::UnofficialRla, OpMode::Abs) => {
-                    yield_all! { abs_modify(nes, UnofficialRlaOperation) }
+                (OpcodeSet::Nmos, 0x93) => {
+                    yield_all! { ind_y_unstable_write(cpu, bus, UnofficialAhxOperation) }
                 }
-                (Instruction::UnofficialRla, OpMode::AbsX) => {
-                    yield_all! { abs_x_modify(nes, UnofficialRlaOperation) }
+                (OpcodeSet::Nmos, 0x0B) | (OpcodeSet::Nmos, 0x2B) => {
+                    yield_all! { imm_read(cpu, bus, UnofficialAncOperation) }
                 }
-                (Instruction::UnofficialRla, OpMode::AbsY) => {
-                    yield_all! { abs_y_modify(nes, UnofficialRlaOperation) }
+                (OpcodeSet::Nmos, 0x4B) => {
+                    yield_all! { imm_read(cpu, bus, UnofficialAlrOperation) }
                 }
-                (Instruction::UnofficialRla, OpMode::IndX) => {
-                    yield_all! { ind_x_modify(nes, UnofficialRlaOperation) }
+                (OpcodeSet::Nmos, 0x6B) => {
+                    yield_all! { imm_read(cpu, bus, UnofficialArrOperation) }
                 }
-                (Instruction::UnofficialRla, OpMode::IndY) => {
-                    yield_all! { ind_y_modify(nes, UnofficialRlaOperation) }
+                (OpcodeSet::Nmos, 0xCB) => {
+                    yield_all! { imm_read(cpu, bus, UnofficialAxsOperation) }
                 }
-                (Instruction::UnofficialRla, OpMode::Zero) => {
-                    yield_all! { zero_modify(nes, UnofficialRlaOperation) }
+                (OpcodeSet::Nmos, 0xCF) => {
+                    yield_all! { abs_modify(cpu, bus, UnofficialDcpOperation) }
                 }
-                (Instruction::UnofficialRla, OpMode::ZeroX) => {
-                    yield_all! { zero_x_modify(nes, UnofficialRlaOperation) }
+                (OpcodeSet::Nmos, 0xDF) => {
+                    yield_all! { abs_x_modify(cpu, bus, UnofficialDcpOperation) }
                 }
-                (Instruction::UnofficialRra, OpMode::Abs) => {
-                    yield_all! { abs_modify(nes, UnofficialRraOperation) }
+                (OpcodeSet::Nmos, 0xDB) => {
+                    yield_all! { abs_y_modify(cpu, bus, UnofficialDcpOperation) }
                 }
-                (Instruction::UnofficialRra, OpMode::AbsX) => {
-                    yield_all! { abs_x_modify(nes, UnofficialRraOperation) }
+                (OpcodeSet::Nmos, 0xC3) => {
+                    yield_all! { ind_x_modify(cpu, bus, UnofficialDcpOperation) }
                 }
-                (Instruction::UnofficialRra, OpMode::IndX) => {
-                    yield_all! { ind_x_modify(nes, UnofficialRraOperation) }
+                (OpcodeSet::Nmos, 0xD3) => {
+                    yield_all! { ind_y_modify(cpu, bus, UnofficialDcpOperation) }
                 }
-                (Instruction::UnofficialRra, OpMode::IndY) => {
-                    yield_all! { ind_y_modify(nes, UnofficialRraOperation) }
+                (OpcodeSet::Nmos, 0xC7) => {
+                    yield_all! { zero_modify(cpu, bus, UnofficialDcpOperation) }
                 }
-                (Instruction::UnofficialRra, OpMode::AbsY) => {
-                    yield_all! { abs_y_modify(nes, UnofficialRraOperation) }
+                (OpcodeSet::Nmos, 0xD7) => {
+                    yield_all! { zero_x_modify(cpu, bus, UnofficialDcpOperation) }
                 }
-                (Instruction::UnofficialRra, OpMode::Zero) => {
-                    yield_all! { zero_modify(nes, UnofficialRraOperation) }
+                (OpcodeSet::Nmos, 0xEF) => {
+                    yield_all! { abs_modify(cpu, bus, UnofficialIscOperation) }
                 }
-                (Instruction::UnofficialRra, OpMode::ZeroX) => {
-                    yield_all! { zero_x_modify(nes, UnofficialRraOperation) }
+                (OpcodeSet::Nmos, 0xFF) => {
+                    yield_all! { abs_x_modify(cpu, bus, UnofficialIscOperation) }
                 }
-                (Instruction::UnofficialSax, OpMode::Abs) => {
-                    yield_all! { abs_write(nes, UnofficialSaxOperation) }
+                (OpcodeSet::Nmos, 0xFB) => {
+                    yield_all! { abs_y_modify(cpu, bus, UnofficialIscOperation) }
                 }
-                (Instruction::UnofficialSax, OpMode::IndX) => {
-                    yield_all! { ind_x_write(nes, UnofficialSaxOperation) }
+                (OpcodeSet::Nmos, 0xE3) => {
+                    yield_all! { ind_x_modify(cpu, bus, UnofficialIscOperation) }
+                }
+                (OpcodeSet::Nmos, 0xF3) => {
+                    yield_all! { ind_y_modify(cpu, bus, UnofficialIscOperation) }
+                }
+                (OpcodeSet::Nmos, 0xE7) => {
+                    yield_all! { zero_modify(cpu, bus, UnofficialIscOperation) }
+                }
+                (OpcodeSet::Nmos, 0xF7) => {
+                    yield_all! { zero_x_modify(cpu, bus, UnofficialIscOperation) }
+                }
+                (OpcodeSet::Nmos, 0x02)
+                | (OpcodeSet::Nmos, 0x12)
+                | (OpcodeSet::Nmos, 0x22)
+                | (OpcodeSet::Nmos, 0x32)
+                | (OpcodeSet::Nmos, 0x42)
+                | (OpcodeSet::Nmos, 0x52)
+                | (OpcodeSet::Nmos, 0x62)
+                | (OpcodeSet::Nmos, 0x72)
+                | (OpcodeSet::Nmos, 0x92)
+                | (OpcodeSet::Nmos, 0xB2)
+                | (OpcodeSet::Nmos, 0xD2)
+                | (OpcodeSet::Nmos, 0xF2) => {
+                    yield_all! { jam(cpu, bus) }
+                }
+                (OpcodeSet::Nmos, 0xBB) => {
+                    yield_all! { abs_y_read(cpu, bus, UnofficialLasOperation) }
+                }
+                (OpcodeSet::Nmos, 0xAF) => {
+                    yield_all! { abs_read(cpu, bus, UnofficialLaxOperation) }
+                }
+                (OpcodeSet::Nmos, 0xBF) => {
+                    yield_all! { abs_y_read(cpu, bus, UnofficialLaxOperation) }
+                }
+                (OpcodeSet::Nmos, 0xA3) => {
+                    yield_all! { ind_x_read(cpu, bus, UnofficialLaxOperation) }
+                }
+                (OpcodeSet::Nmos, 0xB3) => {
+                    yield_all! { ind_y_read(cpu, bus, UnofficialLaxOperation) }
+                }
+                (OpcodeSet::Nmos, 0xA7) => {
+                    yield_all! { zero_read(cpu, bus, UnofficialLaxOperation) }
+                }
+                (OpcodeSet::Nmos, 0xB7) => {
+                    yield_all! { zero_y_read(cpu, bus, UnofficialLaxOperation) }
+                }
+                (OpcodeSet::Nmos, 0xAB) => {
+                    yield_all! { imm_read(cpu, bus, UnofficialLxaOperation) }
+                }
+                (OpcodeSet::Nmos, 0x0C)
+                | (OpcodeSet::Cmos, 0x5C)
+                | (OpcodeSet::Cmos, 0xDC)
+                | (OpcodeSet::Cmos, 0xFC) => {
+                    yield_all! { abs_read(cpu, bus, UnofficialNopOperation) }
+                }
+                (OpcodeSet::Nmos, 0x1C)
+                | (OpcodeSet::Nmos, 0x3C)
+                | (OpcodeSet::Nmos, 0x5C)
+                | (OpcodeSet::Nmos, 0x7C)
+                | (OpcodeSet::Nmos, 0xDC)
+                | (OpcodeSet::Nmos, 0xFC) => {
+                    yield_all! { abs_x_read(cpu, bus, UnofficialNopOperation) }
+                }
+                (OpcodeSet::Cmos, 0x02)
+                | (OpcodeSet::Cmos, 0x22)
+                | (OpcodeSet::Cmos, 0x42)
+                | (OpcodeSet::Cmos, 0x62)
+                | (OpcodeSet::Nmos, 0x80)
+                | (_, 0x82)
+                | (OpcodeSet::Nmos, 0x89)
+                | (_, 0xC2)
+                | (_, 0xE2) => {
+                    yield_all! { imm_read(cpu, bus, UnofficialNopOperation) }
+                }
+                (OpcodeSet::Cmos, 0x03)
+                | (OpcodeSet::Cmos, 0x07)
+                | (OpcodeSet::Cmos, 0x0B)
+                | (OpcodeSet::Cmos, 0x0F)
+                | (OpcodeSet::Cmos, 0x13)
+                | (OpcodeSet::Cmos, 0x17)
+                | (OpcodeSet::Nmos, 0x1A)
+                | (OpcodeSet::Cmos, 0x1B)
+                | (OpcodeSet::Cmos, 0x1F)
+                | (OpcodeSet::Cmos, 0x23)
+                | (OpcodeSet::Cmos, 0x27)
+                | (OpcodeSet::Cmos, 0x2B)
+                | (OpcodeSet::Cmos, 0x2F)
+                | (OpcodeSet::Cmos, 0x33)
+                | (OpcodeSet::Cmos, 0x37)
+                | (OpcodeSet::Nmos, 0x3A)
+                | (OpcodeSet::Cmos, 0x3B)
+                | (OpcodeSet::Cmos, 0x3F)
+                | (OpcodeSet::Cmos, 0x43)
+                | (OpcodeSet::Cmos, 0x47)
+                | (OpcodeSet::Cmos, 0x4B)
+                | (OpcodeSet::Cmos, 0x4F)
+                | (OpcodeSet::Cmos, 0x53)
+                | (OpcodeSet::Cmos, 0x57)
+                | (OpcodeSet::Nmos, 0x5A)
+                | (OpcodeSet::Cmos, 0x5B)
+                | (OpcodeSet::Cmos, 0x5F)
+                | (OpcodeSet::Cmos, 0x63)
+                | (OpcodeSet::Cmos, 0x67)
+                | (OpcodeSet::Cmos, 0x6B)
+                | (OpcodeSet::Cmos, 0x6F)
+                | (OpcodeSet::Cmos, 0x73)
+                | (OpcodeSet::Cmos, 0x77)
+                | (OpcodeSet::Nmos, 0x7A)
+                | (OpcodeSet::Cmos, 0x7B)
+                | (OpcodeSet::Cmos, 0x7F)
+                | (OpcodeSet::Cmos, 0x83)
+                | (OpcodeSet::Cmos, 0x87)
+                | (OpcodeSet::Cmos, 0x8B)
+                | (OpcodeSet::Cmos, 0x8F)
+                | (OpcodeSet::Cmos, 0x93)
+                | (OpcodeSet::Cmos, 0x97)
+                | (OpcodeSet::Cmos, 0x9B)
+                | (OpcodeSet::Cmos, 0x9F)
+                | (OpcodeSet::Cmos, 0xA3)
+                | (OpcodeSet::Cmos, 0xA7)
+                | (OpcodeSet::Cmos, 0xAB)
+                | (OpcodeSet::Cmos, 0xAF)
+                | (OpcodeSet::Cmos, 0xB3)
+                | (OpcodeSet::Cmos, 0xB7)
+                | (OpcodeSet::Cmos, 0xBB)
+                | (OpcodeSet::Cmos, 0xBF)
+                | (OpcodeSet::Cmos, 0xC3)
+                | (OpcodeSet::Cmos, 0xC7)
+                | (OpcodeSet::Cmos, 0xCB)
+                | (OpcodeSet::Cmos, 0xCF)
+                | (OpcodeSet::Cmos, 0xD3)
+                | (OpcodeSet::Cmos, 0xD7)
+                | (OpcodeSet::Nmos, 0xDA)
+                | (OpcodeSet::Cmos, 0xDB)
+                | (OpcodeSet::Cmos, 0xDF)
+                | (OpcodeSet::Cmos, 0xE3)
+                | (OpcodeSet::Cmos, 0xE7)
+                | (OpcodeSet::Cmos, 0xEB)
+                | (OpcodeSet::Cmos, 0xEF)
+                | (OpcodeSet::Cmos, 0xF3)
+                | (OpcodeSet::Cmos, 0xF7)
+                | (OpcodeSet::Nmos, 0xFA)
+                | (OpcodeSet::Cmos, 0xFB)
+                | (OpcodeSet::Cmos, 0xFF) => {
+                    yield_all! { implied(cpu, bus, UnofficialNopOperation) }
+                }
+                (OpcodeSet::Nmos, 0x04) | (_, 0x44) | (OpcodeSet::Nmos, 0x64) => {
+                    yield_all! { zero_read(cpu, bus, UnofficialNopOperation) }
+                }
+                (OpcodeSet::Nmos, 0x14)
+                | (OpcodeSet::Nmos, 0x34)
+                | (_, 0x54)
+                | (OpcodeSet::Nmos, 0x74)
+                | (_, 0xD4)
+                | (_, 0xF4) => {
+                    yield_all! { zero_x_read(cpu, bus, UnofficialNopOperation) }
+                }
+                (OpcodeSet::Nmos, 0x2F) => {
+                    yield_all! { abs_modify(cpu, bus, UnofficialRlaOperation) }
+                }
+                (OpcodeSet::Nmos, 0x3F) => {
+                    yield_all! { abs_x_modify(cpu, bus, UnofficialRlaOperation) }
+                }
+                (OpcodeSet::Nmos, 0x3B) => {
+                    yield_all! { abs_y_modify(cpu, bus, UnofficialRlaOperation) }
+                }
+                (OpcodeSet::Nmos, 0x23) => {
+                    yield_all! { ind_x_modify(cpu, bus, UnofficialRlaOperation) }
+                }
+                (OpcodeSet::Nmos, 0x33) => {
+                    yield_all! { ind_y_modify(cpu, bus, UnofficialRlaOperation) }
+                }
+                (OpcodeSet::Nmos, 0x27) => {
+                    yield_all! { zero_modify(cpu, bus, UnofficialRlaOperation) }
+                }
+                (OpcodeSet::Nmos, 0x37) => {
+                    yield_all! { zero_x_modify(cpu, bus, UnofficialRlaOperation) }
+                }
+                (OpcodeSet::Nmos, 0x6F) => {
+                    yield_all! { abs_modify(cpu, bus, UnofficialRraOperation) }
+                }
+                (OpcodeSet::Nmos, 0x7F) => {
+                    yield_all! { abs_x_modify(cpu, bus, UnofficialRraOperation) }
+                }
+                (OpcodeSet::Nmos, 0x63) => {
+                    yield_all! { ind_x_modify(cpu, bus, UnofficialRraOperation) }
+                }
+                (OpcodeSet::Nmos, 0x73) => {
+                    yield_all! { ind_y_modify(cpu, bus, UnofficialRraOperation) }
+                }
+                (OpcodeSet::Nmos, 0x7B) => {
+                    yield_all! { abs_y_modify(cpu, bus, UnofficialRraOperation) }
+                }
+                (OpcodeSet::Nmos, 0x67) => {
+                    yield_all! { zero_modify(cpu, bus, UnofficialRraOperation) }
+                }
+                (OpcodeSet::Nmos, 0x77) => {
+                    yield_all! { zero_x_modify(cpu, bus, UnofficialRraOperation) }
+                }
+                (OpcodeSet::Nmos, 0x8F) => {
+                    yield_all! { abs_write(cpu, bus, UnofficialSaxOperation) }
+                }
+                (OpcodeSet::Nmos, 0x83) => {
+                    yield_all! { ind_x_write(cpu, bus, UnofficialSaxOperation) }
+                }
+                (OpcodeSet::Nmos, 0x87) => {
+                    yield_all! { zero_write(cpu, bus, UnofficialSaxOperation) }
                 }
-                (Instruction::UnofficialSax, OpMode::Zero) => {
-                    yield_all! { zero_write(nes, UnofficialSaxOperation) }
+                (OpcodeSet::Nmos, 0x97) => {
+                    yield_all! { zero_y_write(cpu, bus, UnofficialSaxOperation) }
                 }
-                (Instruction::UnofficialSax, OpMode::ZeroY) => {
-                    yield_all! { zero_y_write(nes, UnofficialSaxOperation) }
+                (OpcodeSet::Nmos, 0xEB) => {
+                    yield_all! { imm_read(cpu, bus, UnofficialSbcOperation) }
                 }
-                (Instruction::UnofficialSbc, OpMode::Imm) => {
-                    yield_all! { imm_read(nes, UnofficialSbcOperation) }
+                (OpcodeSet::Nmos, 0x9E) => {
+                    yield_all! { abs_y_unstable_write(cpu, bus, UnofficialShxOperation) }
                 }
-                (Instruction::UnofficialShx, OpMode::AbsY) => {
-                    yield_all! { abs_y_modify(nes, UnofficialShxOperation) }
+                (OpcodeSet::Nmos, 0x9C) => {
+                    yield_all! { abs_x_unstable_write(cpu, bus, UnofficialShyOperation) }
                 }
-                (Instruction::UnofficialShy, OpMode::AbsX) => {
-                    yield_all! { abs_x_modify(nes, UnofficialShyOperation) }
+                (OpcodeSet::Nmos, 0x0F) => {
+                    yield_all! { abs_modify(cpu, bus, UnofficialSloOperation) }
                 }
-                (Instruction::UnofficialSlo, OpMode::Abs) => {
-                    yield_all! { abs_modify(nes, UnofficialSloOperation) }
+                (OpcodeSet::Nmos, 0x1F) => {
+                    yield_all! { abs_x_modify(cpu, bus, UnofficialSloOperation) }
                 }
-                (Instruction::UnofficialSlo, OpMode::AbsX) => {
-                    yield_all! { abs_x_modify(nes, UnofficialSloOperation) }
+                (OpcodeSet::Nmos, 0x1B) => {
+                    yield_all! { abs_y_modify(cpu, bus, UnofficialSloOperation) }
                 }
-                (Instruction::UnofficialSlo, OpMode::AbsY) => {
-                    yield_all! { abs_y_modify(nes, UnofficialSloOperation) }
+                (OpcodeSet::Nmos, 0x03) => {
+                    yield_all! { ind_x_modify(cpu, bus, UnofficialSloOperation) }
                 }
-                (Instruction::UnofficialSlo, OpMode::IndX) => {
-                    yield_all! { ind_x_modify(nes, UnofficialSloOperation) }
+                (OpcodeSet::Nmos, 0x13) => {
+                    yield_all! { ind_y_modify(cpu, bus, UnofficialSloOperation) }
                 }
-                (Instruction::UnofficialSlo, OpMode::IndY) => {
-                    yield_all! { ind_y_modify(nes, UnofficialSloOperation) }
+                (OpcodeSet::Nmos, 0x07) => {
+                    yield_all! { zero_modify(cpu, bus, UnofficialSloOperation) }
                 }
-                (Instruction::UnofficialSlo, OpMode::Zero) => {
-                    yield_all! { zero_modify(nes, UnofficialSloOperation) }
+                (OpcodeSet::Nmos, 0x17) => {
+                    yield_all! { zero_x_modify(cpu, bus, UnofficialSloOperation) }
                 }
-                (Instruction::UnofficialSlo, OpMode::ZeroX) => {
-                    yield_all! { zero_x_modify(nes, UnofficialSloOperation) }
+                (OpcodeSet::Nmos, 0x4F) => {
+                    yield_all! { abs_modify(cpu, bus, UnofficialSreOperation) }
                 }
-                (Instruction::UnofficialSre, OpMode::Abs) => {
-                    yield_all! { abs_modify(nes, UnofficialSreOperation) }
+                (OpcodeSet::Nmos, 0x5F) => {
+                    yield_all! { abs_x_modify(cpu, bus, UnofficialSreOperation) }
                 }
-                (Instruction::UnofficialSre, OpMode::AbsX) => {
-                    yield_all! { abs_x_modify(nes, UnofficialSreOperation) }
+                (OpcodeSet::Nmos, 0x5B) => {
+                    yield_all! { abs_y_modify(cpu, bus, UnofficialSreOperation) }
                 }
-                (Instruction::UnofficialSre, OpMode::AbsY) => {
-                    yield_all! { abs_y_modify(nes, UnofficialSreOperation) }
+                (OpcodeSet::Nmos, 0x43) => {
+                    yield_all! { ind_x_modify(cpu, bus, UnofficialSreOperation) }
                 }
-                (Instruction::UnofficialSre, OpMode::IndX) => {
-                    yield_all! { ind_x_modify(nes, UnofficialSreOperation) }
+                (OpcodeSet::Nmos, 0x53) => {
+                    yield_all! { ind_y_modify(cpu, bus, UnofficialSreOperation) }
                 }
-                (Instruction::UnofficialSre, OpMode::IndY) => {
-                    yield_all! { ind_y_modify(nes, UnofficialSreOperation) }
+                (OpcodeSet::Nmos, 0x47) => {
+                    yield_all! { zero_modify(cpu, bus, UnofficialSreOperation) }
                 }
-                (Instruction::UnofficialSre, OpMode::Zero) => {
-                    yield_all! { zero_modify(nes, UnofficialSreOperation) }
+                (OpcodeSet::Nmos, 0x57) => {
+                    yield_all! { zero_x_modify(cpu, bus, UnofficialSreOperation) }
                 }
-                (Instruction::UnofficialSre, OpMode::ZeroX) => {
-                    yield_all! { zero_x_modify(nes, UnofficialSreOperation) }
+                (OpcodeSet::Nmos, 0x9B) => {
+                    yield_all! { abs_y_unstable_write(cpu, bus, UnofficialTasOperation) }
                 }
-                insn_with_mode => {
-                    unimplemented!("Unhandled instruction/mode: {:?}", insn_with_mode);
+                (OpcodeSet::Nmos, 0x8B) => {
+                    yield_all! { imm_read(cpu, bus, UnofficialXaaOperation) }
                 }
             };
 
             debug_assert_eq!(instruction_with_mode, op.instruction_with_mode());
 
-            yield CpuStep::Op(CpuStepOp { pc, op });
+            yield CpuStep::Op(CpuStepOp { cycle, pc, op });
+
+            // Only the poll from before the instruction's last cycle counts,
+            // so CLI, SEI, and PLP (which change the `I` flag during their
+            // last cycle) delay their effect on IRQs by one instruction
+            if let Some(interrupt) = cpu.penultimate_poll.get() {
+                yield_all! { interrupt_sequence(cpu, bus, interrupt) };
+            }
         }
     }
 }
@@ -795,7 +1162,22 @@ bitflags! {
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
-enum Instruction {
+enum Interrupt {
+    Reset,
+    Nmi,
+    Irq,
+}
+
+bitflags! {
+    pub struct IrqSources: u8 {
+        const APU_FRAME = 1 << 0;
+        const APU_DMC = 1 << 1;
+        const MAPPER = 1 << 2;
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Instruction {
     Adc,
     And,
     Asl,
@@ -806,6 +1188,7 @@ enum Instruction {
     Bmi,
     Bne,
     Bpl,
+    Bra,
     Brk,
     Bvc,
     Bvs,
@@ -833,8 +1216,12 @@ enum Instruction {
     Ora,
     Pha,
     Php,
+    Phx,
+    Phy,
     Pla,
     Plp,
+    Plx,
+    Ply,
     Rol,
     Ror,
     Rti,
@@ -846,19 +1233,26 @@ enum Instruction {
     Sta,
     Stx,
     Sty,
+    Stz,
     Tax,
     Tay,
+    Trb,
+    Tsb,
     Tsx,
     Txa,
     Txs,
     Tya,
+    UnofficialAhx,
     UnofficialAnc,
     UnofficialAlr,
     UnofficialArr,
     UnofficialAxs,
     UnofficialDcp,
     UnofficialIsc,
+    UnofficialJam,
+    UnofficialLas,
     UnofficialLax,
+    UnofficialLxa,
     UnofficialNop,
     UnofficialRla,
     UnofficialRra,
@@ -868,6 +1262,38 @@ enum Instruction {
     UnofficialShy,
     UnofficialSlo,
     UnofficialSre,
+    UnofficialTas,
+    UnofficialXaa,
+}
+
+impl Instruction {
+    pub fn is_unofficial(&self) -> bool {
+        match self {
+            Instruction::UnofficialAhx
+            | Instruction::UnofficialAnc
+            | Instruction::UnofficialAlr
+            | Instruction::UnofficialArr
+            | Instruction::UnofficialAxs
+            | Instruction::UnofficialDcp
+            | Instruction::UnofficialIsc
+            | Instruction::UnofficialJam
+            | Instruction::UnofficialLas
+            | Instruction::UnofficialLax
+            | Instruction::UnofficialLxa
+            | Instruction::UnofficialNop
+            | Instruction::UnofficialRla
+            | Instruction::UnofficialRra
+            | Instruction::UnofficialSax
+            | Instruction::UnofficialSbc
+            | Instruction::UnofficialShx
+            | Instruction::UnofficialShy
+            | Instruction::UnofficialSlo
+            | Instruction::UnofficialSre
+            | Instruction::UnofficialTas
+            | Instruction::UnofficialXaa => true,
+            _ => false,
+        }
+    }
 }
 
 impl fmt::Display for Instruction {
@@ -883,6 +1309,7 @@ impl fmt::Display for Instruction {
             Instruction::Bmi => "BMI",
             Instruction::Bne => "BNE",
             Instruction::Bpl => "BPL",
+            Instruction::Bra => "BRA",
             Instruction::Brk => "BRK",
             Instruction::Bvc => "BVC",
             Instruction::Bvs => "BVS",
@@ -910,8 +1337,12 @@ impl fmt::Display for Instruction {
             Instruction::Ora => "ORA",
             Instruction::Pha => "PHA",
             Instruction::Php => "PHP",
+            Instruction::Phx => "PHX",
+            Instruction::Phy => "PHY",
             Instruction::Pla => "PLA",
             Instruction::Plp => "PLP",
+            Instruction::Plx => "PLX",
+            Instruction::Ply => "PLY",
             Instruction::Rol => "ROL",
             Instruction::Ror => "ROR",
             Instruction::Rti => "RTI",
@@ -923,19 +1354,26 @@ impl fmt::Display for Instruction {
             Instruction::Sta => "STA",
             Instruction::Stx => "STX",
             Instruction::Sty => "STY",
+            Instruction::Stz => "STZ",
             Instruction::Tax => "TAX",
             Instruction::Tay => "TAY",
+            Instruction::Trb => "TRB",
+            Instruction::Tsb => "TSB",
             Instruction::Tsx => "TSX",
             Instruction::Txa => "TXA",
             Instruction::Txs => "TXS",
             Instruction::Tya => "TYA",
+            Instruction::UnofficialAhx => "AHX",
             Instruction::UnofficialAnc => "ANC",
             Instruction::UnofficialAlr => "ALR",
             Instruction::UnofficialArr => "ARR",
             Instruction::UnofficialAxs => "AXS",
             Instruction::UnofficialDcp => "DCP",
             Instruction::UnofficialIsc => "ISC",
+            Instruction::UnofficialJam => "JAM",
+            Instruction::UnofficialLas => "LAS",
             Instruction::UnofficialLax => "LAX",
+            Instruction::UnofficialLxa => "LXA",
             Instruction::UnofficialRla => "RLA",
             Instruction::UnofficialRra => "RRA",
             Instruction::UnofficialSax => "SAX",
@@ -943,6 +1381,8 @@ impl fmt::Display for Instruction {
             Instruction::UnofficialShy => "SHY",
             Instruction::UnofficialSlo => "SLO",
             Instruction::UnofficialSre => "SRE",
+            Instruction::UnofficialTas => "TAS",
+            Instruction::UnofficialXaa => "XAA",
         };
         write!(f, "{}", mnemonic)?;
         Ok(())
@@ -950,12 +1390,13 @@ impl fmt::Display for Instruction {
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
-enum OpMode {
+pub enum OpMode {
     Implied,
     Accum,
     Abs,
     AbsX,
     AbsY,
+    AbsXInd,
     Branch,
     Imm,
     Ind,
@@ -964,15 +1405,17 @@ enum OpMode {
     Zero,
     ZeroX,
     ZeroY,
+    ZeroInd,
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
-enum OpArg {
+pub enum OpArg {
     Implied,
     Accum,
     Abs { addr: u16 },
     AbsX { addr_base: u16 },
     AbsY { addr_base: u16 },
+    AbsXInd { target_addr_base: u16 },
     Branch { addr_offset: i8 },
     Ind { target_addr: u16 },
     Imm { value: u8 },
@@ -981,6 +1424,25 @@ enum OpArg {
     Zero { zero_page: u8 },
     ZeroX { zero_page_base: u8 },
     ZeroY { zero_page_base: u8 },
+    ZeroInd { target_addr: u8 },
+}
+
+impl OpMode {
+    // The number of operand bytes that follow the opcode
+    pub fn operand_len(&self) -> usize {
+        match self {
+            OpMode::Implied | OpMode::Accum => 0,
+            OpMode::Branch
+            | OpMode::Imm
+            | OpMode::IndX
+            | OpMode::IndY
+            | OpMode::Zero
+            | OpMode::ZeroX
+            | OpMode::ZeroY
+            | OpMode::ZeroInd => 1,
+            OpMode::Abs | OpMode::AbsX | OpMode::AbsY | OpMode::AbsXInd | OpMode::Ind => 2,
+        }
+    }
 }
 
 impl From<OpArg> for OpMode {
@@ -991,6 +1453,7 @@ impl From<OpArg> for OpMode {
             OpArg::Abs { .. } => OpMode::Abs,
             OpArg::AbsX { .. } => OpMode::AbsX,
             OpArg::AbsY { .. } => OpMode::AbsY,
+            OpArg::AbsXInd { .. } => OpMode::AbsXInd,
             OpArg::Branch { .. } => OpMode::Branch,
             OpArg::Imm { .. } => OpMode::Imm,
             OpArg::Ind { .. } => OpMode::Ind,
@@ -999,14 +1462,15 @@ impl From<OpArg> for OpMode {
             OpArg::Zero { .. } => OpMode::Zero,
             OpArg::ZeroX { .. } => OpMode::ZeroX,
             OpArg::ZeroY { .. } => OpMode::ZeroY,
+            OpArg::ZeroInd { .. } => OpMode::ZeroInd,
         }
     }
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct Op {
-    instruction: Instruction,
-    arg: OpArg,
+    pub instruction: Instruction,
+    pub arg: OpArg,
 }
 
 impl Op {
@@ -1019,6 +1483,7 @@ fn opcode_to_instruction_with_mode(opcode: u8) -> (Instruction, OpMode) {
     match opcode {
         0x00 => (Instruction::Brk, OpMode::Implied),
         0x01 => (Instruction::Ora, OpMode::IndX),
+        0x02 => (Instruction::UnofficialJam, OpMode::Implied),
         0x03 => (Instruction::UnofficialSlo, OpMode::IndX),
         0x04 => (Instruction::UnofficialNop, OpMode::Zero),
         0x05 => (Instruction::Ora, OpMode::Zero),
@@ -1034,6 +1499,7 @@ fn opcode_to_instruction_with_mode(opcode: u8) -> (Instruction, OpMode) {
         0x0F => (Instruction::UnofficialSlo, OpMode::Abs),
         0x10 => (Instruction::Bpl, OpMode::Branch),
         0x11 => (Instruction::Ora, OpMode::IndY),
+        0x12 => (Instruction::UnofficialJam, OpMode::Implied),
         0x13 => (Instruction::UnofficialSlo, OpMode::IndY),
         0x14 => (Instruction::UnofficialNop, OpMode::ZeroX),
         0x15 => (Instruction::Ora, OpMode::ZeroX),
@@ -1049,6 +1515,7 @@ fn opcode_to_instruction_with_mode(opcode: u8) -> (Instruction, OpMode) {
         0x1F => (Instruction::UnofficialSlo, OpMode::AbsX),
         0x20 => (Instruction::Jsr, OpMode::Abs),
         0x21 => (Instruction::And, OpMode::IndX),
+        0x22 => (Instruction::UnofficialJam, OpMode::Implied),
         0x23 => (Instruction::UnofficialRla, OpMode::IndX),
         0x24 => (Instruction::Bit, OpMode::Zero),
         0x25 => (Instruction::And, OpMode::Zero),
@@ -1064,6 +1531,7 @@ fn opcode_to_instruction_with_mode(opcode: u8) -> (Instruction, OpMode) {
         0x2F => (Instruction::UnofficialRla, OpMode::Abs),
         0x30 => (Instruction::Bmi, OpMode::Branch),
         0x31 => (Instruction::And, OpMode::IndY),
+        0x32 => (Instruction::UnofficialJam, OpMode::Implied),
         0x33 => (Instruction::UnofficialRla, OpMode::IndY),
         0x34 => (Instruction::UnofficialNop, OpMode::ZeroX),
         0x35 => (Instruction::And, OpMode::ZeroX),
@@ -1079,6 +1547,7 @@ fn opcode_to_instruction_with_mode(opcode: u8) -> (Instruction, OpMode) {
         0x3F => (Instruction::UnofficialRla, OpMode::AbsX),
         0x40 => (Instruction::Rti, OpMode::Implied),
         0x41 => (Instruction::Eor, OpMode::IndX),
+        0x42 => (Instruction::UnofficialJam, OpMode::Implied),
         0x43 => (Instruction::UnofficialSre, OpMode::IndX),
         0x44 => (Instruction::UnofficialNop, OpMode::Zero),
         0x45 => (Instruction::Eor, OpMode::Zero),
@@ -1094,6 +1563,7 @@ fn opcode_to_instruction_with_mode(opcode: u8) -> (Instruction, OpMode) {
         0x4F => (Instruction::UnofficialSre, OpMode::Abs),
         0x50 => (Instruction::Bvc, OpMode::Branch),
         0x51 => (Instruction::Eor, OpMode::IndY),
+        0x52 => (Instruction::UnofficialJam, OpMode::Implied),
         0x53 => (Instruction::UnofficialSre, OpMode::IndY),
         0x54 => (Instruction::UnofficialNop, OpMode::ZeroX),
         0x55 => (Instruction::Eor, OpMode::ZeroX),
@@ -1109,6 +1579,7 @@ fn opcode_to_instruction_with_mode(opcode: u8) -> (Instruction, OpMode) {
         0x5F => (Instruction::UnofficialSre, OpMode::AbsX),
         0x60 => (Instruction::Rts, OpMode::Implied),
         0x61 => (Instruction::Adc, OpMode::IndX),
+        0x62 => (Instruction::UnofficialJam, OpMode::Implied),
         0x64 => (Instruction::UnofficialNop, OpMode::Zero),
         0x63 => (Instruction::UnofficialRra, OpMode::IndX),
         0x65 => (Instruction::Adc, OpMode::Zero),
@@ -1124,6 +1595,7 @@ fn opcode_to_instruction_with_mode(opcode: u8) -> (Instruction, OpMode) {
         0x6F => (Instruction::UnofficialRra, OpMode::Abs),
         0x70 => (Instruction::Bvs, OpMode::Branch),
         0x71 => (Instruction::Adc, OpMode::IndY),
+        0x72 => (Instruction::UnofficialJam, OpMode::Implied),
         0x73 => (Instruction::UnofficialRra, OpMode::IndY),
         0x74 => (Instruction::UnofficialNop, OpMode::ZeroX),
         0x75 => (Instruction::Adc, OpMode::ZeroX),
@@ -1148,12 +1620,15 @@ fn opcode_to_instruction_with_mode(opcode: u8) -> (Instruction, OpMode) {
         0x88 => (Instruction::Dey, OpMode::Implied),
         0x89 => (Instruction::UnofficialNop, OpMode::Imm),
         0x8A => (Instruction::Txa, OpMode::Implied),
+        0x8B => (Instruction::UnofficialXaa, OpMode::Imm),
         0x8C => (Instruction::Sty, OpMode::Abs),
         0x8D => (Instruction::Sta, OpMode::Abs),
         0x8E => (Instruction::Stx, OpMode::Abs),
         0x8F => (Instruction::UnofficialSax, OpMode::Abs),
         0x90 => (Instruction::Bcc, OpMode::Branch),
         0x91 => (Instruction::Sta, OpMode::IndY),
+        0x92 => (Instruction::UnofficialJam, OpMode::Implied),
+        0x93 => (Instruction::UnofficialAhx, OpMode::IndY),
         0x94 => (Instruction::Sty, OpMode::ZeroX),
         0x95 => (Instruction::Sta, OpMode::ZeroX),
         0x96 => (Instruction::Stx, OpMode::ZeroY),
@@ -1161,9 +1636,11 @@ fn opcode_to_instruction_with_mode(opcode: u8) -> (Instruction, OpMode) {
         0x98 => (Instruction::Tya, OpMode::Implied),
         0x99 => (Instruction::Sta, OpMode::AbsY),
         0x9A => (Instruction::Txs, OpMode::Implied),
+        0x9B => (Instruction::UnofficialTas, OpMode::AbsY),
         0x9C => (Instruction::UnofficialShy, OpMode::AbsX),
         0x9D => (Instruction::Sta, OpMode::AbsX),
         0x9E => (Instruction::UnofficialShx, OpMode::AbsY),
+        0x9F => (Instruction::UnofficialAhx, OpMode::AbsY),
         0xA0 => (Instruction::Ldy, OpMode::Imm),
         0xA1 => (Instruction::Lda, OpMode::IndX),
         0xA2 => (Instruction::Ldx, OpMode::Imm),
@@ -1175,13 +1652,14 @@ fn opcode_to_instruction_with_mode(opcode: u8) -> (Instruction, OpMode) {
         0xA8 => (Instruction::Tay, OpMode::Implied),
         0xA9 => (Instruction::Lda, OpMode::Imm),
         0xAA => (Instruction::Tax, OpMode::Implied),
-        0xAB => (Instruction::UnofficialLax, OpMode::Imm),
+        0xAB => (Instruction::UnofficialLxa, OpMode::Imm),
         0xAC => (Instruction::Ldy, OpMode::Abs),
         0xAD => (Instruction::Lda, OpMode::Abs),
         0xAE => (Instruction::Ldx, OpMode::Abs),
         0xAF => (Instruction::UnofficialLax, OpMode::Abs),
         0xB0 => (Instruction::Bcs, OpMode::Branch),
         0xB1 => (Instruction::Lda, OpMode::IndY),
+        0xB2 => (Instruction::UnofficialJam, OpMode::Implied),
         0xB3 => (Instruction::UnofficialLax, OpMode::IndY),
         0xB4 => (Instruction::Ldy, OpMode::ZeroX),
         0xB5 => (Instruction::Lda, OpMode::ZeroX),
@@ -1190,6 +1668,7 @@ fn opcode_to_instruction_with_mode(opcode: u8) -> (Instruction, OpMode) {
         0xB8 => (Instruction::Clv, OpMode::Implied),
         0xB9 => (Instruction::Lda, OpMode::AbsY),
         0xBA => (Instruction::Tsx, OpMode::Implied),
+        0xBB => (Instruction::UnofficialLas, OpMode::AbsY),
         0xBC => (Instruction::Ldy, OpMode::AbsX),
         0xBD => (Instruction::Lda, OpMode::AbsX),
         0xBE => (Instruction::Ldx, OpMode::AbsY),
@@ -1212,6 +1691,7 @@ fn opcode_to_instruction_with_mode(opcode: u8) -> (Instruction, OpMode) {
         0xCF => (Instruction::UnofficialDcp, OpMode::Abs),
         0xD0 => (Instruction::Bne, OpMode::Branch),
         0xD1 => (Instruction::Cmp, OpMode::IndY),
+        0xD2 => (Instruction::UnofficialJam, OpMode::Implied),
         0xD3 => (Instruction::UnofficialDcp, OpMode::IndY),
         0xD4 => (Instruction::UnofficialNop, OpMode::ZeroX),
         0xD5 => (Instruction::Cmp, OpMode::ZeroX),
@@ -1243,6 +1723,7 @@ fn opcode_to_instruction_with_mode(opcode: u8) -> (Instruction, OpMode) {
         0xEF => (Instruction::UnofficialIsc, OpMode::Abs),
         0xF0 => (Instruction::Beq, OpMode::Branch),
         0xF1 => (Instruction::Sbc, OpMode::IndY),
+        0xF2 => (Instruction::UnofficialJam, OpMode::Implied),
         0xF3 => (Instruction::UnofficialIsc, OpMode::IndY),
         0xF4 => (Instruction::UnofficialNop, OpMode::ZeroX),
         0xF5 => (Instruction::Sbc, OpMode::ZeroX),
@@ -1256,8 +1737,64 @@ fn opcode_to_instruction_with_mode(opcode: u8) -> (Instruction, OpMode) {
         0xFD => (Instruction::Sbc, OpMode::AbsX),
         0xFE => (Instruction::Inc, OpMode::AbsX),
         0xFF => (Instruction::UnofficialIsc, OpMode::AbsX),
+    }
+}
+
+fn decode_opcode(variant: CpuVariant, opcode: u8) -> (Instruction, OpMode) {
+    match variant.opcode_set() {
+        OpcodeSet::Nmos => opcode_to_instruction_with_mode(opcode),
+        OpcodeSet::Cmos => cmos_opcode_to_instruction_with_mode(opcode),
+    }
+}
+
+// The 65C02 keeps every official NMOS opcode, and uses some of the
+// unofficial ones for its new instructions. The rest are NOPs
+fn cmos_opcode_to_instruction_with_mode(opcode: u8) -> (Instruction, OpMode) {
+    match opcode {
+        0x04 => (Instruction::Tsb, OpMode::Zero),
+        0x0C => (Instruction::Tsb, OpMode::Abs),
+        0x12 => (Instruction::Ora, OpMode::ZeroInd),
+        0x14 => (Instruction::Trb, OpMode::Zero),
+        0x1A => (Instruction::Inc, OpMode::Accum),
+        0x1C => (Instruction::Trb, OpMode::Abs),
+        0x32 => (Instruction::And, OpMode::ZeroInd),
+        0x34 => (Instruction::Bit, OpMode::ZeroX),
+        0x3A => (Instruction::Dec, OpMode::Accum),
+        0x3C => (Instruction::Bit, OpMode::AbsX),
+        0x52 => (Instruction::Eor, OpMode::ZeroInd),
+        0x5A => (Instruction::Phy, OpMode::Implied),
+        0x64 => (Instruction::Stz, OpMode::Zero),
+        0x72 => (Instruction::Adc, OpMode::ZeroInd),
+        0x74 => (Instruction::Stz, OpMode::ZeroX),
+        0x7A => (Instruction::Ply, OpMode::Implied),
+        0x7C => (Instruction::Jmp, OpMode::AbsXInd),
+        0x80 => (Instruction::Bra, OpMode::Branch),
+        0x89 => (Instruction::Bit, OpMode::Imm),
+        0x92 => (Instruction::Sta, OpMode::ZeroInd),
+        0x9C => (Instruction::Stz, OpMode::Abs),
+        0x9E => (Instruction::Stz, OpMode::AbsX),
+        0xB2 => (Instruction::Lda, OpMode::ZeroInd),
+        0xD2 => (Instruction::Cmp, OpMode::ZeroInd),
+        0xDA => (Instruction::Phx, OpMode::Implied),
+        0xF2 => (Instruction::Sbc, OpMode::ZeroInd),
+        0xFA => (Instruction::Plx, OpMode::Implied),
         _ => {
-            unimplemented!("Unhandled opcode: 0x{:X}", opcode);
+            let (instruction, mode) = opcode_to_instruction_with_mode(opcode);
+            if !instruction.is_unofficial() {
+                return (instruction, mode);
+            }
+
+            // NOTE: The single-byte NOPs only take 1 cycle on a real
+            // 65C02, and $5C takes 8, but these run with the timing of the
+            // closest NMOS NOP
+            let nop_mode = match opcode & 0x0F {
+                0x02 => OpMode::Imm,
+                0x04 if opcode == 0x44 => OpMode::Zero,
+                0x04 => OpMode::ZeroX,
+                0x0C => OpMode::Abs,
+                _ => OpMode::Implied,
+            };
+            (Instruction::UnofficialNop, nop_mode)
         }
     }
 }
@@ -1298,6 +1835,12 @@ impl fmt::Display for Op {
             OpArg::IndY { target_addr_base } => {
                 write!(f, "{} (${:02X}),Y", self.instruction, target_addr_base)?;
             }
+            OpArg::AbsXInd { target_addr_base } => {
+                write!(f, "{} (${:04X},X)", self.instruction, target_addr_base)?;
+            }
+            OpArg::ZeroInd { target_addr } => {
+                write!(f, "{} (${:02X})", self.instruction, target_addr)?;
+            }
             OpArg::Imm { value } => {
                 write!(f, "{} #${:02X}", self.instruction, value)?;
             }
@@ -1316,10 +1859,17 @@ impl fmt::Display for Op {
 
 pub enum CpuStep {
     Cycle,
+
+    // The CPU is about to fetch the next instruction
+    Start,
+
+    // The CPU finished running an instruction
     Op(CpuStepOp),
 }
 
 pub struct CpuStepOp {
+    // The cycle (counted by `Cpu.cycles`) when the instruction started
+    pub cycle: u64,
     pub pc: u16,
     pub op: Op,
 }
@@ -1339,11 +1889,33 @@ trait ModifyOperation {
     fn instruction(&self) -> Instruction;
 }
 
+// The cycle where a read-modify-write instruction modifies its value. The
+// NMOS 6502 writes the original value back during this cycle, but the
+// 65C02 reads from the address again instead
+fn modify_dummy_access(cpu: &Cpu, bus: &impl Bus, addr: u16, value: u8) {
+    match cpu.variant {
+        CpuVariant::Ricoh2A03 | CpuVariant::Nmos6502 => {
+            bus.write_u8(addr, value);
+        }
+        CpuVariant::Cmos65C02 => {
+            let _garbage = bus.read_u8(addr);
+        }
+    }
+}
+
 trait WriteOperation {
     fn write(&self, cpu: &Cpu) -> u8;
     fn instruction(&self) -> Instruction;
 }
 
+// The unofficial "SH" stores (SHX, SHY, AHX, and TAS) AND the value they
+// write with one more than the high byte of the base address. See:
+// - https://wiki.nesdev.com/w/index.php/CPU_unofficial_opcodes
+trait UnstableWriteOperation {
+    fn write(&self, cpu: &Cpu, addr_base_hi: u8) -> u8;
+    fn instruction(&self) -> Instruction;
+}
+
 trait BranchOperation {
     fn branch(&self, cpu: &Cpu) -> bool;
     fn instruction(&self) -> Instruction;
@@ -1360,15 +1932,16 @@ trait StackPullOperation {
 }
 
 fn implied<'a>(
-    nes: &'a Nes<impl NesIo>,
+    cpu: &'a Cpu,
+    bus: &'a impl Bus,
     op: impl ImpliedOperation + 'a,
 ) -> impl Generator<Yield = CpuStep, Return = Op> + 'a {
     move || {
-        let _opcode = Cpu::pc_fetch_inc(nes);
+        let _opcode = cpu.pc_fetch_inc(bus);
         yield CpuStep::Cycle;
 
-        let _garbage = Cpu::pc_fetch(nes);
-        op.operate(&nes.cpu);
+        let _garbage = cpu.pc_fetch(bus);
+        op.operate(cpu);
         yield CpuStep::Cycle;
 
         Op {
@@ -1378,31 +1951,128 @@ fn implied<'a>(
     }
 }
 
-fn brk<'a>(nes: &'a Nes<impl NesIo>) -> impl Generator<Yield = CpuStep, Return = Op> + 'a {
+// JAM (also known as KIL) locks up the CPU: it stops fetching
+// instructions (and stops responding to interrupts) until it's reset
+fn jam<'a>(cpu: &'a Cpu, bus: &'a impl Bus) -> impl Generator<Yield = CpuStep, Return = Op> + 'a {
     move || {
-        let _opcode = Cpu::pc_fetch_inc(nes);
+        let _opcode = cpu.pc_fetch_inc(bus);
         yield CpuStep::Cycle;
 
-        let _garbage = Cpu::pc_fetch_inc(nes);
+        while !cpu.reset.get() {
+            let _garbage = bus.read_u8(0xFFFF);
+            yield CpuStep::Cycle;
+        }
+
+        // The interrupt lines kept getting polled while the CPU was stuck,
+        // so forget those polls to make sure the reset runs first
+        cpu.latest_poll.set(None);
+        cpu.penultimate_poll.set(None);
+
+        Op {
+            instruction: Instruction::UnofficialJam,
+            arg: OpArg::Implied,
+        }
+    }
+}
+
+// The hardware interrupt sequence, which is shared by /RESET, /NMI, and
+// /IRQ. It works like BRK, except that the opcode fetch is turned into a
+// dummy read (so the PC isn't incremented) and the pushed status has `B`
+// cleared. See:
+// - https://wiki.nesdev.com/w/index.php/CPU_interrupts
+fn interrupt_sequence<'a>(
+    cpu: &'a Cpu,
+    bus: &'a impl Bus,
+    interrupt: Interrupt,
+) -> impl Generator<Yield = CpuStep, Return = ()> + 'a {
+    move || {
+        // During a reset, writes to the stack are suppressed, but S is
+        // still decremented
+        let push_u8 = move |value: u8| match interrupt {
+            Interrupt::Reset => cpu.dec_s(),
+            Interrupt::Nmi | Interrupt::Irq => cpu.push_u8(bus, value),
+        };
+
+        let _garbage = cpu.pc_fetch(bus);
+        yield CpuStep::Cycle;
+
+        let _garbage = cpu.pc_fetch(bus);
         yield CpuStep::Cycle;
 
-        let pc = nes.cpu.pc.get();
-        nes.push_u8(((pc & 0xFF00) >> 8) as u8);
+        let pc = cpu.pc.get();
+        push_u8(((pc & 0xFF00) >> 8) as u8);
         yield CpuStep::Cycle;
 
-        nes.push_u8((pc & 0x00FF) as u8);
+        push_u8((pc & 0x00FF) as u8);
         yield CpuStep::Cycle;
 
-        let p = nes.cpu.p.get().bits | 0b_0011_0000;
-        nes.push_u8(p);
+        // An NMI that arrives before the status is pushed hijacks an IRQ,
+        // so the CPU jumps to the NMI handler instead
+        let p = (cpu.p.get() - CpuFlags::B) | CpuFlags::U;
+        push_u8(p.bits);
+        let vector = match interrupt {
+            Interrupt::Reset => 0xFFFC,
+            Interrupt::Nmi | Interrupt::Irq if cpu.nmi.get() => {
+                cpu.nmi.set(false);
+                0xFFFA
+            }
+            Interrupt::Nmi => 0xFFFA,
+            Interrupt::Irq => 0xFFFE,
+        };
+        yield CpuStep::Cycle;
+
+        let pc_lo = bus.read_u8(vector);
+        cpu.set_flags(CpuFlags::I, true);
+        if cpu.variant == CpuVariant::Cmos65C02 {
+            cpu.set_flags(CpuFlags::D, false);
+        }
+        yield CpuStep::Cycle;
+
+        let pc_hi = bus.read_u8(vector + 1);
+        let pc = u16_from(pc_lo, pc_hi);
+        cpu.pc.set(pc);
         yield CpuStep::Cycle;
+    }
+}
 
-        let pc_hi = nes.read_u8(0xFFFE);
+fn brk<'a>(cpu: &'a Cpu, bus: &'a impl Bus) -> impl Generator<Yield = CpuStep, Return = Op> + 'a {
+    move || {
+        let _opcode = cpu.pc_fetch_inc(bus);
         yield CpuStep::Cycle;
 
-        let pc_lo = nes.read_u8(0xFFFF);
-        let pc = u16_from(pc_hi, pc_lo);
-        nes.cpu.pc.set(pc);
+        let _garbage = cpu.pc_fetch_inc(bus);
+        yield CpuStep::Cycle;
+
+        let pc = cpu.pc.get();
+        cpu.push_u8(bus, ((pc & 0xFF00) >> 8) as u8);
+        yield CpuStep::Cycle;
+
+        cpu.push_u8(bus, (pc & 0x00FF) as u8);
+        yield CpuStep::Cycle;
+
+        // An NMI that arrives before the status is pushed hijacks the BRK:
+        // the status is still pushed with `B` set, but the CPU jumps to the
+        // NMI handler instead
+        let p = cpu.p.get() | CpuFlags::B | CpuFlags::U;
+        cpu.push_u8(bus, p.bits);
+        let vector = if cpu.nmi.get() {
+            cpu.nmi.set(false);
+            0xFFFA
+        } else {
+            0xFFFE
+        };
+        yield CpuStep::Cycle;
+
+        let pc_lo = bus.read_u8(vector);
+        cpu.set_flags(CpuFlags::I, true);
+        if cpu.variant == CpuVariant::Cmos65C02 {
+            cpu.set_flags(CpuFlags::D, false);
+        }
+        yield CpuStep::Cycle;
+
+        let pc_hi = bus.read_u8(vector + 1);
+        let pc = u16_from(pc_lo, pc_hi);
+        cpu.pc.set(pc);
         yield CpuStep::Cycle;
 
         Op {
@@ -1413,18 +2083,19 @@ fn brk<'a>(nes: &'a Nes<impl NesIo>) -> impl Generator<Yield = CpuStep, Return =
 }
 
 fn accum_modify<'a>(
-    nes: &'a Nes<impl NesIo>,
+    cpu: &'a Cpu,
+    bus: &'a impl Bus,
     op: impl ModifyOperation + 'a,
 ) -> impl Generator<Yield = CpuStep, Return = Op> + 'a {
     move || {
-        let _opcode = Cpu::pc_fetch_inc(nes);
+        let _opcode = cpu.pc_fetch_inc(bus);
         yield CpuStep::Cycle;
 
-        let _garbage = Cpu::pc_fetch(nes);
+        let _garbage = cpu.pc_fetch(bus);
 
-        let value = nes.cpu.a.get();
-        let new_value = op.modify(&nes.cpu, value);
-        nes.cpu.a.set(new_value);
+        let value = cpu.a.get();
+        let new_value = op.modify(cpu, value);
+        cpu.a.set(new_value);
         yield CpuStep::Cycle;
 
         Op {
@@ -1435,15 +2106,16 @@ fn accum_modify<'a>(
 }
 
 fn imm_read<'a>(
-    nes: &'a Nes<impl NesIo>,
+    cpu: &'a Cpu,
+    bus: &'a impl Bus,
     op: impl ReadOperation + 'a,
 ) -> impl Generator<Yield = CpuStep, Return = Op> + 'a {
     move || {
-        let _opcode = Cpu::pc_fetch_inc(nes);
+        let _opcode = cpu.pc_fetch_inc(bus);
         yield CpuStep::Cycle;
 
-        let value = Cpu::pc_fetch_inc(nes);
-        op.read(&nes.cpu, value);
+        let value = cpu.pc_fetch_inc(bus);
+        op.read(cpu, value);
         yield CpuStep::Cycle;
 
         Op {
@@ -1454,20 +2126,21 @@ fn imm_read<'a>(
 }
 
 fn zero_read<'a>(
-    nes: &'a Nes<impl NesIo>,
+    cpu: &'a Cpu,
+    bus: &'a impl Bus,
     op: impl ReadOperation + 'a,
 ) -> impl Generator<Yield = CpuStep, Return = Op> + 'a {
     move || {
-        let _opcode = Cpu::pc_fetch_inc(nes);
+        let _opcode = cpu.pc_fetch_inc(bus);
         yield CpuStep::Cycle;
 
-        let zero_page = Cpu::pc_fetch_inc(nes);
+        let zero_page = cpu.pc_fetch_inc(bus);
         yield CpuStep::Cycle;
 
         let addr = zero_page as u16;
-        let value = nes.read_u8(addr);
+        let value = bus.read_u8(addr);
 
-        op.read(&nes.cpu, value);
+        op.read(cpu, value);
         yield CpuStep::Cycle;
 
         Op {
@@ -1478,25 +2151,26 @@ fn zero_read<'a>(
 }
 
 fn zero_modify<'a>(
-    nes: &'a Nes<impl NesIo>,
+    cpu: &'a Cpu,
+    bus: &'a impl Bus,
     op: impl ModifyOperation + 'a,
 ) -> impl Generator<Yield = CpuStep, Return = Op> + 'a {
     move || {
-        let _opcode = Cpu::pc_fetch_inc(nes);
+        let _opcode = cpu.pc_fetch_inc(bus);
         yield CpuStep::Cycle;
 
-        let zero_page = Cpu::pc_fetch_inc(nes);
+        let zero_page = cpu.pc_fetch_inc(bus);
         yield CpuStep::Cycle;
 
         let addr = zero_page as u16;
-        let value = nes.read_u8(addr);
+        let value = bus.read_u8(addr);
         yield CpuStep::Cycle;
 
-        nes.write_u8(addr, value);
-        let new_value = op.modify(&nes.cpu, value);
+        modify_dummy_access(cpu, bus, addr, value);
+        let new_value = op.modify(cpu, value);
         yield CpuStep::Cycle;
 
-        nes.write_u8(addr, new_value);
+        bus.write_u8(addr, new_value);
         yield CpuStep::Cycle;
 
         Op {
@@ -1507,20 +2181,21 @@ fn zero_modify<'a>(
 }
 
 fn zero_write<'a>(
-    nes: &'a Nes<impl NesIo>,
+    cpu: &'a Cpu,
+    bus: &'a impl Bus,
     op: impl WriteOperation + 'a,
 ) -> impl Generator<Yield = CpuStep, Return = Op> + 'a {
     move || {
-        let _opcode = Cpu::pc_fetch_inc(nes);
+        let _opcode = cpu.pc_fetch_inc(bus);
         yield CpuStep::Cycle;
 
-        let zero_page = Cpu::pc_fetch_inc(nes);
+        let zero_page = cpu.pc_fetch_inc(bus);
         yield CpuStep::Cycle;
 
         let addr = zero_page as u16;
 
-        let value = op.write(&nes.cpu);
-        nes.write_u8(addr, value);
+        let value = op.write(cpu);
+        bus.write_u8(addr, value);
         yield CpuStep::Cycle;
 
         Op {
@@ -1531,22 +2206,23 @@ fn zero_write<'a>(
 }
 
 fn zero_x_read<'a>(
-    nes: &'a Nes<impl NesIo>,
+    cpu: &'a Cpu,
+    bus: &'a impl Bus,
     op: impl ReadOperation + 'a,
 ) -> impl Generator<Yield = CpuStep, Return = Op> + 'a {
     move || {
-        let _opcode = Cpu::pc_fetch_inc(nes);
+        let _opcode = cpu.pc_fetch_inc(bus);
         yield CpuStep::Cycle;
 
-        let zero_page_base = Cpu::pc_fetch_inc(nes);
+        let zero_page_base = cpu.pc_fetch_inc(bus);
         yield CpuStep::Cycle;
 
-        let _garbage = nes.read_u8(zero_page_base as u16);
-        let addr = zero_page_base.wrapping_add(nes.cpu.x.get()) as u16;
+        let _garbage = bus.read_u8(zero_page_base as u16);
+        let addr = zero_page_base.wrapping_add(cpu.x.get()) as u16;
         yield CpuStep::Cycle;
 
-        let value = nes.read_u8(addr);
-        op.read(&nes.cpu, value);
+        let value = bus.read_u8(addr);
+        op.read(cpu, value);
         yield CpuStep::Cycle;
 
         Op {
@@ -1557,28 +2233,29 @@ fn zero_x_read<'a>(
 }
 
 fn zero_x_modify<'a>(
-    nes: &'a Nes<impl NesIo>,
+    cpu: &'a Cpu,
+    bus: &'a impl Bus,
     op: impl ModifyOperation + 'a,
 ) -> impl Generator<Yield = CpuStep, Return = Op> + 'a {
     move || {
-        let _opcode = Cpu::pc_fetch_inc(nes);
+        let _opcode = cpu.pc_fetch_inc(bus);
         yield CpuStep::Cycle;
 
-        let zero_page_base = Cpu::pc_fetch_inc(nes);
+        let zero_page_base = cpu.pc_fetch_inc(bus);
         yield CpuStep::Cycle;
 
-        let _garbage = nes.read_u8(zero_page_base as u16);
-        let addr = zero_page_base.wrapping_add(nes.cpu.x.get()) as u16;
+        let _garbage = bus.read_u8(zero_page_base as u16);
+        let addr = zero_page_base.wrapping_add(cpu.x.get()) as u16;
         yield CpuStep::Cycle;
 
-        let value = nes.read_u8(addr);
+        let value = bus.read_u8(addr);
         yield CpuStep::Cycle;
 
-        nes.write_u8(addr, value);
-        let new_value = op.modify(&nes.cpu, value);
+        modify_dummy_access(cpu, bus, addr, value);
+        let new_value = op.modify(cpu, value);
         yield CpuStep::Cycle;
 
-        nes.write_u8(addr, new_value);
+        bus.write_u8(addr, new_value);
         yield CpuStep::Cycle;
 
         Op {
@@ -1589,22 +2266,23 @@ fn zero_x_modify<'a>(
 }
 
 fn zero_x_write<'a>(
-    nes: &'a Nes<impl NesIo>,
+    cpu: &'a Cpu,
+    bus: &'a impl Bus,
     op: impl WriteOperation + 'a,
 ) -> impl Generator<Yield = CpuStep, Return = Op> + 'a {
     move || {
-        let _opcode = Cpu::pc_fetch_inc(nes);
+        let _opcode = cpu.pc_fetch_inc(bus);
         yield CpuStep::Cycle;
 
-        let zero_page_base = Cpu::pc_fetch_inc(nes);
+        let zero_page_base = cpu.pc_fetch_inc(bus);
         yield CpuStep::Cycle;
 
-        let _garbage = nes.read_u8(zero_page_base as u16);
-        let addr = zero_page_base.wrapping_add(nes.cpu.x.get()) as u16;
+        let _garbage = bus.read_u8(zero_page_base as u16);
+        let addr = zero_page_base.wrapping_add(cpu.x.get()) as u16;
         yield CpuStep::Cycle;
 
-        let value = op.write(&nes.cpu);
-        nes.write_u8(addr, value);
+        let value = op.write(cpu);
+        bus.write_u8(addr, value);
         yield CpuStep::Cycle;
 
         Op {
@@ -1615,22 +2293,23 @@ fn zero_x_write<'a>(
 }
 
 fn zero_y_read<'a>(
-    nes: &'a Nes<impl NesIo>,
+    cpu: &'a Cpu,
+    bus: &'a impl Bus,
     op: impl ReadOperation + 'a,
 ) -> impl Generator<Yield = CpuStep, Return = Op> + 'a {
     move || {
-        let _opcode = Cpu::pc_fetch_inc(nes);
+        let _opcode = cpu.pc_fetch_inc(bus);
         yield CpuStep::Cycle;
 
-        let zero_page_base = Cpu::pc_fetch_inc(nes);
+        let zero_page_base = cpu.pc_fetch_inc(bus);
         yield CpuStep::Cycle;
 
-        let _garbage = nes.read_u8(zero_page_base as u16);
-        let addr = zero_page_base.wrapping_add(nes.cpu.y.get()) as u16;
+        let _garbage = bus.read_u8(zero_page_base as u16);
+        let addr = zero_page_base.wrapping_add(cpu.y.get()) as u16;
         yield CpuStep::Cycle;
 
-        let value = nes.read_u8(addr);
-        op.read(&nes.cpu, value);
+        let value = bus.read_u8(addr);
+        op.read(cpu, value);
         yield CpuStep::Cycle;
 
         Op {
@@ -1641,22 +2320,23 @@ fn zero_y_read<'a>(
 }
 
 fn zero_y_write<'a>(
-    nes: &'a Nes<impl NesIo>,
+    cpu: &'a Cpu,
+    bus: &'a impl Bus,
     op: impl WriteOperation + 'a,
 ) -> impl Generator<Yield = CpuStep, Return = Op> + 'a {
     move || {
-        let _opcode = Cpu::pc_fetch_inc(nes);
+        let _opcode = cpu.pc_fetch_inc(bus);
         yield CpuStep::Cycle;
 
-        let zero_page_base = Cpu::pc_fetch_inc(nes);
+        let zero_page_base = cpu.pc_fetch_inc(bus);
         yield CpuStep::Cycle;
 
-        let _garbage = nes.read_u8(zero_page_base as u16);
-        let addr = zero_page_base.wrapping_add(nes.cpu.y.get()) as u16;
+        let _garbage = bus.read_u8(zero_page_base as u16);
+        let addr = zero_page_base.wrapping_add(cpu.y.get()) as u16;
         yield CpuStep::Cycle;
 
-        let value = op.write(&nes.cpu);
-        nes.write_u8(addr, value);
+        let value = op.write(cpu);
+        bus.write_u8(addr, value);
         yield CpuStep::Cycle;
 
         Op {
@@ -1667,23 +2347,24 @@ fn zero_y_write<'a>(
 }
 
 fn abs_read<'a>(
-    nes: &'a Nes<impl NesIo>,
+    cpu: &'a Cpu,
+    bus: &'a impl Bus,
     op: impl ReadOperation + 'a,
 ) -> impl Generator<Yield = CpuStep, Return = Op> + 'a {
     move || {
-        let _opcode = Cpu::pc_fetch_inc(nes);
+        let _opcode = cpu.pc_fetch_inc(bus);
         yield CpuStep::Cycle;
 
-        let addr_lo = Cpu::pc_fetch_inc(nes);
+        let addr_lo = cpu.pc_fetch_inc(bus);
         yield CpuStep::Cycle;
 
-        let addr_hi = Cpu::pc_fetch_inc(nes);
+        let addr_hi = cpu.pc_fetch_inc(bus);
         yield CpuStep::Cycle;
 
         let addr = u16_from(addr_lo, addr_hi);
-        let value = nes.read_u8(addr);
+        let value = bus.read_u8(addr);
 
-        op.read(&nes.cpu, value);
+        op.read(cpu, value);
         yield CpuStep::Cycle;
 
         Op {
@@ -1694,28 +2375,30 @@ fn abs_read<'a>(
 }
 
 fn abs_modify<'a>(
-    nes: &'a Nes<impl NesIo>,
+    cpu: &'a Cpu,
+    bus: &'a impl Bus,
     op: impl ModifyOperation + 'a,
 ) -> impl Generator<Yield = CpuStep, Return = Op> + 'a {
     move || {
-        let _opcode = Cpu::pc_fetch_inc(nes);
+        let _opcode = cpu.pc_fetch_inc(bus);
         yield CpuStep::Cycle;
 
-        let addr_lo = Cpu::pc_fetch_inc(nes);
+        let addr_lo = cpu.pc_fetch_inc(bus);
         yield CpuStep::Cycle;
 
-        let addr_hi = Cpu::pc_fetch_inc(nes);
+        let addr_hi = cpu.pc_fetch_inc(bus);
         yield CpuStep::Cycle;
 
         let addr = u16_from(addr_lo, addr_hi);
-        let value = nes.read_u8(addr);
+        let value = bus.read_u8(addr);
         yield CpuStep::Cycle;
 
-        nes.write_u8(addr, value);
-        let new_value = op.modify(&nes.cpu, value);
+        modify_dummy_access(cpu, bus, addr, value);
+        let new_value = op.modify(cpu, value);
         yield CpuStep::Cycle;
 
-        nes.write_u8(addr, new_value);
+        bus.write_u8(addr, new_value);
+        yield CpuStep::Cycle;
 
         Op {
             instruction: op.instruction(),
@@ -1725,23 +2408,24 @@ fn abs_modify<'a>(
 }
 
 fn abs_write<'a>(
-    nes: &'a Nes<impl NesIo>,
+    cpu: &'a Cpu,
+    bus: &'a impl Bus,
     op: impl WriteOperation + 'a,
 ) -> impl Generator<Yield = CpuStep, Return = Op> + 'a {
     move || {
-        let _opcode = Cpu::pc_fetch_inc(nes);
+        let _opcode = cpu.pc_fetch_inc(bus);
         yield CpuStep::Cycle;
 
-        let addr_lo = Cpu::pc_fetch_inc(nes);
+        let addr_lo = cpu.pc_fetch_inc(bus);
         yield CpuStep::Cycle;
 
-        let addr_hi = Cpu::pc_fetch_inc(nes);
+        let addr_hi = cpu.pc_fetch_inc(bus);
         yield CpuStep::Cycle;
 
         let addr = u16_from(addr_lo, addr_hi);
-        let value = op.write(&nes.cpu);
+        let value = op.write(cpu);
 
-        nes.write_u8(addr, value);
+        bus.write_u8(addr, value);
         yield CpuStep::Cycle;
 
         Op {
@@ -1751,17 +2435,20 @@ fn abs_write<'a>(
     }
 }
 
-fn abs_jmp<'a>(nes: &'a Nes<impl NesIo>) -> impl Generator<Yield = CpuStep, Return = Op> + 'a {
+fn abs_jmp<'a>(
+    cpu: &'a Cpu,
+    bus: &'a impl Bus,
+) -> impl Generator<Yield = CpuStep, Return = Op> + 'a {
     move || {
-        let _opcode = Cpu::pc_fetch_inc(nes);
+        let _opcode = cpu.pc_fetch_inc(bus);
         yield CpuStep::Cycle;
 
-        let pc_lo = Cpu::pc_fetch_inc(nes);
+        let pc_lo = cpu.pc_fetch_inc(bus);
         yield CpuStep::Cycle;
 
-        let pc_hi = Cpu::pc_fetch(nes);
+        let pc_hi = cpu.pc_fetch(bus);
         let addr = u16_from(pc_lo, pc_hi);
-        nes.cpu.pc.set(addr);
+        cpu.pc.set(addr);
         yield CpuStep::Cycle;
 
         Op {
@@ -1771,25 +2458,42 @@ fn abs_jmp<'a>(nes: &'a Nes<impl NesIo>) -> impl Generator<Yield = CpuStep, Retu
     }
 }
 
-fn ind_jmp<'a>(nes: &'a Nes<impl NesIo>) -> impl Generator<Yield = CpuStep, Return = Op> + 'a {
+fn ind_jmp<'a>(
+    cpu: &'a Cpu,
+    bus: &'a impl Bus,
+) -> impl Generator<Yield = CpuStep, Return = Op> + 'a {
     move || {
-        let _opcode = Cpu::pc_fetch_inc(nes);
+        let _opcode = cpu.pc_fetch_inc(bus);
         yield CpuStep::Cycle;
 
-        let target_lo = Cpu::pc_fetch_inc(nes);
+        let target_lo = cpu.pc_fetch_inc(bus);
         yield CpuStep::Cycle;
 
-        let target_hi = Cpu::pc_fetch(nes);
+        let target_hi = cpu.pc_fetch(bus);
         let addr_lo = u16_from(target_lo, target_hi);
-        let addr_hi = u16_from(target_lo.wrapping_add(1), target_hi);
         yield CpuStep::Cycle;
 
-        let pc_lo = nes.read_u8(addr_lo);
+        // The NMOS 6502 doesn't carry into the high byte of the target
+        // address, so JMP ($xxFF) reads its high byte from $xx00. The
+        // 65C02 fixes this, but takes an extra cycle
+        let addr_hi = match cpu.variant {
+            CpuVariant::Ricoh2A03 | CpuVariant::Nmos6502 => {
+                u16_from(target_lo.wrapping_add(1), target_hi)
+            }
+            CpuVariant::Cmos65C02 => {
+                let _garbage = cpu.pc_fetch(bus);
+                yield CpuStep::Cycle;
+
+                addr_lo.wrapping_add(1)
+            }
+        };
+
+        let pc_lo = bus.read_u8(addr_lo);
         yield CpuStep::Cycle;
 
-        let pc_hi = nes.read_u8(addr_hi);
+        let pc_hi = bus.read_u8(addr_hi);
         let addr = u16_from(pc_lo, pc_hi);
-        nes.cpu.pc.set(addr);
+        cpu.pc.set(addr);
         yield CpuStep::Cycle;
 
         Op {
@@ -1801,42 +2505,79 @@ fn ind_jmp<'a>(nes: &'a Nes<impl NesIo>) -> impl Generator<Yield = CpuStep, Retu
     }
 }
 
+fn abs_x_ind_jmp<'a>(
+    cpu: &'a Cpu,
+    bus: &'a impl Bus,
+) -> impl Generator<Yield = CpuStep, Return = Op> + 'a {
+    move || {
+        let _opcode = cpu.pc_fetch_inc(bus);
+        yield CpuStep::Cycle;
+
+        let target_lo = cpu.pc_fetch_inc(bus);
+        yield CpuStep::Cycle;
+
+        let target_hi = cpu.pc_fetch(bus);
+        let target_addr_base = u16_from(target_lo, target_hi);
+        yield CpuStep::Cycle;
+
+        let _garbage = cpu.pc_fetch(bus);
+        let x = cpu.x.get();
+        let target_addr = target_addr_base.wrapping_add(x as u16);
+        yield CpuStep::Cycle;
+
+        let pc_lo = bus.read_u8(target_addr);
+        yield CpuStep::Cycle;
+
+        let pc_hi = bus.read_u8(target_addr.wrapping_add(1));
+        let addr = u16_from(pc_lo, pc_hi);
+        cpu.pc.set(addr);
+        yield CpuStep::Cycle;
+
+        Op {
+            instruction: Instruction::Jmp,
+            arg: OpArg::AbsXInd { target_addr_base },
+        }
+    }
+}
+
 fn abs_x_read<'a>(
-    nes: &'a Nes<impl NesIo>,
+    cpu: &'a Cpu,
+    bus: &'a impl Bus,
     op: impl ReadOperation + 'a,
 ) -> impl Generator<Yield = CpuStep, Return = Op> + 'a {
     move || {
-        let _opcode = Cpu::pc_fetch_inc(nes);
+        let _opcode = cpu.pc_fetch_inc(bus);
         yield CpuStep::Cycle;
 
-        let addr_lo = Cpu::pc_fetch_inc(nes);
+        let addr_lo = cpu.pc_fetch_inc(bus);
         yield CpuStep::Cycle;
 
-        let addr_hi = Cpu::pc_fetch_inc(nes);
+        let addr_hi = cpu.pc_fetch_inc(bus);
         let addr_base = u16_from(addr_lo, addr_hi);
-        let x = nes.cpu.x.get();
+        let x = cpu.x.get();
         let addr_lo_x = addr_lo.wrapping_add(x);
         yield CpuStep::Cycle;
 
         // Speculatively load from memory based on the
         // incomplete address calculation
         let addr_unfixed = u16_from(addr_lo_x, addr_hi);
-        let value_unfixed = nes.read_u8(addr_unfixed);
+        let value_unfixed = bus.read_u8(addr_unfixed);
 
         // Calculate the actual address to use
         let addr = addr_base.wrapping_add(x as u16);
         let value = if addr == addr_unfixed {
             value_unfixed
         } else {
-            // If the speculative load was incorrect, read the from
-            // the correct address (at the cost of an extra cycle)
-            let fixed_value = nes.read_u8(addr);
+            // If the speculative load was incorrect, it was a dummy read,
+            // so read from the correct address on the next cycle
             yield CpuStep::Cycle;
 
-            fixed_value
+            bus.read_u8(addr)
         };
 
-        op.read(&nes.cpu, value);
+        op.read(cpu, value);
+        yield CpuStep::Cycle;
+
         Op {
             instruction: op.instruction(),
             arg: OpArg::AbsX { addr_base },
@@ -1845,35 +2586,49 @@ fn abs_x_read<'a>(
 }
 
 fn abs_x_modify<'a>(
-    nes: &'a Nes<impl NesIo>,
+    cpu: &'a Cpu,
+    bus: &'a impl Bus,
     op: impl ModifyOperation + 'a,
 ) -> impl Generator<Yield = CpuStep, Return = Op> + 'a {
     move || {
-        let _opcode = Cpu::pc_fetch_inc(nes);
+        let _opcode = cpu.pc_fetch_inc(bus);
         yield CpuStep::Cycle;
 
-        let addr_lo = Cpu::pc_fetch_inc(nes);
+        let addr_lo = cpu.pc_fetch_inc(bus);
         yield CpuStep::Cycle;
 
-        let addr_hi = Cpu::pc_fetch_inc(nes);
+        let addr_hi = cpu.pc_fetch_inc(bus);
         let addr_base = u16_from(addr_lo, addr_hi);
-        let x = nes.cpu.x.get();
+        let x = cpu.x.get();
         let addr_lo_x = addr_lo.wrapping_add(x);
         yield CpuStep::Cycle;
 
         let addr_unfixed = u16_from(addr_lo_x, addr_hi);
-        let _garbage = nes.read_u8(addr_unfixed);
-        yield CpuStep::Cycle;
-
         let addr = addr_base.wrapping_add(x as u16);
-        let value = nes.read_u8(addr);
+
+        // The 65C02 skips the dummy read when the page doesn't change,
+        // except for INC and DEC
+        let skip_dummy_read = match cpu.variant {
+            CpuVariant::Ricoh2A03 | CpuVariant::Nmos6502 => false,
+            CpuVariant::Cmos65C02 => {
+                let instruction = op.instruction();
+                let is_inc_dec = instruction == Instruction::Inc || instruction == Instruction::Dec;
+                addr == addr_unfixed && !is_inc_dec
+            }
+        };
+        if !skip_dummy_read {
+            let _garbage = bus.read_u8(addr_unfixed);
+            yield CpuStep::Cycle;
+        }
+
+        let value = bus.read_u8(addr);
         yield CpuStep::Cycle;
 
-        nes.write_u8(addr, value);
-        let new_value = op.modify(&nes.cpu, value);
+        modify_dummy_access(cpu, bus, addr, value);
+        let new_value = op.modify(cpu, value);
         yield CpuStep::Cycle;
 
-        nes.write_u8(addr, new_value);
+        bus.write_u8(addr, new_value);
         yield CpuStep::Cycle;
 
         Op {
@@ -1884,29 +2639,64 @@ fn abs_x_modify<'a>(
 }
 
 fn abs_x_write<'a>(
-    nes: &'a Nes<impl NesIo>,
+    cpu: &'a Cpu,
+    bus: &'a impl Bus,
     op: impl WriteOperation + 'a,
 ) -> impl Generator<Yield = CpuStep, Return = Op> + 'a {
     move || {
-        let _opcode = Cpu::pc_fetch_inc(nes);
+        let _opcode = cpu.pc_fetch_inc(bus);
         yield CpuStep::Cycle;
 
-        let addr_lo = Cpu::pc_fetch_inc(nes);
+        let addr_lo = cpu.pc_fetch_inc(bus);
         yield CpuStep::Cycle;
 
-        let addr_hi = Cpu::pc_fetch_inc(nes);
+        let addr_hi = cpu.pc_fetch_inc(bus);
         let addr_base = u16_from(addr_lo, addr_hi);
-        let x = nes.cpu.x.get();
+        let x = cpu.x.get();
         let addr_lo_x = addr_lo.wrapping_add(x);
         yield CpuStep::Cycle;
 
         let addr_unfixed = u16_from(addr_lo_x, addr_hi);
-        let _garbage = nes.read_u8(addr_unfixed);
+        let _garbage = bus.read_u8(addr_unfixed);
         yield CpuStep::Cycle;
 
         let addr = addr_base.wrapping_add(x as u16);
-        let new_value = op.write(&nes.cpu);
-        nes.write_u8(addr, new_value);
+        let new_value = op.write(cpu);
+        bus.write_u8(addr, new_value);
+        yield CpuStep::Cycle;
+
+        Op {
+            instruction: op.instruction(),
+            arg: OpArg::AbsX { addr_base },
+        }
+    }
+}
+
+fn abs_x_unstable_write<'a>(
+    cpu: &'a Cpu,
+    bus: &'a impl Bus,
+    op: impl UnstableWriteOperation + 'a,
+) -> impl Generator<Yield = CpuStep, Return = Op> + 'a {
+    move || {
+        let _opcode = cpu.pc_fetch_inc(bus);
+        yield CpuStep::Cycle;
+
+        let addr_lo = cpu.pc_fetch_inc(bus);
+        yield CpuStep::Cycle;
+
+        let addr_hi = cpu.pc_fetch_inc(bus);
+        let addr_base = u16_from(addr_lo, addr_hi);
+        let x = cpu.x.get();
+        let addr_lo_x = addr_lo.wrapping_add(x);
+        yield CpuStep::Cycle;
+
+        let addr_unfixed = u16_from(addr_lo_x, addr_hi);
+        let _garbage = bus.read_u8(addr_unfixed);
+        yield CpuStep::Cycle;
+
+        let new_value = op.write(cpu, addr_hi);
+        let addr = unstable_write_addr(addr_base, x, new_value);
+        bus.write_u8(addr, new_value);
         yield CpuStep::Cycle;
 
         Op {
@@ -1917,41 +2707,43 @@ fn abs_x_write<'a>(
 }
 
 fn abs_y_read<'a>(
-    nes: &'a Nes<impl NesIo>,
+    cpu: &'a Cpu,
+    bus: &'a impl Bus,
     op: impl ReadOperation + 'a,
 ) -> impl Generator<Yield = CpuStep, Return = Op> + 'a {
     move || {
-        let _opcode = Cpu::pc_fetch_inc(nes);
+        let _opcode = cpu.pc_fetch_inc(bus);
         yield CpuStep::Cycle;
 
-        let addr_lo = Cpu::pc_fetch_inc(nes);
+        let addr_lo = cpu.pc_fetch_inc(bus);
         yield CpuStep::Cycle;
 
-        let addr_hi = Cpu::pc_fetch_inc(nes);
+        let addr_hi = cpu.pc_fetch_inc(bus);
         let addr_base = u16_from(addr_lo, addr_hi);
-        let y = nes.cpu.y.get();
+        let y = cpu.y.get();
         let addr_lo_y = addr_lo.wrapping_add(y);
         yield CpuStep::Cycle;
 
         // Speculatively load from memory based on the
         // incomplete address calculation
         let addr_unfixed = u16_from(addr_lo_y, addr_hi);
-        let value_unfixed = nes.read_u8(addr_unfixed);
+        let value_unfixed = bus.read_u8(addr_unfixed);
 
         // Calculate the actual address to use
         let addr = addr_base.wrapping_add(y as u16);
         let value = if addr == addr_unfixed {
             value_unfixed
         } else {
-            // If the speculative load was incorrect, read the from
-            // the correct address (at the cost of an extra cycle)
-            let fixed_value = nes.read_u8(addr);
+            // If the speculative load was incorrect, it was a dummy read,
+            // so read from the correct address on the next cycle
             yield CpuStep::Cycle;
 
-            fixed_value
+            bus.read_u8(addr)
         };
 
-        op.read(&nes.cpu, value);
+        op.read(cpu, value);
+        yield CpuStep::Cycle;
+
         Op {
             instruction: op.instruction(),
             arg: OpArg::AbsY { addr_base },
@@ -1960,35 +2752,36 @@ fn abs_y_read<'a>(
 }
 
 fn abs_y_modify<'a>(
-    nes: &'a Nes<impl NesIo>,
+    cpu: &'a Cpu,
+    bus: &'a impl Bus,
     op: impl ModifyOperation + 'a,
 ) -> impl Generator<Yield = CpuStep, Return = Op> + 'a {
     move || {
-        let _opcode = Cpu::pc_fetch_inc(nes);
+        let _opcode = cpu.pc_fetch_inc(bus);
         yield CpuStep::Cycle;
 
-        let addr_lo = Cpu::pc_fetch_inc(nes);
+        let addr_lo = cpu.pc_fetch_inc(bus);
         yield CpuStep::Cycle;
 
-        let addr_hi = Cpu::pc_fetch_inc(nes);
+        let addr_hi = cpu.pc_fetch_inc(bus);
         let addr_base = u16_from(addr_lo, addr_hi);
-        let y = nes.cpu.y.get();
+        let y = cpu.y.get();
         let addr_lo_y = addr_lo.wrapping_add(y);
         yield CpuStep::Cycle;
 
         let addr_unfixed = u16_from(addr_lo_y, addr_hi);
-        let _garbage = nes.read_u8(addr_unfixed);
+        let _garbage = bus.read_u8(addr_unfixed);
         yield CpuStep::Cycle;
 
         let addr = addr_base.wrapping_add(y as u16);
-        let value = nes.read_u8(addr);
+        let value = bus.read_u8(addr);
         yield CpuStep::Cycle;
 
-        nes.write_u8(addr, value);
-        let new_value = op.modify(&nes.cpu, value);
+        bus.write_u8(addr, value);
+        let new_value = op.modify(cpu, value);
         yield CpuStep::Cycle;
 
-        nes.write_u8(addr, new_value);
+        bus.write_u8(addr, new_value);
         yield CpuStep::Cycle;
 
         Op {
@@ -1999,29 +2792,64 @@ fn abs_y_modify<'a>(
 }
 
 fn abs_y_write<'a>(
-    nes: &'a Nes<impl NesIo>,
+    cpu: &'a Cpu,
+    bus: &'a impl Bus,
     op: impl WriteOperation + 'a,
 ) -> impl Generator<Yield = CpuStep, Return = Op> + 'a {
     move || {
-        let _opcode = Cpu::pc_fetch_inc(nes);
+        let _opcode = cpu.pc_fetch_inc(bus);
         yield CpuStep::Cycle;
 
-        let addr_lo = Cpu::pc_fetch_inc(nes);
+        let addr_lo = cpu.pc_fetch_inc(bus);
         yield CpuStep::Cycle;
 
-        let addr_hi = Cpu::pc_fetch_inc(nes);
+        let addr_hi = cpu.pc_fetch_inc(bus);
         let addr_base = u16_from(addr_lo, addr_hi);
-        let y = nes.cpu.y.get();
+        let y = cpu.y.get();
         let addr_lo_y = addr_lo.wrapping_add(y);
         yield CpuStep::Cycle;
 
         let addr_unfixed = u16_from(addr_lo_y, addr_hi);
-        let _garbage = nes.read_u8(addr_unfixed);
+        let _garbage = bus.read_u8(addr_unfixed);
         yield CpuStep::Cycle;
 
         let addr = addr_base.wrapping_add(y as u16);
-        let new_value = op.write(&nes.cpu);
-        nes.write_u8(addr, new_value);
+        let new_value = op.write(cpu);
+        bus.write_u8(addr, new_value);
+        yield CpuStep::Cycle;
+
+        Op {
+            instruction: op.instruction(),
+            arg: OpArg::AbsY { addr_base },
+        }
+    }
+}
+
+fn abs_y_unstable_write<'a>(
+    cpu: &'a Cpu,
+    bus: &'a impl Bus,
+    op: impl UnstableWriteOperation + 'a,
+) -> impl Generator<Yield = CpuStep, Return = Op> + 'a {
+    move || {
+        let _opcode = cpu.pc_fetch_inc(bus);
+        yield CpuStep::Cycle;
+
+        let addr_lo = cpu.pc_fetch_inc(bus);
+        yield CpuStep::Cycle;
+
+        let addr_hi = cpu.pc_fetch_inc(bus);
+        let addr_base = u16_from(addr_lo, addr_hi);
+        let y = cpu.y.get();
+        let addr_lo_y = addr_lo.wrapping_add(y);
+        yield CpuStep::Cycle;
+
+        let addr_unfixed = u16_from(addr_lo_y, addr_hi);
+        let _garbage = bus.read_u8(addr_unfixed);
+        yield CpuStep::Cycle;
+
+        let new_value = op.write(cpu, addr_hi);
+        let addr = unstable_write_addr(addr_base, y, new_value);
+        bus.write_u8(addr, new_value);
         yield CpuStep::Cycle;
 
         Op {
@@ -2032,30 +2860,31 @@ fn abs_y_write<'a>(
 }
 
 fn ind_x_read<'a>(
-    nes: &'a Nes<impl NesIo>,
+    cpu: &'a Cpu,
+    bus: &'a impl Bus,
     op: impl ReadOperation + 'a,
 ) -> impl Generator<Yield = CpuStep, Return = Op> + 'a {
     move || {
-        let _opcode = Cpu::pc_fetch_inc(nes);
+        let _opcode = cpu.pc_fetch_inc(bus);
         yield CpuStep::Cycle;
 
-        let target_addr_base = Cpu::pc_fetch_inc(nes);
+        let target_addr_base = cpu.pc_fetch_inc(bus);
         yield CpuStep::Cycle;
 
-        let _garbage = nes.read_u8(target_addr_base as u16);
-        let x = nes.cpu.x.get();
+        let _garbage = bus.read_u8(target_addr_base as u16);
+        let x = cpu.x.get();
         let target_addr = target_addr_base.wrapping_add(x);
         yield CpuStep::Cycle;
 
-        let addr_lo = nes.read_u8(target_addr as u16);
+        let addr_lo = bus.read_u8(target_addr as u16);
         yield CpuStep::Cycle;
 
-        let addr_hi = nes.read_u8(target_addr.wrapping_add(1) as u16);
+        let addr_hi = bus.read_u8(target_addr.wrapping_add(1) as u16);
         yield CpuStep::Cycle;
 
         let addr = u16_from(addr_lo, addr_hi);
-        let value = nes.read_u8(addr);
-        op.read(&nes.cpu, value);
+        let value = bus.read_u8(addr);
+        op.read(cpu, value);
         yield CpuStep::Cycle;
 
         Op {
@@ -2066,36 +2895,37 @@ fn ind_x_read<'a>(
 }
 
 fn ind_x_modify<'a>(
-    nes: &'a Nes<impl NesIo>,
+    cpu: &'a Cpu,
+    bus: &'a impl Bus,
     op: impl ModifyOperation + 'a,
 ) -> impl Generator<Yield = CpuStep, Return = Op> + 'a {
     move || {
-        let _opcode = Cpu::pc_fetch_inc(nes);
+        let _opcode = cpu.pc_fetch_inc(bus);
         yield CpuStep::Cycle;
 
-        let target_addr_base = Cpu::pc_fetch_inc(nes);
+        let target_addr_base = cpu.pc_fetch_inc(bus);
         yield CpuStep::Cycle;
 
-        let _garbage = nes.read_u8(target_addr_base as u16);
-        let x = nes.cpu.x.get();
+        let _garbage = bus.read_u8(target_addr_base as u16);
+        let x = cpu.x.get();
         let target_addr = target_addr_base.wrapping_add(x);
         yield CpuStep::Cycle;
 
-        let addr_lo = nes.read_u8(target_addr as u16);
+        let addr_lo = bus.read_u8(target_addr as u16);
         yield CpuStep::Cycle;
 
-        let addr_hi = nes.read_u8(target_addr.wrapping_add(1) as u16);
+        let addr_hi = bus.read_u8(target_addr.wrapping_add(1) as u16);
         yield CpuStep::Cycle;
 
         let addr = u16_from(addr_lo, addr_hi);
-        let value = nes.read_u8(addr);
+        let value = bus.read_u8(addr);
         yield CpuStep::Cycle;
 
-        nes.write_u8(addr, value);
+        bus.write_u8(addr, value);
         yield CpuStep::Cycle;
 
-        let new_value = op.modify(&nes.cpu, value);
-        nes.write_u8(addr, new_value);
+        let new_value = op.modify(cpu, value);
+        bus.write_u8(addr, new_value);
         yield CpuStep::Cycle;
 
         Op {
@@ -2106,30 +2936,31 @@ fn ind_x_modify<'a>(
 }
 
 fn ind_x_write<'a>(
-    nes: &'a Nes<impl NesIo>,
+    cpu: &'a Cpu,
+    bus: &'a impl Bus,
     op: impl WriteOperation + 'a,
 ) -> impl Generator<Yield = CpuStep, Return = Op> + 'a {
     move || {
-        let _opcode = Cpu::pc_fetch_inc(nes);
+        let _opcode = cpu.pc_fetch_inc(bus);
         yield CpuStep::Cycle;
 
-        let target_addr_base = Cpu::pc_fetch_inc(nes);
+        let target_addr_base = cpu.pc_fetch_inc(bus);
         yield CpuStep::Cycle;
 
-        let _garbage = nes.read_u8(target_addr_base as u16);
-        let x = nes.cpu.x.get();
+        let _garbage = bus.read_u8(target_addr_base as u16);
+        let x = cpu.x.get();
         let target_addr = target_addr_base.wrapping_add(x);
         yield CpuStep::Cycle;
 
-        let addr_lo = nes.read_u8(target_addr as u16);
+        let addr_lo = bus.read_u8(target_addr as u16);
         yield CpuStep::Cycle;
 
-        let addr_hi = nes.read_u8(target_addr.wrapping_add(1) as u16);
+        let addr_hi = bus.read_u8(target_addr.wrapping_add(1) as u16);
         yield CpuStep::Cycle;
 
         let addr = u16_from(addr_lo, addr_hi);
-        let value = op.write(&nes.cpu);
-        nes.write_u8(addr, value);
+        let value = op.write(cpu);
+        bus.write_u8(addr, value);
         yield CpuStep::Cycle;
 
         Op {
@@ -2140,31 +2971,31 @@ fn ind_x_write<'a>(
 }
 
 fn ind_y_read<'a>(
-    nes: &'a Nes<impl NesIo>,
+    cpu: &'a Cpu,
+    bus: &'a impl Bus,
     op: impl ReadOperation + 'a,
 ) -> impl Generator<Yield = CpuStep, Return = Op> + 'a {
     move || {
-        let _opcode = Cpu::pc_fetch_inc(&nes);
+        let _opcode = cpu.pc_fetch_inc(bus);
         yield CpuStep::Cycle;
 
-        let target_addr_base = Cpu::pc_fetch_inc(&nes);
+        let target_addr_base = cpu.pc_fetch_inc(bus);
         let target_addr_base_lo = target_addr_base as u16;
         let target_addr_base_hi = target_addr_base.wrapping_add(1) as u16;
         yield CpuStep::Cycle;
 
-        let addr_base_lo = nes.read_u8(target_addr_base_lo);
+        let addr_base_lo = bus.read_u8(target_addr_base_lo);
         yield CpuStep::Cycle;
 
-        let addr_base_hi = nes.read_u8(target_addr_base_hi);
-        let y = nes.cpu.y.get();
+        let addr_base_hi = bus.read_u8(target_addr_base_hi);
+        let y = cpu.y.get();
 
         yield CpuStep::Cycle;
 
         // Speculatively load from memory based on the
         // incomplete address calculation
         let addr_unfixed = u16_from(addr_base_lo.wrapping_add(y), addr_base_hi);
-        let value_unfixed = nes.read_u8(addr_unfixed);
-        yield CpuStep::Cycle;
+        let value_unfixed = bus.read_u8(addr_unfixed);
 
         // Calculate the actual address to use
         let addr_base = u16_from(addr_base_lo, addr_base_hi);
@@ -2172,15 +3003,14 @@ fn ind_y_read<'a>(
         let value = if addr == addr_unfixed {
             value_unfixed
         } else {
-            // If the speculative load was incorrect, read the from
-            // the correct address (at the cost of an extra cycle)
-            let fixed_value = nes.read_u8(addr);
+            // If the speculative load was incorrect, it was a dummy read,
+            // so read from the correct address on the next cycle
             yield CpuStep::Cycle;
 
-            fixed_value
+            bus.read_u8(addr)
         };
 
-        op.read(&nes.cpu, value);
+        op.read(cpu, value);
         yield CpuStep::Cycle;
 
         Op {
@@ -2191,40 +3021,41 @@ fn ind_y_read<'a>(
 }
 
 fn ind_y_modify<'a>(
-    nes: &'a Nes<impl NesIo>,
+    cpu: &'a Cpu,
+    bus: &'a impl Bus,
     op: impl ModifyOperation + 'a,
 ) -> impl Generator<Yield = CpuStep, Return = Op> + 'a {
     move || {
-        let _opcode = Cpu::pc_fetch_inc(&nes);
+        let _opcode = cpu.pc_fetch_inc(bus);
         yield CpuStep::Cycle;
 
-        let target_addr_base = Cpu::pc_fetch_inc(&nes);
+        let target_addr_base = cpu.pc_fetch_inc(bus);
         let target_addr_base_lo = target_addr_base as u16;
         let target_addr_base_hi = target_addr_base.wrapping_add(1) as u16;
         yield CpuStep::Cycle;
 
-        let addr_base_lo = nes.read_u8(target_addr_base_lo);
+        let addr_base_lo = bus.read_u8(target_addr_base_lo);
         yield CpuStep::Cycle;
 
-        let addr_base_hi = nes.read_u8(target_addr_base_hi);
-        let y = nes.cpu.y.get();
+        let addr_base_hi = bus.read_u8(target_addr_base_hi);
+        let y = cpu.y.get();
 
         let addr_unfixed = u16_from(addr_base_lo.wrapping_add(y), addr_base_hi);
         yield CpuStep::Cycle;
 
-        let _garbage = nes.read_u8(addr_unfixed);
+        let _garbage = bus.read_u8(addr_unfixed);
         yield CpuStep::Cycle;
 
         let addr_base = u16_from(addr_base_lo, addr_base_hi);
         let addr = addr_base.wrapping_add(y as u16);
-        let value = nes.read_u8(addr);
+        let value = bus.read_u8(addr);
         yield CpuStep::Cycle;
 
-        nes.write_u8(addr, value);
-        let new_value = op.modify(&nes.cpu, value);
+        bus.write_u8(addr, value);
+        let new_value = op.modify(cpu, value);
         yield CpuStep::Cycle;
 
-        nes.write_u8(addr, new_value);
+        bus.write_u8(addr, new_value);
         yield CpuStep::Cycle;
 
         Op {
@@ -2235,36 +3066,37 @@ fn ind_y_modify<'a>(
 }
 
 fn ind_y_write<'a>(
-    nes: &'a Nes<impl NesIo>,
+    cpu: &'a Cpu,
+    bus: &'a impl Bus,
     op: impl WriteOperation + 'a,
 ) -> impl Generator<Yield = CpuStep, Return = Op> + 'a {
     move || {
-        let _opcode = Cpu::pc_fetch_inc(&nes);
+        let _opcode = cpu.pc_fetch_inc(bus);
         yield CpuStep::Cycle;
 
-        let target_addr_base = Cpu::pc_fetch_inc(&nes);
+        let target_addr_base = cpu.pc_fetch_inc(bus);
         let target_addr_base_lo = target_addr_base as u16;
         let target_addr_base_hi = target_addr_base.wrapping_add(1) as u16;
         yield CpuStep::Cycle;
 
-        let addr_base_lo = nes.read_u8(target_addr_base_lo);
+        let addr_base_lo = bus.read_u8(target_addr_base_lo);
         yield CpuStep::Cycle;
 
-        let addr_base_hi = nes.read_u8(target_addr_base_hi);
-        let y = nes.cpu.y.get();
+        let addr_base_hi = bus.read_u8(target_addr_base_hi);
+        let y = cpu.y.get();
 
         let addr_unfixed = u16_from(addr_base_lo.wrapping_add(y), addr_base_hi);
         yield CpuStep::Cycle;
 
-        let _garbage = nes.read_u8(addr_unfixed);
+        let _garbage = bus.read_u8(addr_unfixed);
         yield CpuStep::Cycle;
 
         let addr_base = u16_from(addr_base_lo, addr_base_hi);
         let addr = addr_base.wrapping_add(y as u16);
 
-        let value = op.write(&nes.cpu);
+        let value = op.write(cpu);
 
-        nes.write_u8(addr, value);
+        bus.write_u8(addr, value);
         yield CpuStep::Cycle;
 
         Op {
@@ -2274,33 +3106,150 @@ fn ind_y_write<'a>(
     }
 }
 
+fn ind_y_unstable_write<'a>(
+    cpu: &'a Cpu,
+    bus: &'a impl Bus,
+    op: impl UnstableWriteOperation + 'a,
+) -> impl Generator<Yield = CpuStep, Return = Op> + 'a {
+    move || {
+        let _opcode = cpu.pc_fetch_inc(bus);
+        yield CpuStep::Cycle;
+
+        let target_addr_base = cpu.pc_fetch_inc(bus);
+        let target_addr_base_lo = target_addr_base as u16;
+        let target_addr_base_hi = target_addr_base.wrapping_add(1) as u16;
+        yield CpuStep::Cycle;
+
+        let addr_base_lo = bus.read_u8(target_addr_base_lo);
+        yield CpuStep::Cycle;
+
+        let addr_base_hi = bus.read_u8(target_addr_base_hi);
+        let y = cpu.y.get();
+
+        let addr_unfixed = u16_from(addr_base_lo.wrapping_add(y), addr_base_hi);
+        yield CpuStep::Cycle;
+
+        let _garbage = bus.read_u8(addr_unfixed);
+        yield CpuStep::Cycle;
+
+        let addr_base = u16_from(addr_base_lo, addr_base_hi);
+        let value = op.write(cpu, addr_base_hi);
+        let addr = unstable_write_addr(addr_base, y, value);
+
+        bus.write_u8(addr, value);
+        yield CpuStep::Cycle;
+
+        Op {
+            instruction: op.instruction(),
+            arg: OpArg::IndY { target_addr_base },
+        }
+    }
+}
+
+// When an unstable write's indexing crosses a page boundary, the high byte
+// of the target address gets replaced with the value being written
+fn unstable_write_addr(addr_base: u16, index: u8, value: u8) -> u16 {
+    let addr = addr_base.wrapping_add(index as u16);
+    if (addr & 0xFF00) == (addr_base & 0xFF00) {
+        addr
+    } else {
+        u16_from((addr & 0x00FF) as u8, value)
+    }
+}
+
+fn zero_ind_read<'a>(
+    cpu: &'a Cpu,
+    bus: &'a impl Bus,
+    op: impl ReadOperation + 'a,
+) -> impl Generator<Yield = CpuStep, Return = Op> + 'a {
+    move || {
+        let _opcode = cpu.pc_fetch_inc(bus);
+        yield CpuStep::Cycle;
+
+        let target_addr = cpu.pc_fetch_inc(bus);
+        yield CpuStep::Cycle;
+
+        let addr_lo = bus.read_u8(target_addr as u16);
+        yield CpuStep::Cycle;
+
+        let addr_hi = bus.read_u8(target_addr.wrapping_add(1) as u16);
+        yield CpuStep::Cycle;
+
+        let addr = u16_from(addr_lo, addr_hi);
+        let value = bus.read_u8(addr);
+        op.read(cpu, value);
+        yield CpuStep::Cycle;
+
+        Op {
+            instruction: op.instruction(),
+            arg: OpArg::ZeroInd { target_addr },
+        }
+    }
+}
+
+fn zero_ind_write<'a>(
+    cpu: &'a Cpu,
+    bus: &'a impl Bus,
+    op: impl WriteOperation + 'a,
+) -> impl Generator<Yield = CpuStep, Return = Op> + 'a {
+    move || {
+        let _opcode = cpu.pc_fetch_inc(bus);
+        yield CpuStep::Cycle;
+
+        let target_addr = cpu.pc_fetch_inc(bus);
+        yield CpuStep::Cycle;
+
+        let addr_lo = bus.read_u8(target_addr as u16);
+        yield CpuStep::Cycle;
+
+        let addr_hi = bus.read_u8(target_addr.wrapping_add(1) as u16);
+        yield CpuStep::Cycle;
+
+        let addr = u16_from(addr_lo, addr_hi);
+        let value = op.write(cpu);
+        bus.write_u8(addr, value);
+        yield CpuStep::Cycle;
+
+        Op {
+            instruction: op.instruction(),
+            arg: OpArg::ZeroInd { target_addr },
+        }
+    }
+}
+
 fn branch<'a>(
-    nes: &'a Nes<impl NesIo>,
+    cpu: &'a Cpu,
+    bus: &'a impl Bus,
     op: impl BranchOperation + 'a,
 ) -> impl Generator<Yield = CpuStep, Return = Op> + 'a {
     move || {
-        let _opcode = Cpu::pc_fetch_inc(&nes);
+        let _opcode = cpu.pc_fetch_inc(bus);
         yield CpuStep::Cycle;
 
-        let addr_offset = Cpu::pc_fetch_inc(&nes) as i8;
+        let addr_offset = cpu.pc_fetch_inc(bus) as i8;
         yield CpuStep::Cycle;
 
-        if op.branch(&nes.cpu) {
-            let pc = nes.cpu.pc.get();
+        if op.branch(cpu) {
+            let _garbage = cpu.pc_fetch(bus);
+
+            let pc = cpu.pc.get();
             let pc_hi = (pc >> 8) as u8;
             let pc_lo = (pc & 0x00FF) as u8;
 
             let pc_lo_offset = pc_lo.wrapping_add(addr_offset as u8);
-            let unfixed_addr = u16_from(pc_hi, pc_lo_offset);
+            let unfixed_addr = u16_from(pc_lo_offset, pc_hi);
 
             let addr = pc.wrapping_add(addr_offset as u16);
-            nes.cpu.pc.set(addr);
+            yield CpuStep::Cycle;
 
+            // When the branch crosses a page, the high byte of the PC gets
+            // fixed on an extra cycle, after a dummy read from the wrong page
             if addr != unfixed_addr {
-                // yield CpuStep::Cycle;
+                let _garbage = bus.read_u8(unfixed_addr);
+                yield CpuStep::Cycle;
             }
 
-            yield CpuStep::Cycle;
+            cpu.pc.set(addr);
         }
 
         Op {
@@ -2311,19 +3260,20 @@ fn branch<'a>(
 }
 
 fn stack_push<'a>(
-    nes: &'a Nes<impl NesIo>,
+    cpu: &'a Cpu,
+    bus: &'a impl Bus,
     op: impl StackPushOperation + 'a,
 ) -> impl Generator<Yield = CpuStep, Return = Op> + 'a {
     move || {
-        let _opcode = Cpu::pc_fetch_inc(nes);
+        let _opcode = cpu.pc_fetch_inc(bus);
         yield CpuStep::Cycle;
 
-        let _garbage = Cpu::pc_fetch(nes);
+        let _garbage = cpu.pc_fetch(bus);
         yield CpuStep::Cycle;
 
-        let value = op.push(&nes.cpu);
-        nes.write_u8(nes.cpu.stack_addr(), value);
-        nes.cpu.dec_s();
+        let value = op.push(cpu);
+        cpu.push_u8(bus, value);
+        yield CpuStep::Cycle;
 
         Op {
             instruction: op.instruction(),
@@ -2333,21 +3283,23 @@ fn stack_push<'a>(
 }
 
 fn stack_pull<'a>(
-    nes: &'a Nes<impl NesIo>,
+    cpu: &'a Cpu,
+    bus: &'a impl Bus,
     op: impl StackPullOperation + 'a,
 ) -> impl Generator<Yield = CpuStep, Return = Op> + 'a {
     move || {
-        let _opcode = Cpu::pc_fetch_inc(nes);
+        let _opcode = cpu.pc_fetch_inc(bus);
         yield CpuStep::Cycle;
 
-        let _garbage = Cpu::pc_fetch(nes);
+        let _garbage = cpu.pc_fetch(bus);
         yield CpuStep::Cycle;
 
-        nes.cpu.inc_s();
+        let _garbage = bus.read_u8(cpu.stack_addr());
+        cpu.inc_s();
         yield CpuStep::Cycle;
 
-        let value = nes.read_u8(nes.cpu.stack_addr());
-        op.pull(&nes.cpu, value);
+        let value = bus.read_u8(cpu.stack_addr());
+        op.pull(cpu, value);
         yield CpuStep::Cycle;
 
         Op {
@@ -2357,33 +3309,31 @@ fn stack_pull<'a>(
     }
 }
 
-fn jsr<'a>(nes: &'a Nes<impl NesIo>) -> impl Generator<Yield = CpuStep, Return = Op> + 'a {
+fn jsr<'a>(cpu: &'a Cpu, bus: &'a impl Bus) -> impl Generator<Yield = CpuStep, Return = Op> + 'a {
     move || {
-        let ret_pc = nes.cpu.pc.get().wrapping_add(3);
+        let ret_pc = cpu.pc.get().wrapping_add(3);
         let push_pc = ret_pc.wrapping_sub(1);
         let push_pc_hi = (push_pc >> 8) as u8;
         let push_pc_lo = (push_pc & 0x00FF) as u8;
 
-        let _opcode = Cpu::pc_fetch_inc(nes);
+        let _opcode = cpu.pc_fetch_inc(bus);
         yield CpuStep::Cycle;
 
-        let addr_lo = Cpu::pc_fetch_inc(nes);
+        let addr_lo = cpu.pc_fetch_inc(bus);
         yield CpuStep::Cycle;
 
-        // No-op
+        let _garbage = bus.read_u8(cpu.stack_addr());
         yield CpuStep::Cycle;
 
-        nes.write_u8(nes.cpu.stack_addr(), push_pc_hi);
-        nes.cpu.dec_s();
+        cpu.push_u8(bus, push_pc_hi);
         yield CpuStep::Cycle;
 
-        nes.write_u8(nes.cpu.stack_addr(), push_pc_lo);
-        nes.cpu.dec_s();
+        cpu.push_u8(bus, push_pc_lo);
         yield CpuStep::Cycle;
 
-        let addr_hi = Cpu::pc_fetch(nes);
+        let addr_hi = cpu.pc_fetch(bus);
         let addr = u16_from(addr_lo, addr_hi);
-        nes.cpu.pc.set(addr);
+        cpu.pc.set(addr);
         yield CpuStep::Cycle;
 
         Op {
@@ -2393,28 +3343,29 @@ fn jsr<'a>(nes: &'a Nes<impl NesIo>) -> impl Generator<Yield = CpuStep, Return =
     }
 }
 
-fn rti<'a>(nes: &'a Nes<impl NesIo>) -> impl Generator<Yield = CpuStep, Return = Op> + 'a {
+fn rti<'a>(cpu: &'a Cpu, bus: &'a impl Bus) -> impl Generator<Yield = CpuStep, Return = Op> + 'a {
     move || {
-        let _opcode = Cpu::pc_fetch_inc(nes);
+        let _opcode = cpu.pc_fetch_inc(bus);
         yield CpuStep::Cycle;
 
-        let _garbage = Cpu::pc_fetch(nes);
+        let _garbage = cpu.pc_fetch(bus);
         yield CpuStep::Cycle;
 
-        nes.cpu.inc_s();
+        let _garbage = bus.read_u8(cpu.stack_addr());
+        cpu.inc_s();
         yield CpuStep::Cycle;
 
-        let p = nes.read_u8(nes.cpu.stack_addr());
-        nes.cpu.p.set(CpuFlags::from_bits_truncate(p));
-        nes.cpu.inc_s();
+        let p = bus.read_u8(cpu.stack_addr());
+        cpu.p.set(CpuFlags::from_bits_truncate(p));
+        cpu.inc_s();
         yield CpuStep::Cycle;
 
-        let pc_lo = nes.read_u8(nes.cpu.stack_addr());
-        nes.cpu.inc_s();
+        let pc_lo = bus.read_u8(cpu.stack_addr());
+        cpu.inc_s();
         yield CpuStep::Cycle;
 
-        let pc_hi = nes.read_u8(nes.cpu.stack_addr());
-        nes.cpu.pc.set(u16_from(pc_lo, pc_hi));
+        let pc_hi = bus.read_u8(cpu.stack_addr());
+        cpu.pc.set(u16_from(pc_lo, pc_hi));
         yield CpuStep::Cycle;
 
         Op {
@@ -2424,26 +3375,27 @@ fn rti<'a>(nes: &'a Nes<impl NesIo>) -> impl Generator<Yield = CpuStep, Return =
     }
 }
 
-fn rts<'a>(nes: &'a Nes<impl NesIo>) -> impl Generator<Yield = CpuStep, Return = Op> + 'a {
+fn rts<'a>(cpu: &'a Cpu, bus: &'a impl Bus) -> impl Generator<Yield = CpuStep, Return = Op> + 'a {
     move || {
-        let _opcode = Cpu::pc_fetch_inc(nes);
+        let _opcode = cpu.pc_fetch_inc(bus);
         yield CpuStep::Cycle;
 
-        let _garbage = Cpu::pc_fetch(nes);
+        let _garbage = cpu.pc_fetch(bus);
         yield CpuStep::Cycle;
 
-        nes.cpu.inc_s();
+        let _garbage = bus.read_u8(cpu.stack_addr());
+        cpu.inc_s();
         yield CpuStep::Cycle;
 
-        let pull_pc_lo = nes.read_u8(nes.cpu.stack_addr());
-        nes.cpu.inc_s();
+        let pull_pc_lo = bus.read_u8(cpu.stack_addr());
+        cpu.inc_s();
         yield CpuStep::Cycle;
 
-        let pull_pc_hi = nes.read_u8(nes.cpu.stack_addr());
-        nes.cpu.pc.set(u16_from(pull_pc_lo, pull_pc_hi));
+        let pull_pc_hi = bus.read_u8(cpu.stack_addr());
+        cpu.pc.set(u16_from(pull_pc_lo, pull_pc_hi));
         yield CpuStep::Cycle;
 
-        nes.cpu.pc_inc();
+        let _garbage = cpu.pc_fetch_inc(bus);
         yield CpuStep::Cycle;
 
         Op {
@@ -2456,11 +3408,16 @@ fn rts<'a>(nes: &'a Nes<impl NesIo>) -> impl Generator<Yield = CpuStep, Return =
 struct AdcOperation;
 impl ReadOperation for AdcOperation {
     fn read(&self, cpu: &Cpu, value: u8) {
-        let AdcResult { a, c, z, v, n } = adc(AdcArg {
+        let arg = AdcArg {
             a: cpu.a.get(),
             c: cpu.contains_flags(CpuFlags::C),
             value,
-        });
+        };
+        let AdcResult { a, c, z, v, n } = if cpu.is_decimal() {
+            adc_decimal(arg, cpu.variant)
+        } else {
+            adc(arg)
+        };
 
         cpu.a.set(a);
         cpu.set_flags(CpuFlags::C, c);
@@ -2553,6 +3510,19 @@ impl ReadOperation for BitOperation {
     }
 }
 
+// BIT #imm (65C02 only) has nothing to copy `N` and `V` from, so it only
+// sets `Z`
+struct BitImmOperation;
+impl ReadOperation for BitImmOperation {
+    fn read(&self, cpu: &Cpu, value: u8) {
+        cpu.set_flags(CpuFlags::Z, cpu.a.get() & value == 0);
+    }
+
+    fn instruction(&self) -> Instruction {
+        Instruction::Bit
+    }
+}
+
 struct BmiOperation;
 impl BranchOperation for BmiOperation {
     fn branch(&self, cpu: &Cpu) -> bool {
@@ -2586,6 +3556,17 @@ impl BranchOperation for BplOperation {
     }
 }
 
+struct BraOperation;
+impl BranchOperation for BraOperation {
+    fn branch(&self, _cpu: &Cpu) -> bool {
+        true
+    }
+
+    fn instruction(&self) -> Instruction {
+        Instruction::Bra
+    }
+}
+
 struct BvcOperation;
 impl BranchOperation for BvcOperation {
     fn branch(&self, cpu: &Cpu) -> bool {
@@ -2909,6 +3890,28 @@ impl StackPushOperation for PhpOperation {
     }
 }
 
+struct PhxOperation;
+impl StackPushOperation for PhxOperation {
+    fn push(&self, cpu: &Cpu) -> u8 {
+        cpu.x.get()
+    }
+
+    fn instruction(&self) -> Instruction {
+        Instruction::Phx
+    }
+}
+
+struct PhyOperation;
+impl StackPushOperation for PhyOperation {
+    fn push(&self, cpu: &Cpu) -> u8 {
+        cpu.y.get()
+    }
+
+    fn instruction(&self) -> Instruction {
+        Instruction::Phy
+    }
+}
+
 struct PlaOperation;
 impl StackPullOperation for PlaOperation {
     fn pull(&self, cpu: &Cpu, value: u8) {
@@ -2933,6 +3936,32 @@ impl StackPullOperation for PlpOperation {
     }
 }
 
+struct PlxOperation;
+impl StackPullOperation for PlxOperation {
+    fn pull(&self, cpu: &Cpu, value: u8) {
+        cpu.x.set(value);
+        cpu.set_flags(CpuFlags::Z, value == 0);
+        cpu.set_flags(CpuFlags::N, (value & 0b_1000_0000) != 0);
+    }
+
+    fn instruction(&self) -> Instruction {
+        Instruction::Plx
+    }
+}
+
+struct PlyOperation;
+impl StackPullOperation for PlyOperation {
+    fn pull(&self, cpu: &Cpu, value: u8) {
+        cpu.y.set(value);
+        cpu.set_flags(CpuFlags::Z, value == 0);
+        cpu.set_flags(CpuFlags::N, (value & 0b_1000_0000) != 0);
+    }
+
+    fn instruction(&self) -> Instruction {
+        Instruction::Ply
+    }
+}
+
 struct RolOperation;
 impl ModifyOperation for RolOperation {
     fn modify(&self, cpu: &Cpu, value: u8) -> u8 {
@@ -2984,11 +4013,16 @@ impl ReadOperation for SbcOperation {
     fn read(&self, cpu: &Cpu, value: u8) {
         // Subtract-with-carry is the same as add-with-carry after
         // performing a bitwise not on `value`
-        let AdcResult { a, c, z, v, n } = adc(AdcArg {
+        let arg = AdcArg {
             a: cpu.a.get(),
             c: cpu.contains_flags(CpuFlags::C),
             value: !value,
-        });
+        };
+        let AdcResult { a, c, z, v, n } = if cpu.is_decimal() {
+            sbc_decimal(arg, cpu.variant)
+        } else {
+            adc(arg)
+        };
 
         cpu.a.set(a);
         cpu.set_flags(CpuFlags::C, c);
@@ -3068,6 +4102,17 @@ impl WriteOperation for StyOperation {
     }
 }
 
+struct StzOperation;
+impl WriteOperation for StzOperation {
+    fn write(&self, _cpu: &Cpu) -> u8 {
+        0
+    }
+
+    fn instruction(&self) -> Instruction {
+        Instruction::Stz
+    }
+}
+
 struct TaxOperation;
 impl ImpliedOperation for TaxOperation {
     fn operate(&self, cpu: &Cpu) {
@@ -3098,6 +4143,36 @@ impl ImpliedOperation for TayOperation {
     }
 }
 
+// TRB and TSB set `Z` like BIT, then clear (or set) the bits of `A` in
+// memory
+struct TrbOperation;
+impl ModifyOperation for TrbOperation {
+    fn modify(&self, cpu: &Cpu, value: u8) -> u8 {
+        let a = cpu.a.get();
+        cpu.set_flags(CpuFlags::Z, a & value == 0);
+
+        value & !a
+    }
+
+    fn instruction(&self) -> Instruction {
+        Instruction::Trb
+    }
+}
+
+struct TsbOperation;
+impl ModifyOperation for TsbOperation {
+    fn modify(&self, cpu: &Cpu, value: u8) -> u8 {
+        let a = cpu.a.get();
+        cpu.set_flags(CpuFlags::Z, a & value == 0);
+
+        value | a
+    }
+
+    fn instruction(&self) -> Instruction {
+        Instruction::Tsb
+    }
+}
+
 struct TsxOperation;
 impl ImpliedOperation for TsxOperation {
     fn operate(&self, cpu: &Cpu) {
@@ -3156,6 +4231,18 @@ impl ImpliedOperation for TyaOperation {
     }
 }
 
+struct UnofficialAhxOperation;
+impl UnstableWriteOperation for UnofficialAhxOperation {
+    fn write(&self, cpu: &Cpu, addr_base_hi: u8) -> u8 {
+        // This operation writes (A & X & (H + 1))
+        cpu.a.get() & cpu.x.get() & addr_base_hi.wrapping_add(1)
+    }
+
+    fn instruction(&self) -> Instruction {
+        Instruction::UnofficialAhx
+    }
+}
+
 struct UnofficialAncOperation;
 impl ReadOperation for UnofficialAncOperation {
     fn read(&self, cpu: &Cpu, value: u8) {
@@ -3275,6 +4362,24 @@ impl ModifyOperation for UnofficialIscOperation {
     }
 }
 
+struct UnofficialLasOperation;
+impl ReadOperation for UnofficialLasOperation {
+    fn read(&self, cpu: &Cpu, value: u8) {
+        // This operation sets A, X, and S to (value & S)
+        let result = value & cpu.s.get();
+
+        cpu.a.set(result);
+        cpu.x.set(result);
+        cpu.s.set(result);
+        cpu.set_flags(CpuFlags::Z, result == 0);
+        cpu.set_flags(CpuFlags::N, (result & 0b_1000_0000) != 0);
+    }
+
+    fn instruction(&self) -> Instruction {
+        Instruction::UnofficialLas
+    }
+}
+
 struct UnofficialLaxOperation;
 impl ReadOperation for UnofficialLaxOperation {
     fn read(&self, cpu: &Cpu, value: u8) {
@@ -3288,6 +4393,23 @@ impl ReadOperation for UnofficialLaxOperation {
     }
 }
 
+struct UnofficialLxaOperation;
+impl ReadOperation for UnofficialLxaOperation {
+    fn read(&self, cpu: &Cpu, value: u8) {
+        // This operation sets A and X to ((A | magic) & value), where the
+        // "magic" constant varies between chips. Using $FF makes it act
+        // like an immediate LAX, which is what blargg's tests expect
+        let result = (cpu.a.get() | 0xFF) & value;
+
+        LdaOperation.read(cpu, result);
+        LdxOperation.read(cpu, result);
+    }
+
+    fn instruction(&self) -> Instruction {
+        Instruction::UnofficialLxa
+    }
+}
+
 struct UnofficialNopOperation;
 
 impl ImpliedOperation for UnofficialNopOperation {
@@ -3361,10 +4483,10 @@ impl ReadOperation for UnofficialSbcOperation {
 }
 
 struct UnofficialShxOperation;
-impl ModifyOperation for UnofficialShxOperation {
-    fn modify(&self, cpu: &Cpu, value: u8) -> u8 {
-        // This operation writes (X & ((value << 7) + 1))
-        cpu.x.get() & ((value << 7) + 1)
+impl UnstableWriteOperation for UnofficialShxOperation {
+    fn write(&self, cpu: &Cpu, addr_base_hi: u8) -> u8 {
+        // This operation writes (X & (H + 1))
+        cpu.x.get() & addr_base_hi.wrapping_add(1)
     }
 
     fn instruction(&self) -> Instruction {
@@ -3373,10 +4495,10 @@ impl ModifyOperation for UnofficialShxOperation {
 }
 
 struct UnofficialShyOperation;
-impl ModifyOperation for UnofficialShyOperation {
-    fn modify(&self, cpu: &Cpu, value: u8) -> u8 {
-        // This operation writes (Y & ((value << 7) + 1))
-        cpu.y.get() & ((value << 7) + 1)
+impl UnstableWriteOperation for UnofficialShyOperation {
+    fn write(&self, cpu: &Cpu, addr_base_hi: u8) -> u8 {
+        // This operation writes (Y & (H + 1))
+        cpu.y.get() & addr_base_hi.wrapping_add(1)
     }
 
     fn instruction(&self) -> Instruction {
@@ -3414,6 +4536,36 @@ impl ModifyOperation for UnofficialSreOperation {
     }
 }
 
+struct UnofficialTasOperation;
+impl UnstableWriteOperation for UnofficialTasOperation {
+    fn write(&self, cpu: &Cpu, addr_base_hi: u8) -> u8 {
+        // This operation sets S to (A & X), then writes (S & (H + 1))
+        let s = cpu.a.get() & cpu.x.get();
+        cpu.s.set(s);
+
+        s & addr_base_hi.wrapping_add(1)
+    }
+
+    fn instruction(&self) -> Instruction {
+        Instruction::UnofficialTas
+    }
+}
+
+struct UnofficialXaaOperation;
+impl ReadOperation for UnofficialXaaOperation {
+    fn read(&self, cpu: &Cpu, value: u8) {
+        // This operation sets A to ((A | magic) & X & value), where the
+        // "magic" constant varies between chips ($EE is a common value)
+        let result = (cpu.a.get() | 0xEE) & cpu.x.get() & value;
+
+        LdaOperation.read(cpu, result);
+    }
+
+    fn instruction(&self) -> Instruction {
+        Instruction::UnofficialXaa
+    }
+}
+
 struct AdcArg {
     a: u8,
     value: u8,
@@ -3451,6 +4603,93 @@ fn adc(AdcArg { a, value, c }: AdcArg) -> AdcResult {
     AdcResult { a: out, c, z, v, n }
 }
 
+// Decimal mode treats each nibble as a decimal digit. On the NMOS 6502,
+// `Z` still comes from the binary sum, while `N` and `V` come from the sum
+// before the high digit is corrected. The 65C02 sets `N` and `Z` from the
+// decimal result instead (but doesn't take its extra cycle here). See:
+// - http://www.6502.org/tutorials/decimal_mode.html#A
+fn adc_decimal(AdcArg { a, value, c }: AdcArg, variant: CpuVariant) -> AdcResult {
+    let binary = adc(AdcArg { a, value, c });
+
+    let mut lo = (a & 0x0F) as i16 + (value & 0x0F) as i16 + c as i16;
+    if lo >= 0x0A {
+        lo = ((lo + 0x06) & 0x0F) + 0x10;
+    }
+
+    let signed_result = (a & 0xF0) as i8 as i16 + (value & 0xF0) as i8 as i16 + lo;
+    let mut result = (a & 0xF0) as i16 + (value & 0xF0) as i16 + lo;
+    if result >= 0xA0 {
+        result += 0x60;
+    }
+
+    let result = AdcResult {
+        a: result as u8,
+        c: result >= 0x100,
+        z: binary.z,
+        v: signed_result < -128 || signed_result > 127,
+        n: (signed_result & 0b_1000_0000) != 0,
+    };
+
+    match variant {
+        CpuVariant::Ricoh2A03 | CpuVariant::Nmos6502 => result,
+        CpuVariant::Cmos65C02 => with_decimal_nz(result),
+    }
+}
+
+// Like `adc`, this takes the bitwise not of the value to subtract. Only
+// the result is decimal: the flags match binary subtraction, except for
+// `N` and `Z` on the 65C02. The 65C02 also corrects the high digit first,
+// which gives different results for invalid BCD values
+fn sbc_decimal(AdcArg { a, value, c }: AdcArg, variant: CpuVariant) -> AdcResult {
+    let binary = adc(AdcArg { a, value, c });
+    let value = !value;
+
+    let lo = (a & 0x0F) as i16 - (value & 0x0F) as i16 + c as i16 - 1;
+    let result = match variant {
+        CpuVariant::Ricoh2A03 | CpuVariant::Nmos6502 => {
+            let lo = if lo < 0 {
+                ((lo - 0x06) & 0x0F) - 0x10
+            } else {
+                lo
+            };
+
+            let mut result = (a & 0xF0) as i16 - (value & 0xF0) as i16 + lo;
+            if result < 0 {
+                result -= 0x60;
+            }
+            result
+        }
+        CpuVariant::Cmos65C02 => {
+            let mut result = a as i16 - value as i16 + c as i16 - 1;
+            if result < 0 {
+                result -= 0x60;
+            }
+            if lo < 0 {
+                result -= 0x06;
+            }
+            result
+        }
+    };
+
+    let result = AdcResult {
+        a: result as u8,
+        ..binary
+    };
+
+    match variant {
+        CpuVariant::Ricoh2A03 | CpuVariant::Nmos6502 => result,
+        CpuVariant::Cmos65C02 => with_decimal_nz(result),
+    }
+}
+
+fn with_decimal_nz(result: AdcResult) -> AdcResult {
+    AdcResult {
+        z: result.a == 0,
+        n: (result.a & 0b_1000_0000) != 0,
+        ..result
+    }
+}
+
 fn u16_from(lo: u8, hi: u8) -> u16 {
     ((hi as u16) << 8) | (lo as u16)
 }
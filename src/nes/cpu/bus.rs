@@ -0,0 +1,15 @@
+// Everything the CPU is connected to. The CPU only talks to the rest of
+// the system through its bus, so the same core can run inside a `Nes` or
+// against a plain block of memory
+pub trait Bus {
+    fn read_u8(&self, addr: u16) -> u8;
+
+    fn write_u8(&self, addr: u16, value: u8);
+
+    // Read a byte without any side effects, for debugging output
+    fn peek_u8(&self, addr: u16) -> u8;
+
+    // Called at the end of each CPU cycle, after all of the cycle's reads
+    // and writes
+    fn end_cycle(&self) {}
+}
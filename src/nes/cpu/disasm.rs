@@ -0,0 +1,130 @@
+use super::{decode_opcode, CpuVariant, Op, OpArg, OpMode};
+use std::fmt;
+
+// Decode the instructions in `bytes`, where the first byte is located at
+// `base_addr`. Decoding stops at the end of `bytes`, or at an instruction
+// whose operands would run past the end
+pub fn disassemble(bytes: &[u8], base_addr: u16) -> Disassembler<'_> {
+    Disassembler {
+        bytes,
+        base_addr,
+        offset: 0,
+        variant: CpuVariant::Ricoh2A03,
+    }
+}
+
+pub struct Disassembler<'a> {
+    bytes: &'a [u8],
+    base_addr: u16,
+    offset: usize,
+    variant: CpuVariant,
+}
+
+impl<'a> Disassembler<'a> {
+    // Decode opcodes for `variant` instead of the NES's 2A03
+    pub fn with_variant(self, variant: CpuVariant) -> Self {
+        Disassembler { variant, ..self }
+    }
+}
+
+impl<'a> Iterator for Disassembler<'a> {
+    type Item = DisasmOp;
+
+    fn next(&mut self) -> Option<DisasmOp> {
+        let opcode = *self.bytes.get(self.offset)?;
+        let (instruction, mode) = decode_opcode(self.variant, opcode);
+
+        let len = 1 + mode.operand_len();
+        let bytes = self.bytes.get(self.offset..self.offset + len)?;
+        let addr = self.base_addr.wrapping_add(self.offset as u16);
+        self.offset += len;
+
+        let arg = decode_arg(mode, &bytes[1..]);
+        let branch_target = match arg {
+            OpArg::Branch { addr_offset } => {
+                let next_addr = addr.wrapping_add(len as u16);
+                Some(next_addr.wrapping_add(addr_offset as u16))
+            }
+            _ => None,
+        };
+
+        Some(DisasmOp {
+            addr,
+            bytes: bytes.to_vec(),
+            op: Op { instruction, arg },
+            branch_target,
+        })
+    }
+}
+
+// A single decoded instruction, including its opcode and operand bytes
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DisasmOp {
+    pub addr: u16,
+    pub bytes: Vec<u8>,
+    pub op: Op,
+
+    // The address a branch instruction jumps to when taken
+    pub branch_target: Option<u16>,
+}
+
+impl fmt::Display for DisasmOp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let bytes: Vec<_> = self
+            .bytes
+            .iter()
+            .map(|byte| format!("{:02X}", byte))
+            .collect();
+        write!(f, "${:04X}  {:<8}  ", self.addr, bytes.join(" "))?;
+
+        match self.branch_target {
+            Some(target) => write!(f, "{} ${:04X}", self.op.instruction, target),
+            None => write!(f, "{}", self.op),
+        }
+    }
+}
+
+fn decode_arg(mode: OpMode, operands: &[u8]) -> OpArg {
+    let u8_arg = || operands[0];
+    let u16_arg = || u16::from_le_bytes([operands[0], operands[1]]);
+
+    match mode {
+        OpMode::Implied => OpArg::Implied,
+        OpMode::Accum => OpArg::Accum,
+        OpMode::Abs => OpArg::Abs { addr: u16_arg() },
+        OpMode::AbsX => OpArg::AbsX {
+            addr_base: u16_arg(),
+        },
+        OpMode::AbsY => OpArg::AbsY {
+            addr_base: u16_arg(),
+        },
+        OpMode::AbsXInd => OpArg::AbsXInd {
+            target_addr_base: u16_arg(),
+        },
+        OpMode::Branch => OpArg::Branch {
+            addr_offset: u8_arg() as i8,
+        },
+        OpMode::Imm => OpArg::Imm { value: u8_arg() },
+        OpMode::Ind => OpArg::Ind {
+            target_addr: u16_arg(),
+        },
+        OpMode::IndX => OpArg::IndX {
+            target_addr_base: u8_arg(),
+        },
+        OpMode::IndY => OpArg::IndY {
+            target_addr_base: u8_arg(),
+        },
+        OpMode::Zero => OpArg::Zero {
+            zero_page: u8_arg(),
+        },
+        OpMode::ZeroX => OpArg::ZeroX {
+            zero_page_base: u8_arg(),
+        },
+        OpMode::ZeroY => OpArg::ZeroY {
+            zero_page_base: u8_arg(),
+        },
+        OpMode::ZeroInd => OpArg::ZeroInd {
+            target_addr: u8_arg(),
+        },
+    }
+}
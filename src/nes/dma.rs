@@ -0,0 +1,114 @@
+use crate::nes::{Nes, NesIo};
+use std::cell::Cell;
+use std::ops::Generator;
+
+// The 2A03's DMA unit, which halts the CPU while it copies sprite data to
+// OAM, or while the DMC fetches its next sample byte. See:
+// - https://wiki.nesdev.com/w/index.php/PPU_registers#OAMDMA
+// - https://wiki.nesdev.com/w/index.php/DMA
+#[derive(Clone)]
+pub struct Dma {
+    // The page written to $4014, until the transfer starts
+    oam_page: Cell<Option<u8>>,
+
+    // The address of the sample byte the DMC asked for, until the
+    // transfer finishes
+    dmc_addr: Cell<Option<u16>>,
+
+    // OAM DMA reads on even CPU cycles and writes on odd CPU cycles
+    is_odd_cycle: Cell<bool>,
+}
+
+impl Dma {
+    pub fn new() -> Self {
+        Dma {
+            oam_page: Cell::new(None),
+            dmc_addr: Cell::new(None),
+            is_odd_cycle: Cell::new(false),
+        }
+    }
+
+    // Forget any pending transfers (a transfer that's already underway
+    // still runs to completion)
+    pub fn reset(&self) {
+        self.oam_page.set(None);
+        self.dmc_addr.set(None);
+    }
+
+    // Start copying the 256-byte page at `page << 8` to OAM, starting with
+    // the next CPU cycle
+    pub fn start_oam_dma(&self, page: u8) {
+        self.oam_page.set(Some(page));
+    }
+
+    // Start fetching the DMC's next sample byte from `addr`, unless a fetch
+    // is already underway
+    pub fn start_dmc_dma(&self, addr: u16) {
+        if self.dmc_addr.get().is_none() {
+            self.dmc_addr.set(Some(addr));
+        }
+    }
+
+    // Advance to the next CPU cycle. Should be called once at the end of
+    // each CPU cycle, whether or not the CPU was halted
+    pub fn end_cycle(&self) {
+        self.is_odd_cycle.update(|is_odd_cycle| !is_odd_cycle);
+    }
+
+    // Resumed once per CPU cycle. Yields `DmaStep::Cycle` when the DMA unit
+    // takes the cycle (so the CPU is halted), or `DmaStep::Idle` when the
+    // CPU is free to run
+    //
+    // TODO: The CPU can only be halted on a read cycle, so on hardware the
+    // halt gets delayed while the CPU is writing (such as during the pushes
+    // of JSR, BRK, or an interrupt sequence). Here, the CPU always gets
+    // halted on the next cycle, so stalls that start during a write take
+    // a different number of cycles than on hardware
+    pub fn run<'a>(nes: &'a Nes<impl NesIo>) -> impl Generator<Yield = DmaStep, Return = !> + 'a {
+        move || loop {
+            if let Some(page) = nes.dma.oam_page.take() {
+                // Wait a cycle for the CPU to halt
+                yield DmaStep::Cycle;
+
+                // Reads need to land on even cycles, so wait one more
+                // cycle to line up if needed (making the whole transfer
+                // take 513 or 514 cycles)
+                if nes.dma.is_odd_cycle.get() {
+                    yield DmaStep::Cycle;
+                }
+
+                for index in 0x00..=0xFF {
+                    let addr = ((page as u16) << 8) | index;
+                    let value = nes.read_u8(addr);
+                    yield DmaStep::Cycle;
+
+                    nes.write_u8(0x2004, value);
+                    yield DmaStep::Cycle;
+                }
+            } else if let Some(addr) = nes.dma.dmc_addr.get() {
+                // Wait a cycle for the CPU to halt, then a dummy cycle
+                yield DmaStep::Cycle;
+                yield DmaStep::Cycle;
+
+                // Like OAM DMA, the read needs to land on an even cycle, so
+                // the whole transfer takes 3 or 4 cycles
+                if nes.dma.is_odd_cycle.get() {
+                    yield DmaStep::Cycle;
+                }
+
+                let value = nes.read_u8(addr);
+                nes.apu.dmc.load_sample_buffer(value);
+                nes.dma.dmc_addr.set(None);
+                yield DmaStep::Cycle;
+            } else {
+                yield DmaStep::Idle;
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DmaStep {
+    Idle,
+    Cycle,
+}
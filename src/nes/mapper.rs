@@ -1,11 +1,12 @@
 use crate::nes::{Nes, NesIo};
-use crate::rom::Rom;
+use crate::rom::{MirrorMode, Nsf, Rom, TvSystem};
 use std::cell::Cell;
 
 #[derive(Clone)]
 pub enum Mapper {
     Nrom(NromMapper),
     Uxrom(UxromMapper),
+    Nsf(NsfMapper),
 }
 
 impl Mapper {
@@ -19,10 +20,13 @@ impl Mapper {
         }
     }
 
-    pub fn read_u8(&self, addr: u16) -> u8 {
+    // Read from the cartridge's side of the CPU bus, returning `None` for
+    // addresses that the cartridge doesn't respond to (open bus)
+    pub fn read_u8(&self, addr: u16) -> Option<u8> {
         match self {
             Mapper::Nrom(mapper) => mapper.read_u8(addr),
             Mapper::Uxrom(mapper) => mapper.read_u8(addr),
+            Mapper::Nsf(mapper) => mapper.read_u8(addr),
         }
     }
 
@@ -30,6 +34,31 @@ impl Mapper {
         match self {
             Mapper::Nrom(mapper) => mapper.write_u8(addr, value),
             Mapper::Uxrom(mapper) => mapper.write_u8(addr, value),
+            Mapper::Nsf(mapper) => mapper.write_u8(addr, value),
+        }
+    }
+
+    // Called when the reset button is pressed
+    pub fn reset(&self) {
+        match self {
+            Mapper::Nrom(_) | Mapper::Uxrom(_) => {}
+            Mapper::Nsf(mapper) => mapper.reset(),
+        }
+    }
+
+    pub fn power_cycle(&self) {
+        match self {
+            Mapper::Nrom(mapper) => mapper.power_cycle(),
+            Mapper::Uxrom(mapper) => mapper.power_cycle(),
+            Mapper::Nsf(mapper) => mapper.power_cycle(),
+        }
+    }
+
+    // Called once per CPU cycle, for mappers that have their own timers
+    pub fn clock(&self, nes: &Nes<impl NesIo>) {
+        match self {
+            Mapper::Nrom(_) | Mapper::Uxrom(_) => {}
+            Mapper::Nsf(mapper) => mapper.clock(nes),
         }
     }
 
@@ -37,6 +66,7 @@ impl Mapper {
         match self {
             Mapper::Nrom(mapper) => mapper.read_ppu_u8(nes, addr),
             Mapper::Uxrom(mapper) => mapper.read_ppu_u8(nes, addr),
+            Mapper::Nsf(mapper) => mapper.read_ppu_u8(nes, addr),
         }
     }
 
@@ -44,8 +74,90 @@ impl Mapper {
         match self {
             Mapper::Nrom(mapper) => mapper.write_ppu_u8(nes, addr, value),
             Mapper::Uxrom(mapper) => mapper.write_ppu_u8(nes, addr, value),
+            Mapper::Nsf(mapper) => mapper.write_ppu_u8(nes, addr, value),
+        }
+    }
+
+    fn nametables(&self) -> &Nametables {
+        match self {
+            Mapper::Nrom(mapper) => &mapper.nametables,
+            Mapper::Uxrom(mapper) => &mapper.nametables,
+            Mapper::Nsf(mapper) => &mapper.nametables,
+        }
+    }
+
+    pub fn mirror_mode(&self) -> MirrorMode {
+        self.nametables().mirror_mode.get()
+    }
+
+    pub fn set_mirror_mode(&self, mirror_mode: MirrorMode) {
+        self.nametables().set_mirror_mode(mirror_mode);
+    }
+}
+
+// Maps the PPU's nametable addresses ($2000-$2FFF, mirrored up to $3EFF)
+// onto the PPU's 2KiB of VRAM, following the current mirroring mode. For
+// four-screen mirroring, the third and fourth nametables use 2KiB of
+// extra VRAM on the cartridge. Mappers can switch to four-screen mirroring
+// at runtime, so the extra VRAM is always there. See:
+// - https://wiki.nesdev.com/w/index.php/Mirroring
+#[derive(Clone)]
+struct Nametables {
+    mirror_mode: Cell<MirrorMode>,
+    initial_mirror_mode: MirrorMode,
+    extra_vram: Cell<[u8; 0x0800]>,
+}
+
+impl Nametables {
+    fn new(mirror_mode: MirrorMode) -> Self {
+        Nametables {
+            mirror_mode: Cell::new(mirror_mode),
+            initial_mirror_mode: mirror_mode,
+            extra_vram: Cell::new([0; 0x0800]),
         }
     }
+
+    fn power_cycle(&self) {
+        self.mirror_mode.set(self.initial_mirror_mode);
+        self.extra_vram.set([0; 0x0800]);
+    }
+
+    fn set_mirror_mode(&self, mirror_mode: MirrorMode) {
+        self.mirror_mode.set(mirror_mode);
+    }
+
+    fn read_u8(&self, nes: &Nes<impl NesIo>, addr: u16) -> u8 {
+        self.vram_cell(nes, addr).get()
+    }
+
+    fn write_u8(&self, nes: &Nes<impl NesIo>, addr: u16, value: u8) {
+        self.vram_cell(nes, addr).set(value);
+    }
+
+    fn vram_cell<'a>(&'a self, nes: &'a Nes<impl NesIo>, addr: u16) -> &'a Cell<u8> {
+        let ppu_ram = nes.ppu.ppu_ram();
+        let nametable = (addr & 0x0FFF) / 0x0400;
+        let offset = (addr & 0x03FF) as usize;
+
+        let vram_nametable = match (self.mirror_mode.get(), nametable) {
+            (MirrorMode::Horizontal, nametable) => nametable / 2,
+            (MirrorMode::Vertical, nametable) => nametable % 2,
+            (MirrorMode::SingleScreenA, _) => 0,
+            (MirrorMode::SingleScreenB, _) => 1,
+            (MirrorMode::FourScreenVram, 0..=1) => nametable,
+            (MirrorMode::FourScreenVram, nametable) => {
+                let extra_offset = (nametable - 2) as usize * 0x0400 + offset;
+                return &self.extra_vram()[extra_offset];
+            }
+        };
+
+        &ppu_ram[vram_nametable as usize * 0x0400 + offset]
+    }
+
+    fn extra_vram(&self) -> &[Cell<u8>] {
+        let extra_vram: &Cell<[u8]> = &self.extra_vram;
+        extra_vram.as_slice_of_cells()
+    }
 }
 
 #[derive(Clone)]
@@ -53,35 +165,44 @@ pub struct NromMapper {
     rom: Rom,
     work_ram: Cell<[u8; 0x2000]>,
     chr_ram: Vec<Cell<u8>>,
+    nametables: Nametables,
 }
 
 impl NromMapper {
     pub fn from_rom(rom: Rom) -> Self {
         let work_ram = Cell::new([0; 0x2000]);
         let chr_ram = vec![Cell::new(0); rom.header.chr_ram_size_bytes];
+        let nametables = Nametables::new(rom.header.mirror_mode);
 
         NromMapper {
             rom,
             work_ram,
             chr_ram,
+            nametables,
         }
     }
 
-    pub fn read_u8(&self, addr: u16) -> u8 {
+    pub fn power_cycle(&self) {
+        self.work_ram.set([0; 0x2000]);
+        for byte in &self.chr_ram {
+            byte.set(0);
+        }
+        self.nametables.power_cycle();
+    }
+
+    pub fn read_u8(&self, addr: u16) -> Option<u8> {
         let work_ram = self.work_ram();
         let prg_rom = &self.rom.prg_rom;
 
         match addr {
-            0x0000..=0x5FFF => {
-                panic!("Tried to read from mapper at address ${:04X}", addr);
-            }
+            0x0000..=0x5FFF => None,
             0x6000..=0x7FFF => {
                 let offset = ((addr - 0x6000) as usize) % work_ram.len();
-                work_ram[offset].get()
+                Some(work_ram[offset].get())
             }
             0x8000..=0xFFFF => {
                 let offset = ((addr - 0x8000) as usize) % prg_rom.len();
-                prg_rom[offset]
+                Some(prg_rom[offset])
             }
         }
     }
@@ -90,9 +211,7 @@ impl NromMapper {
         let work_ram = self.work_ram();
 
         match addr {
-            0x0000..=0x5FFF => {
-                panic!("Tried to write to mapper at address ${:04X}", addr);
-            }
+            0x0000..=0x5FFF => {}
             0x6000..=0x7FFF => {
                 let offset = ((addr - 0x6000) as usize) % work_ram.len();
                 work_ram[offset].set(value);
@@ -104,7 +223,6 @@ impl NromMapper {
     pub fn read_ppu_u8(&self, nes: &Nes<impl NesIo>, addr: u16) -> u8 {
         let chr_rom = &self.rom.chr_rom;
         let chr_ram = &self.chr_ram;
-        let ppu_ram = nes.ppu.ppu_ram();
         match addr {
             0x0000..=0x1FFF => {
                 if chr_rom.is_empty() {
@@ -115,14 +233,7 @@ impl NromMapper {
                     chr_rom[offset]
                 }
             }
-            0x2000..=0x2FFF => {
-                let offset = ((addr - 0x2000) as usize) % ppu_ram.len();
-                ppu_ram[offset].get()
-            }
-            0x3000..=0x3EFF => {
-                let offset = (addr - 0x3000) % 0x0FFF;
-                self.read_ppu_u8(nes, offset + 0x2000)
-            }
+            0x2000..=0x3EFF => self.nametables.read_u8(nes, addr),
             0x3F00..=0xFFFF => {
                 unreachable!();
             }
@@ -130,7 +241,6 @@ impl NromMapper {
     }
 
     pub fn write_ppu_u8(&self, nes: &Nes<impl NesIo>, addr: u16, value: u8) {
-        let ppu_ram = nes.ppu.ppu_ram();
         let chr_rom = &self.rom.chr_rom;
         let chr_ram = &self.chr_ram;
         match addr {
@@ -142,13 +252,8 @@ impl NromMapper {
                     // Do nothing-- tried to write to read-only CHR ROM
                 }
             }
-            0x2000..=0x2FFF => {
-                let offset = ((addr - 0x2000) as usize) % ppu_ram.len();
-                ppu_ram[offset].set(value)
-            }
-            0x3000..=0x3EFF => {
-                let offset = (addr - 0x3000) % 0x0FFF;
-                self.write_ppu_u8(nes, offset + 0x2000, value)
+            0x2000..=0x3EFF => {
+                self.nametables.write_u8(nes, addr, value);
             }
             0x3F00..=0xFFFF => {
                 unreachable!();
@@ -168,6 +273,7 @@ pub struct UxromMapper {
     bank: Cell<usize>,
     work_ram: Cell<[u8; 0x2000]>,
     chr_ram: Vec<Cell<u8>>,
+    nametables: Nametables,
 }
 
 impl UxromMapper {
@@ -175,41 +281,51 @@ impl UxromMapper {
         let work_ram = Cell::new([0; 0x2000]);
         let chr_ram = vec![Cell::new(0); rom.header.chr_ram_size_bytes];
         let bank = Cell::new(5);
+        let nametables = Nametables::new(rom.header.mirror_mode);
 
         UxromMapper {
             rom,
             bank,
             work_ram,
             chr_ram,
+            nametables,
         }
     }
 
+    pub fn power_cycle(&self) {
+        // NOTE: The bank register isn't reset, since its value at power-up
+        // is undefined
+        self.work_ram.set([0; 0x2000]);
+        for byte in &self.chr_ram {
+            byte.set(0);
+        }
+        self.nametables.power_cycle();
+    }
+
     pub fn banks<'a>(&'a self) -> impl ExactSizeIterator<Item = &'a [u8]> + 'a {
         self.rom.prg_rom.chunks(16_384)
     }
 
-    pub fn read_u8(&self, addr: u16) -> u8 {
+    pub fn read_u8(&self, addr: u16) -> Option<u8> {
         let work_ram = self.work_ram();
         let mut banks = self.banks();
 
         match addr {
-            0x0000..=0x5FFF => {
-                panic!("Tried to read from mapper at address ${:04X}", addr);
-            }
+            0x0000..=0x5FFF => None,
             0x6000..=0x7FFF => {
                 let offset = ((addr - 0x6000) as usize) % work_ram.len();
-                work_ram[offset].get()
+                Some(work_ram[offset].get())
             }
             0x8000..=0xBFFF => {
                 let n = self.bank.get();
                 let bank = banks.nth(n).unwrap();
                 let offset = ((addr - 0x8000) as usize) % bank.len();
-                bank[offset]
+                Some(bank[offset])
             }
             0xC000..=0xFFFF => {
                 let bank = banks.last().unwrap();
                 let offset = ((addr - 0xC000) as usize) % bank.len();
-                bank[offset]
+                Some(bank[offset])
             }
         }
     }
@@ -219,9 +335,7 @@ impl UxromMapper {
         let banks = self.banks();
 
         match addr {
-            0x0000..=0x5FFF => {
-                panic!("Tried to write to mapper at address ${:04X}", addr);
-            }
+            0x0000..=0x5FFF => {}
             0x6000..=0x7FFF => {
                 let offset = ((addr - 0x6000) as usize) % work_ram.len();
                 work_ram[offset].set(value);
@@ -240,7 +354,6 @@ impl UxromMapper {
     pub fn read_ppu_u8(&self, nes: &Nes<impl NesIo>, addr: u16) -> u8 {
         let chr_rom = &self.rom.chr_rom;
         let chr_ram = &self.chr_ram;
-        let ppu_ram = nes.ppu.ppu_ram();
         match addr {
             0x0000..=0x1FFF => {
                 if chr_rom.is_empty() {
@@ -251,14 +364,7 @@ impl UxromMapper {
                     chr_rom[offset]
                 }
             }
-            0x2000..=0x2FFF => {
-                let offset = ((addr - 0x2000) as usize) % ppu_ram.len();
-                ppu_ram[offset].get()
-            }
-            0x3000..=0x3EFF => {
-                let offset = (addr - 0x3000) % 0x0FFF;
-                self.read_ppu_u8(nes, offset + 0x2000)
-            }
+            0x2000..=0x3EFF => self.nametables.read_u8(nes, addr),
             0x3F00..=0xFFFF => {
                 unreachable!();
             }
@@ -266,7 +372,6 @@ impl UxromMapper {
     }
 
     pub fn write_ppu_u8(&self, nes: &Nes<impl NesIo>, addr: u16, value: u8) {
-        let ppu_ram = nes.ppu.ppu_ram();
         let chr_rom = &self.rom.chr_rom;
         let chr_ram = &self.chr_ram;
         match addr {
@@ -278,13 +383,226 @@ impl UxromMapper {
                     // Do nothing-- tried to write to read-only CHR ROM
                 }
             }
-            0x2000..=0x2FFF => {
-                let offset = ((addr - 0x2000) as usize) % ppu_ram.len();
-                ppu_ram[offset].set(value)
+            0x2000..=0x3EFF => {
+                self.nametables.write_u8(nes, addr, value);
+            }
+            0x3F00..=0xFFFF => {
+                unreachable!();
+            }
+        }
+    }
+
+    fn work_ram(&self) -> &[Cell<u8>] {
+        let work_ram: &Cell<[u8]> = &self.work_ram;
+        work_ram.as_slice_of_cells()
+    }
+}
+
+const NTSC_CPU_FREQUENCY: u64 = 1_789_773;
+const PAL_CPU_FREQUENCY: u64 = 1_662_607;
+
+// The NSF mapper places a small driver program here, which sets up the APU,
+// calls the NSF's INIT routine, then calls the PLAY routine from its NMI
+// handler. This is in the cartridge expansion area, which is unused by NSFs
+// without expansion audio.
+const NSF_DRIVER_ADDR: u16 = 0x4100;
+const NSF_DRIVER_NMI_ADDR: u16 = NSF_DRIVER_ADDR + 0x47;
+const NSF_DRIVER_IRQ_ADDR: u16 = NSF_DRIVER_ADDR + 0x4D;
+
+// The driver writes to this register when INIT or PLAY returns, so that
+// the next call to PLAY can be scheduled
+const NSF_PLAY_DONE_ADDR: u16 = 0x41F0;
+
+// A synthetic mapper for playing NSF files. The NSF's data is mapped into
+// $8000-$FFFF (in 4KiB banks for bankswitched NSFs), and NMIs are raised
+// at the NSF's play rate to drive the PLAY routine. See:
+// - https://wiki.nesdev.com/w/index.php/NSF
+#[derive(Clone)]
+pub struct NsfMapper {
+    nsf: Nsf,
+    driver: Vec<u8>,
+    prg: Vec<u8>,
+    initial_banks: [u8; 8],
+    banks: Cell<[u8; 8]>,
+    work_ram: Cell<[u8; 0x2000]>,
+    chr_ram: Vec<Cell<u8>>,
+    nametables: Nametables,
+    play_period: u32,
+    play_timer: Cell<u32>,
+    is_play_ready: Cell<bool>,
+}
+
+impl NsfMapper {
+    // Create a mapper that plays `song` (starting at 0) from the NSF
+    pub fn new(nsf: Nsf, song: u8) -> Self {
+        let header = &nsf.header;
+
+        // Bankswitched NSFs are padded so the load address lands at the
+        // right offset within the first bank. Otherwise, the NSF's data
+        // is placed at the load address, with banks fixed in order.
+        let (padding, banks) = if header.is_bankswitched() {
+            (header.load_addr & 0x0FFF, header.bankswitch_init)
+        } else {
+            (header.load_addr - 0x8000, [0, 1, 2, 3, 4, 5, 6, 7])
+        };
+
+        let mut prg = vec![0; padding as usize];
+        prg.extend_from_slice(&nsf.data);
+        let prg_len = (prg.len() + 0x0FFF) & !0x0FFF;
+        prg.resize(prg_len, 0);
+
+        let (play_speed, cpu_frequency, tv_flag) = match header.tv_system {
+            TvSystem::Ntsc | TvSystem::Dual => (header.ntsc_play_speed, NTSC_CPU_FREQUENCY, 0),
+            TvSystem::Pal => (header.pal_play_speed, PAL_CPU_FREQUENCY, 1),
+        };
+
+        // The play speed is the number of microseconds between each call
+        // to PLAY. Fall back to the usual NTSC or PAL rate if it's unset.
+        let play_speed = match (play_speed, tv_flag) {
+            (0, 0) => 16_639,
+            (0, _) => 19_997,
+            (play_speed, _) => play_speed,
+        };
+        let play_period = (play_speed as u64 * cpu_frequency / 1_000_000) as u32;
+
+        let driver = nsf_driver(header.init_addr, header.play_addr, song, tv_flag);
+
+        NsfMapper {
+            nsf,
+            driver,
+            prg,
+            initial_banks: banks,
+            banks: Cell::new(banks),
+            work_ram: Cell::new([0; 0x2000]),
+            chr_ram: vec![Cell::new(0); 0x2000],
+            nametables: Nametables::new(MirrorMode::Horizontal),
+            play_period,
+            play_timer: Cell::new(play_period),
+            is_play_ready: Cell::new(false),
+        }
+    }
+
+    // Resetting restarts the driver, which calls INIT again. INIT expects
+    // the work RAM to be cleared (the driver clears the internal RAM)
+    pub fn reset(&self) {
+        self.banks.set(self.initial_banks);
+        self.work_ram.set([0; 0x2000]);
+        self.play_timer.set(self.play_period);
+        self.is_play_ready.set(false);
+    }
+
+    pub fn power_cycle(&self) {
+        self.reset();
+        for byte in &self.chr_ram {
+            byte.set(0);
+        }
+        self.nametables.power_cycle();
+    }
+
+    pub fn nsf(&self) -> &Nsf {
+        &self.nsf
+    }
+
+    pub fn read_u8(&self, addr: u16) -> Option<u8> {
+        let work_ram = self.work_ram();
+
+        match addr {
+            0x0000..=0x40FF => None,
+            0x4100..=0x41FF => {
+                let offset = (addr - NSF_DRIVER_ADDR) as usize;
+                Some(self.driver.get(offset).cloned().unwrap_or(0x00))
+            }
+            0x4200..=0x5FFF => None,
+            0x6000..=0x7FFF => {
+                let offset = ((addr - 0x6000) as usize) % work_ram.len();
+                Some(work_ram[offset].get())
+            }
+            0x8000..=0xFFF9 => {
+                let bank_index = ((addr - 0x8000) / 0x1000) as usize;
+                let bank = self.banks.get()[bank_index] as usize;
+                let num_banks = self.prg.len() / 0x1000;
+                let offset = (bank % num_banks) * 0x1000 + (addr & 0x0FFF) as usize;
+                Some(self.prg[offset])
+            }
+            0xFFFA..=0xFFFF => {
+                // The interrupt vectors always point into the driver
+                let vector = match addr & 0xFFFE {
+                    0xFFFA => NSF_DRIVER_NMI_ADDR,
+                    0xFFFC => NSF_DRIVER_ADDR,
+                    _ => NSF_DRIVER_IRQ_ADDR,
+                };
+
+                if addr & 1 == 0 {
+                    Some(vector as u8)
+                } else {
+                    Some((vector >> 8) as u8)
+                }
+            }
+        }
+    }
+
+    pub fn write_u8(&self, addr: u16, value: u8) {
+        let work_ram = self.work_ram();
+
+        match addr {
+            NSF_PLAY_DONE_ADDR => {
+                self.is_play_ready.set(true);
+            }
+            0x5FF8..=0x5FFF => {
+                if self.nsf.header.is_bankswitched() {
+                    let mut banks = self.banks.get();
+                    banks[(addr - 0x5FF8) as usize] = value;
+                    self.banks.set(banks);
+                }
+            }
+            0x0000..=0x5FFF => {}
+            0x6000..=0x7FFF => {
+                let offset = ((addr - 0x6000) as usize) % work_ram.len();
+                work_ram[offset].set(value);
+            }
+            0x8000..=0xFFFF => {}
+        }
+    }
+
+    fn clock(&self, nes: &Nes<impl NesIo>) {
+        // The play timer runs continuously. If PLAY is still running when
+        // the timer expires, that call to PLAY is skipped.
+        let play_timer = self.play_timer.get();
+        if play_timer == 0 {
+            self.play_timer.set(self.play_period - 1);
+
+            if self.is_play_ready.get() {
+                self.is_play_ready.set(false);
+                nes.cpu.nmi.set(true);
+            }
+        } else {
+            self.play_timer.set(play_timer - 1);
+        }
+    }
+
+    pub fn read_ppu_u8(&self, nes: &Nes<impl NesIo>, addr: u16) -> u8 {
+        let chr_ram = &self.chr_ram;
+        match addr {
+            0x0000..=0x1FFF => {
+                let offset = (addr as usize) % chr_ram.len();
+                chr_ram[offset].get()
+            }
+            0x2000..=0x3EFF => self.nametables.read_u8(nes, addr),
+            0x3F00..=0xFFFF => {
+                unreachable!();
             }
-            0x3000..=0x3EFF => {
-                let offset = (addr - 0x3000) % 0x0FFF;
-                self.write_ppu_u8(nes, offset + 0x2000, value)
+        }
+    }
+
+    pub fn write_ppu_u8(&self, nes: &Nes<impl NesIo>, addr: u16, value: u8) {
+        let chr_ram = &self.chr_ram;
+        match addr {
+            0x0000..=0x1FFF => {
+                let offset = (addr as usize) % chr_ram.len();
+                chr_ram[offset].set(value);
+            }
+            0x2000..=0x3EFF => {
+                self.nametables.write_u8(nes, addr, value);
             }
             0x3F00..=0xFFFF => {
                 unreachable!();
@@ -297,3 +615,54 @@ impl UxromMapper {
         work_ram.as_slice_of_cells()
     }
 }
+
+fn nsf_driver(init_addr: u16, play_addr: u16, song: u8, tv_flag: u8) -> Vec<u8> {
+    let [init_lo, init_hi] = init_addr.to_le_bytes();
+    let [play_lo, play_hi] = play_addr.to_le_bytes();
+    let [done_lo, done_hi] = NSF_PLAY_DONE_ADDR.to_le_bytes();
+    let [idle_lo, idle_hi] = (NSF_DRIVER_ADDR + 0x44).to_le_bytes();
+
+    vec![
+        // Reset:
+        0x78, //             SEI
+        0xD8, //             CLD
+        0xA2, 0xFF, //       LDX #$FF
+        0x9A, //             TXS
+        // Clear the APU registers, then enable all channels
+        0xA9, 0x00, //       LDA #$00
+        0xA2, 0x13, //       LDX #$13
+        0x9D, 0x00, 0x40, // STA $4000,X
+        0xCA, //             DEX
+        0x10, 0xFA, //       BPL -6
+        0x8D, 0x15, 0x40, // STA $4015
+        0xA9, 0x0F, //       LDA #$0F
+        0x8D, 0x15, 0x40, // STA $4015
+        0xA9, 0x40, //       LDA #$40
+        0x8D, 0x17, 0x40, // STA $4017
+        // Clear the internal RAM, so INIT always starts from a clean state
+        0xA9, 0x00, //       LDA #$00
+        0xAA, //             TAX
+        0x9D, 0x00, 0x00, // STA $0000,X
+        0x9D, 0x00, 0x01, // STA $0100,X
+        0x9D, 0x00, 0x02, // STA $0200,X
+        0x9D, 0x00, 0x03, // STA $0300,X
+        0x9D, 0x00, 0x04, // STA $0400,X
+        0x9D, 0x00, 0x05, // STA $0500,X
+        0x9D, 0x00, 0x06, // STA $0600,X
+        0x9D, 0x00, 0x07, // STA $0700,X
+        0xE8, //             INX
+        0xD0, 0xE5, //       BNE -27
+        // Call INIT with the song number in A and the TV system in X
+        0xA9, song, //       LDA #song
+        0xA2, tv_flag, //    LDX #tv_flag
+        0x20, init_lo, init_hi, // JSR init
+        0x8D, done_lo, done_hi, // STA done
+        // Idle:
+        0x4C, idle_lo, idle_hi, // JMP idle
+        // NMI:
+        0x20, play_lo, play_hi, // JSR play
+        0x8D, done_lo, done_hi, // STA done
+        // IRQ:
+        0x40, //             RTI
+    ]
+}
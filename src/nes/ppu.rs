@@ -12,13 +12,29 @@ pub struct Ppu {
     pub mask: Cell<PpuMaskFlags>,
     pub status: Cell<PpuStatusFlags>,
     pub oam_addr: Cell<u8>,
-    pub scroll: Cell<u16>,
-    pub addr: Cell<u16>,
+
+    // The PPU's internal scroll registers (also known as the "loopy"
+    // registers). `vram_addr` (v) is the address used by PPUDATA and by the
+    // renderer, and `temp_vram_addr` (t) holds the scroll position written
+    // with PPUCTRL, PPUSCROLL, and PPUADDR until it gets copied to
+    // `vram_addr`. While rendering, both are laid out as `0yyy NNYY YYYX XXXX`
+    // (fine Y, nametable, coarse Y, and coarse X). See:
+    // - https://wiki.nesdev.com/w/index.php/PPU_scrolling
+    pub vram_addr: Cell<u16>,
+    pub temp_vram_addr: Cell<u16>,
+    pub fine_x_scroll: Cell<u8>,
 
     // Latch used for writing to PPUSCROLL and PPUADDR (toggles after a write
     // to each, used to determine if the high bit or low bit is being written).
+    // Reading PPUSTATUS clears it
     pub scroll_addr_latch: Cell<bool>,
 
+    // The PPU's own data bus latch (separate from the CPU's open bus). Every
+    // write to a PPU register fills it, and reading a write-only register
+    // returns it. See:
+    // - https://wiki.nesdev.com/w/index.php/Open_bus_behavior#PPU_open_bus
+    pub io_latch: Cell<u8>,
+
     pub ppu_ram: Cell<[u8; 0x0800]>,
     pub oam: Cell<[u8; 0x0100]>,
     pub palette_ram: Cell<[u8; 0x20]>,
@@ -29,6 +45,12 @@ pub struct Ppu {
     // the sprites at index 0 and 6 should be rendered at pixel 5 of
     // the scanline (since bits 0 and 6 are set)
     scanline_sprite_indices: Cell<[u64; 256]>,
+
+    // The frame, scanline, and dot (the cycle within the scanline) that
+    // the PPU will run next. Frames are counted from power-up
+    pub frame: Cell<u64>,
+    pub scanline: Cell<u16>,
+    pub dot: Cell<u16>,
 }
 
 impl Ppu {
@@ -38,16 +60,44 @@ impl Ppu {
             mask: Cell::new(PpuMaskFlags::from_bits_truncate(0x00)),
             status: Cell::new(PpuStatusFlags::from_bits_truncate(0x00)),
             oam_addr: Cell::new(0x00),
-            scroll: Cell::new(0x0000),
-            addr: Cell::new(0x0000),
+            vram_addr: Cell::new(0x0000),
+            temp_vram_addr: Cell::new(0x0000),
+            fine_x_scroll: Cell::new(0),
             scroll_addr_latch: Cell::new(false),
+            io_latch: Cell::new(0x00),
             ppu_ram: Cell::new([0; 0x0800]),
             oam: Cell::new([0; 0x0100]),
             palette_ram: Cell::new([0; 0x20]),
             scanline_sprite_indices: Cell::new([0; 256]),
+            frame: Cell::new(0),
+            scanline: Cell::new(0),
+            dot: Cell::new(0),
         }
     }
 
+    // Pressing reset clears some of the PPU's registers, but leaves its
+    // memory alone. See:
+    // - https://wiki.nesdev.com/w/index.php/PPU_power_up_state
+    pub fn reset(&self) {
+        self.ctrl.set(PpuCtrlFlags::from_bits_truncate(0x00));
+        self.mask.set(PpuMaskFlags::from_bits_truncate(0x00));
+        self.temp_vram_addr.set(0x0000);
+        self.fine_x_scroll.set(0);
+        self.scroll_addr_latch.set(false);
+    }
+
+    pub fn power_cycle(&self) {
+        self.reset();
+        self.status.set(PpuStatusFlags::from_bits_truncate(0x00));
+        self.oam_addr.set(0x00);
+        self.vram_addr.set(0x0000);
+        self.io_latch.set(0x00);
+        self.ppu_ram.set([0; 0x0800]);
+        self.oam.set([0; 0x0100]);
+        self.palette_ram.set([0; 0x20]);
+        self.scanline_sprite_indices.set([0; 256]);
+    }
+
     pub fn ppu_ram(&self) -> &[Cell<u8>] {
         let ppu_ram: &Cell<[u8]> = &self.ppu_ram;
         ppu_ram.as_slice_of_cells()
@@ -65,6 +115,11 @@ impl Ppu {
 
     pub fn set_ppuctrl(&self, value: u8) {
         self.ctrl.set(PpuCtrlFlags::from_bits_truncate(value));
+
+        // The nametable select bits go into t
+        let nametable = (value as u16 & 0b_11) << 10;
+        self.temp_vram_addr
+            .update(|t| (t & !0b_0000_1100_0000_0000) | nametable);
     }
 
     pub fn set_ppumask(&self, value: u8) {
@@ -75,6 +130,21 @@ impl Ppu {
         self.oam_addr.set(value);
     }
 
+    pub fn read_oamdata(&self) -> u8 {
+        let oam_addr = self.oam_addr.get();
+        let value = self.oam()[oam_addr as usize].get();
+
+        // Bits 2-4 of each sprite's attribute byte don't exist in OAM,
+        // so they always read back as 0
+        let value = match oam_addr % 4 {
+            2 => value & 0b_1110_0011,
+            _ => value,
+        };
+
+        self.io_latch.set(value);
+        value
+    }
+
     pub fn write_oamdata(&self, value: u8) {
         let oam_addr = self.oam_addr.get();
         let oam = self.oam();
@@ -87,15 +157,20 @@ impl Ppu {
 
     pub fn write_ppuscroll(&self, value: u8) {
         let latch = self.scroll_addr_latch.get();
+        let value = value as u16;
 
         if latch {
-            let scroll_lo = self.scroll.get() & 0x00FF;
-            let scroll_hi = (value as u16) << 8;
-            self.scroll.set(scroll_lo | scroll_hi);
+            // Second write: fine Y and coarse Y
+            let fine_y = (value & 0b_0000_0111) << 12;
+            let coarse_y = (value & 0b_1111_1000) << 2;
+            self.temp_vram_addr
+                .update(|t| (t & !0b_0111_0011_1110_0000) | fine_y | coarse_y);
         } else {
-            let scroll_lo = value as u16;
-            let scroll_hi = self.scroll.get() & 0xFF00;
-            self.scroll.set(scroll_lo | scroll_hi);
+            // First write: coarse X and fine X
+            let coarse_x = value >> 3;
+            self.temp_vram_addr
+                .update(|t| (t & !0b_0000_0000_0001_1111) | coarse_x);
+            self.fine_x_scroll.set(value as u8 & 0b_0000_0111);
         }
 
         self.scroll_addr_latch.set(!latch);
@@ -103,39 +178,45 @@ impl Ppu {
 
     pub fn write_ppuaddr(&self, value: u8) {
         let latch = self.scroll_addr_latch.get();
+        let value = value as u16;
 
         if latch {
-            let addr_lo = value as u16;
-            let addr_hi = self.addr.get() & 0xFF00;
-            self.addr.set(addr_lo | addr_hi);
+            let t = (self.temp_vram_addr.get() & 0xFF00) | value;
+            self.temp_vram_addr.set(t);
+            self.vram_addr.set(t);
         } else {
-            let addr_lo = self.addr.get() & 0x00FF;
-            let addr_hi = (value as u16) << 8;
-            self.addr.set(addr_lo | addr_hi);
+            // The high byte only has 6 bits, and the top bit of t is cleared
+            let addr_hi = (value & 0b_0011_1111) << 8;
+            self.temp_vram_addr.update(|t| (t & 0x00FF) | addr_hi);
         }
 
         self.scroll_addr_latch.set(!latch);
     }
 
     pub fn read_ppudata(&self, nes: &Nes<impl NesIo>) -> u8 {
-        let addr = self.addr.get();
-        let ctrl = self.ctrl.get();
-        let stride =
-            // Add 1 to the PPU address if the I flag is clear, add 32 if
-            // it is set
-            match ctrl.contains(PpuCtrlFlags::VRAM_ADDR_INCREMENT) {
-                false => 1,
-                true => 32
-            };
-
+        let addr = self.vram_addr.get() & 0x3FFF;
         let value = nes.read_ppu_u8(addr);
-        self.addr.update(|addr| addr.wrapping_add(stride));
+        self.increment_vram_addr();
 
+        self.io_latch.set(value);
         value
     }
 
     pub fn write_ppudata(&self, nes: &Nes<impl NesIo>, value: u8) {
-        let addr = self.addr.get();
+        let addr = self.vram_addr.get() & 0x3FFF;
+        nes.write_ppu_u8(addr, value);
+        self.increment_vram_addr();
+    }
+
+    fn increment_vram_addr(&self) {
+        if self.is_rendering() {
+            // Accessing PPUDATA while rendering increments coarse X and
+            // Y at the same time, instead of adding the normal stride
+            self.increment_coarse_x();
+            self.increment_y();
+            return;
+        }
+
         let ctrl = self.ctrl.get();
         let stride =
             // Add 1 to the PPU address if the I flag is clear, add 32 if
@@ -144,13 +225,99 @@ impl Ppu {
                 false => 1,
                 true => 32
             };
+        self.vram_addr.update(|v| v.wrapping_add(stride) & 0x7FFF);
+    }
 
-        nes.write_ppu_u8(addr, value);
-        self.addr.update(|addr| addr.wrapping_add(stride));
+    fn is_rendering_enabled(&self) -> bool {
+        self.mask
+            .get()
+            .intersects(PpuMaskFlags::SHOW_BACKGROUND | PpuMaskFlags::SHOW_SPRITES)
+    }
+
+    // Whether the renderer is currently fetching tiles (and updating
+    // `vram_addr`), which happens on the visible and pre-render scanlines
+    fn is_rendering(&self) -> bool {
+        let scanline = self.scanline.get();
+        self.is_rendering_enabled() && (scanline < 240 || scanline == 261)
+    }
+
+    // Move `vram_addr` to the next tile, switching to the horizontally
+    // adjacent nametable after the last column
+    fn increment_coarse_x(&self) {
+        let v = self.vram_addr.get();
+        let v = if v & 0x001F == 31 {
+            (v & !0x001F) ^ 0x0400
+        } else {
+            v + 1
+        };
+        self.vram_addr.set(v);
+    }
+
+    // Move `vram_addr` to the next row of pixels. Coarse Y wraps at row 29
+    // (switching to the vertically adjacent nametable), since rows 30 and
+    // 31 hold the attribute table. If coarse Y was set past row 29, it
+    // wraps at row 31 without switching nametables
+    fn increment_y(&self) {
+        let v = self.vram_addr.get();
+        let v = if v & 0x7000 != 0x7000 {
+            v + 0x1000
+        } else {
+            let v = v & !0x7000;
+            let coarse_y = (v & 0x03E0) >> 5;
+            let (coarse_y, v) = match coarse_y {
+                29 => (0, v ^ 0x0800),
+                31 => (0, v),
+                coarse_y => (coarse_y + 1, v),
+            };
+            (v & !0x03E0) | (coarse_y << 5)
+        };
+        self.vram_addr.set(v);
+    }
+
+    // Copy coarse X and the horizontal nametable bit from t to v
+    fn copy_horizontal_scroll(&self) {
+        let t = self.temp_vram_addr.get();
+        self.vram_addr.update(|v| (v & !0x041F) | (t & 0x041F));
+    }
+
+    // Copy fine Y, coarse Y, and the vertical nametable bit from t to v
+    fn copy_vertical_scroll(&self) {
+        let t = self.temp_vram_addr.get();
+        self.vram_addr.update(|v| (v & !0x7BE0) | (t & 0x7BE0));
+    }
+
+    // Move to the next dot, following the same timing as the renderer:
+    // 262 scanlines of 341 dots, except that the first dot of odd frames
+    // is skipped
+    fn advance_dot(&self) {
+        let dot = self.dot.get() + 1;
+        if dot < 341 {
+            self.dot.set(dot);
+            return;
+        }
+
+        let scanline = self.scanline.get() + 1;
+        if scanline < 262 {
+            self.scanline.set(scanline);
+            self.dot.set(0);
+            return;
+        }
+
+        let frame = self.frame.get() + 1;
+        let is_odd_frame = frame % 2 != 0;
+        self.frame.set(frame);
+        self.scanline.set(0);
+        self.dot.set(if is_odd_frame { 1 } else { 0 });
     }
 
     pub fn ppustatus(&self) -> u8 {
-        self.status.get().bits()
+        self.scroll_addr_latch.set(false);
+
+        // Only the top 3 bits are driven, so the rest come from the latch
+        let latch = self.io_latch.get();
+        let value = self.status.get().bits() | (latch & 0b_0001_1111);
+        self.io_latch.set(value);
+        value
     }
 
     pub fn run<'a>(nes: &'a Nes<impl NesIo>) -> impl Generator<Yield = PpuStep, Return = !> + 'a {
@@ -179,6 +346,7 @@ impl Ppu {
                 }
             }
 
+            nes.ppu.advance_dot();
             yield PpuStep::Cycle;
         }
     }
@@ -239,12 +407,15 @@ impl Ppu {
         nes: &'a Nes<impl NesIo>,
     ) -> impl Generator<Yield = PpuStep, Return = !> + 'a {
         move || loop {
+            let mut next_tile = BackgroundTile::default();
+            let mut background_shifters = BackgroundShifters::default();
+
             for frame in 0_u64.. {
                 let frame_is_odd = frame % 2 != 0;
                 for scanline in 0_u16..=261 {
-                    let tile_y = scanline / 8;
-                    let tile_y_pixel = scanline % 8;
                     let y = scanline;
+                    let is_visible = scanline < 240;
+                    let is_pre_render = scanline == 261;
 
                     let sprite_indices = nes.ppu.scanline_sprite_indices.get();
                     let oam = nes.ppu.oam();
@@ -278,82 +449,81 @@ impl Ppu {
                         nes.io.video().clear();
                     }
 
-                    for tile_x in 0_u16..42 {
-                        if tile_x >= 32 || scanline >= 240 {
-                            // TODO: Implement sprite tile fetching here
-
-                            for _cycle in 0..8 {
-                                yield PpuStep::Cycle;
+                    for dot in 1_u16..=340 {
+                        let is_fetching = (2..=257).contains(&dot) || (321..=337).contains(&dot);
+                        let rendering_enabled = nes.ppu.is_rendering_enabled();
+
+                        if rendering_enabled && (is_visible || is_pre_render) {
+                            // Each tile takes 8 dots to fetch: the nametable
+                            // byte, the attribute byte, then the low and
+                            // high bitplanes of the pattern. The first two
+                            // tiles of each scanline are fetched at the end
+                            // of the previous one. See:
+                            // - https://wiki.nesdev.com/w/index.php/PPU_rendering
+                            if is_fetching {
+                                background_shifters.shift();
+
+                                match (dot - 1) % 8 {
+                                    0 => {
+                                        background_shifters.load(&next_tile);
+
+                                        let v = nes.ppu.vram_addr.get();
+                                        next_tile.index = nes.read_ppu_u8(0x2000 | (v & 0x0FFF));
+                                    }
+                                    2 => {
+                                        let v = nes.ppu.vram_addr.get();
+                                        let attr_addr = 0x23C0
+                                            | (v & 0x0C00)
+                                            | ((v >> 4) & 0x38)
+                                            | ((v >> 2) & 0x07);
+                                        let attr = nes.read_ppu_u8(attr_addr);
+
+                                        // Each attribute byte covers 4x4
+                                        // tiles, with 2 bits for each 2x2
+                                        // quadrant
+                                        let coarse_x = v & 0x001F;
+                                        let coarse_y = (v & 0x03E0) >> 5;
+                                        let shift = ((coarse_y & 0b_10) << 1) | (coarse_x & 0b_10);
+                                        next_tile.palette_index = (attr >> shift) & 0b_11;
+                                    }
+                                    4 => {
+                                        let addr = next_tile.pattern_addr(&nes.ppu);
+                                        next_tile.pattern_lo = nes.read_ppu_u8(addr);
+                                    }
+                                    6 => {
+                                        let addr = next_tile.pattern_addr(&nes.ppu);
+                                        next_tile.pattern_hi = nes.read_ppu_u8(addr + 8);
+                                    }
+                                    7 => {
+                                        nes.ppu.increment_coarse_x();
+                                    }
+                                    _ => {}
+                                }
                             }
 
-                            continue;
+                            if dot == 256 {
+                                nes.ppu.increment_y();
+                            } else if dot == 257 {
+                                nes.ppu.copy_horizontal_scroll();
+                            } else if is_pre_render && (280..=304).contains(&dot) {
+                                nes.ppu.copy_vertical_scroll();
+                            }
                         }
 
-                        let scroll = nes.ppu.scroll.get();
-                        let scroll_x = scroll & 0x00FF;
-                        let tile_offset = scroll_x / 8;
-                        let tile_x_pixel_offset = scroll_x % 8;
-                        let scroll_tile_x = tile_x + tile_offset;
-
-                        yield PpuStep::Cycle;
-                        yield PpuStep::Cycle;
-                        let nametable_index = tile_y * 32 + scroll_tile_x;
-                        let nametable_byte = nes.read_ppu_u8(0x2000 + nametable_index);
-
-                        yield PpuStep::Cycle;
-                        yield PpuStep::Cycle;
-                        let attr_x = scroll_tile_x / 4;
-                        let attr_y = tile_y / 4;
-                        let attr_is_left = ((scroll_tile_x / 2) % 2) == 0;
-                        let attr_is_top = ((tile_y / 2) % 2) == 0;
-
-                        let attr_index = attr_y * 8 + attr_x;
-                        let attr = nes.read_ppu_u8(0x23C0 + attr_index);
-                        let background_palette_index = match (attr_is_top, attr_is_left) {
-                            (true, true) => attr & 0b_0000_0011,
-                            (true, false) => (attr & 0b_0000_1100) >> 2,
-                            (false, true) => (attr & 0b_0011_0000) >> 4,
-                            (false, false) => (attr & 0b_1100_0000) >> 6,
-                        };
-
-                        let pattern_table_offset = if nes
-                            .ppu
-                            .ctrl
-                            .get()
-                            .contains(PpuCtrlFlags::BACKGROUND_PATTERN_TABLE_ADDR)
-                        {
-                            0x1000
-                        } else {
-                            0x0000
-                        };
-                        let bitmap_offset = pattern_table_offset + nametable_byte as u16 * 16;
-                        let bitmap_lo_byte = nes.read_ppu_u8(bitmap_offset + tile_y_pixel);
-
-                        yield PpuStep::Cycle;
-                        yield PpuStep::Cycle;
-                        let bitmap_hi_byte = nes.read_ppu_u8(bitmap_offset + tile_y_pixel + 8);
+                        if is_visible && dot <= 256 {
+                            let x = dot - 1;
+                            let mask = nes.ppu.mask.get();
 
-                        yield PpuStep::Cycle;
-                        yield PpuStep::Cycle;
-                        for tile_x_pixel in 0..8 {
-                            let tile_x_pixel_scroll = tile_x_pixel + tile_x_pixel_offset;
-                            let x = (tile_x * 8) + tile_x_pixel;
-
-                            let background_color_index = {
-                                let bitmap_bitmask = 0b_1000_0000 >> (tile_x_pixel_scroll % 8);
-                                let bitmap_lo_bit = (bitmap_lo_byte & bitmap_bitmask) != 0;
-                                let bitmap_hi_bit = (bitmap_hi_byte & bitmap_bitmask) != 0;
-
-                                let color_index = match (bitmap_hi_bit, bitmap_lo_bit) {
-                                    (false, false) => 0,
-                                    (false, true) => 1,
-                                    (true, false) => 2,
-                                    (true, true) => 3,
+                            let (background_palette_index, background_color_index) =
+                                if mask.contains(PpuMaskFlags::SHOW_BACKGROUND) {
+                                    background_shifters.pixel(nes.ppu.fine_x_scroll.get())
+                                } else {
+                                    (0, 0)
                                 };
-                                color_index
-                            };
 
-                            let sprite_palette_and_color_index = {
+                            let sprite_palette_and_color_index = if mask
+                                .contains(PpuMaskFlags::SHOW_SPRITES)
+                            {
                                 let sprite_index_bitmask = sprite_indices[x as usize];
                                 let included_sprites = (0_u64..64).filter(|sprite_index| {
                                     (sprite_index_bitmask & (1_u64 << sprite_index)) != 0
@@ -427,10 +597,9 @@ impl Ppu {
                                         (palette_index, color_index)
                                     });
 
-                                let palette_and_color_index = palette_and_color_indices
-                                    .find(|&(_, color_index)| color_index != 0);
-
-                                palette_and_color_index
+                                palette_and_color_indices.find(|&(_, color_index)| color_index != 0)
+                            } else {
+                                None
                             };
 
                             let color_code = match sprite_palette_and_color_index {
@@ -449,10 +618,9 @@ impl Ppu {
                             let point = Point { x, y };
                             nes.io.video().draw_point(point, color);
                         }
-                    }
 
-                    for _ in 0..4 {
-                        // TODO: Implement PPU garbage reads
+                        // TODO: Implement sprite tile fetching and PPU
+                        // garbage reads
                         yield PpuStep::Cycle;
                     }
                 }
@@ -527,6 +695,71 @@ impl Ppu {
     }
 }
 
+// The background tile being fetched by the renderer
+#[derive(Default)]
+struct BackgroundTile {
+    index: u8,
+    palette_index: u8,
+    pattern_lo: u8,
+    pattern_hi: u8,
+}
+
+impl BackgroundTile {
+    // The address of the low bitplane for the current row of the tile
+    fn pattern_addr(&self, ppu: &Ppu) -> u16 {
+        let pattern_table_offset = if ppu
+            .ctrl
+            .get()
+            .contains(PpuCtrlFlags::BACKGROUND_PATTERN_TABLE_ADDR)
+        {
+            0x1000
+        } else {
+            0x0000
+        };
+        let fine_y = (ppu.vram_addr.get() & 0x7000) >> 12;
+        pattern_table_offset + self.index as u16 * 16 + fine_y
+    }
+}
+
+// The renderer's shift registers, which hold the pattern and palette bits
+// for the next 16 background pixels. The next tile is loaded into the low
+// 8 bits, and pixels are taken from the high bits (offset by fine X)
+#[derive(Default)]
+struct BackgroundShifters {
+    pattern_lo: u16,
+    pattern_hi: u16,
+    palette_lo: u16,
+    palette_hi: u16,
+}
+
+impl BackgroundShifters {
+    fn load(&mut self, tile: &BackgroundTile) {
+        let expand = |bit: bool| if bit { 0x00FF } else { 0x0000 };
+
+        self.pattern_lo = (self.pattern_lo & 0xFF00) | tile.pattern_lo as u16;
+        self.pattern_hi = (self.pattern_hi & 0xFF00) | tile.pattern_hi as u16;
+        self.palette_lo = (self.palette_lo & 0xFF00) | expand(tile.palette_index & 0b_01 != 0);
+        self.palette_hi = (self.palette_hi & 0xFF00) | expand(tile.palette_index & 0b_10 != 0);
+    }
+
+    fn shift(&mut self) {
+        self.pattern_lo <<= 1;
+        self.pattern_hi <<= 1;
+        self.palette_lo <<= 1;
+        self.palette_hi <<= 1;
+    }
+
+    // Returns the palette index and color index of the current pixel
+    fn pixel(&self, fine_x_scroll: u8) -> (u8, u8) {
+        let bitmask = 0x8000 >> fine_x_scroll;
+        let bit = |shifter: u16| (shifter & bitmask != 0) as u8;
+
+        let palette_index = (bit(self.palette_hi) << 1) | bit(self.palette_lo);
+        let color_index = (bit(self.pattern_hi) << 1) | bit(self.pattern_lo);
+        (palette_index, color_index)
+    }
+}
+
 pub enum PpuStep {
     Cycle,
     Vblank,
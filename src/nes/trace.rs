@@ -0,0 +1,152 @@
+use crate::nes::cpu::{disassemble, CpuFlags, Instruction, OpArg};
+use crate::nes::{Nes, NesIo};
+
+// Describe the instruction the CPU is about to run, along with the state
+// of the CPU and PPU, in the same format as Nintendulator's trace logs
+// (such as `nestest.log`). See:
+// - https://www.qmtpro.com/~nes/misc/nestest.log
+pub fn trace_line(nes: &Nes<impl NesIo>) -> String {
+    let cpu = &nes.cpu;
+    let pc = cpu.pc.get();
+
+    let bytes = [
+        nes.peek_u8(pc),
+        nes.peek_u8(pc.wrapping_add(1)),
+        nes.peek_u8(pc.wrapping_add(2)),
+    ];
+    let disasm_op = disassemble(&bytes, pc)
+        .next()
+        .expect("Failed to decode instruction");
+    let op = disasm_op.op;
+
+    let op_bytes: Vec<_> = disasm_op
+        .bytes
+        .iter()
+        .map(|byte| format!("{:02X}", byte))
+        .collect();
+    let unofficial_marker = if op.instruction.is_unofficial() {
+        "*"
+    } else {
+        " "
+    };
+    let mnemonic = match op.instruction {
+        Instruction::UnofficialIsc => "ISB".to_string(),
+        instruction => instruction.to_string(),
+    };
+
+    let disasm = match disasm_op.branch_target {
+        Some(target) => format!("{} ${:04X}", mnemonic, target),
+        None => format!("{}{}", mnemonic, describe_arg(nes, op.instruction, op.arg)),
+    };
+
+    // The `B` flag only exists when the status is pushed to the stack,
+    // and the unused flag always reads as set
+    let p = (cpu.p.get() - CpuFlags::B) | CpuFlags::U;
+
+    format!(
+        "{:04X}  {:<8} {}{:<32}A:{:02X} X:{:02X} Y:{:02X} P:{:02X} SP:{:02X} PPU:{:>3},{:>3} CYC:{}",
+        pc,
+        op_bytes.join(" "),
+        unofficial_marker,
+        disasm,
+        cpu.a.get(),
+        cpu.x.get(),
+        cpu.y.get(),
+        p.bits(),
+        cpu.s.get(),
+        nes.ppu.scanline.get(),
+        nes.ppu.dot.get(),
+        cpu.cycles.get(),
+    )
+}
+
+// Format an instruction's operand, including the effective address and
+// the value currently stored there
+fn describe_arg(nes: &Nes<impl NesIo>, instruction: Instruction, arg: OpArg) -> String {
+    let cpu = &nes.cpu;
+    let peek_u16_zero_page = |zero_page: u8| {
+        let lo = nes.peek_u8(zero_page as u16);
+        let hi = nes.peek_u8(zero_page.wrapping_add(1) as u16);
+        u16::from_le_bytes([lo, hi])
+    };
+
+    match arg {
+        OpArg::Implied => String::new(),
+        OpArg::Accum => " A".to_string(),
+        OpArg::Imm { value } => format!(" #${:02X}", value),
+        OpArg::Zero { zero_page } => {
+            let value = nes.peek_u8(zero_page as u16);
+            format!(" ${:02X} = {:02X}", zero_page, value)
+        }
+        OpArg::ZeroX { zero_page_base } => {
+            let addr = zero_page_base.wrapping_add(cpu.x.get());
+            let value = nes.peek_u8(addr as u16);
+            format!(" ${:02X},X @ {:02X} = {:02X}", zero_page_base, addr, value)
+        }
+        OpArg::ZeroY { zero_page_base } => {
+            let addr = zero_page_base.wrapping_add(cpu.y.get());
+            let value = nes.peek_u8(addr as u16);
+            format!(" ${:02X},Y @ {:02X} = {:02X}", zero_page_base, addr, value)
+        }
+        OpArg::Abs { addr } => match instruction {
+            Instruction::Jmp | Instruction::Jsr => format!(" ${:04X}", addr),
+            _ => {
+                let value = nes.peek_u8(addr);
+                format!(" ${:04X} = {:02X}", addr, value)
+            }
+        },
+        OpArg::AbsX { addr_base } => {
+            let addr = addr_base.wrapping_add(cpu.x.get() as u16);
+            let value = nes.peek_u8(addr);
+            format!(" ${:04X},X @ {:04X} = {:02X}", addr_base, addr, value)
+        }
+        OpArg::AbsY { addr_base } => {
+            let addr = addr_base.wrapping_add(cpu.y.get() as u16);
+            let value = nes.peek_u8(addr);
+            format!(" ${:04X},Y @ {:04X} = {:02X}", addr_base, addr, value)
+        }
+        OpArg::Ind { target_addr } => {
+            // JMP's indirect mode doesn't carry into the high byte when
+            // reading the target address
+            let target_addr_hi = (target_addr & 0xFF00) | (target_addr.wrapping_add(1) & 0x00FF);
+            let lo = nes.peek_u8(target_addr);
+            let hi = nes.peek_u8(target_addr_hi);
+            let addr = u16::from_le_bytes([lo, hi]);
+            format!(" (${:04X}) = {:04X}", target_addr, addr)
+        }
+        OpArg::IndX { target_addr_base } => {
+            let target_addr = target_addr_base.wrapping_add(cpu.x.get());
+            let addr = peek_u16_zero_page(target_addr);
+            let value = nes.peek_u8(addr);
+            format!(
+                " (${:02X},X) @ {:02X} = {:04X} = {:02X}",
+                target_addr_base, target_addr, addr, value
+            )
+        }
+        OpArg::IndY { target_addr_base } => {
+            let addr_base = peek_u16_zero_page(target_addr_base);
+            let addr = addr_base.wrapping_add(cpu.y.get() as u16);
+            let value = nes.peek_u8(addr);
+            format!(
+                " (${:02X}),Y = {:04X} @ {:04X} = {:02X}",
+                target_addr_base, addr_base, addr, value
+            )
+        }
+        OpArg::AbsXInd { target_addr_base } => {
+            let target_addr = target_addr_base.wrapping_add(cpu.x.get() as u16);
+            let lo = nes.peek_u8(target_addr);
+            let hi = nes.peek_u8(target_addr.wrapping_add(1));
+            let addr = u16::from_le_bytes([lo, hi]);
+            format!(
+                " (${:04X},X) @ {:04X} = {:04X}",
+                target_addr_base, target_addr, addr
+            )
+        }
+        OpArg::ZeroInd { target_addr } => {
+            let addr = peek_u16_zero_page(target_addr);
+            let value = nes.peek_u8(addr);
+            format!(" (${:02X}) = {:04X} = {:02X}", target_addr, addr, value)
+        }
+        OpArg::Branch { addr_offset } => format!(" {:+}", addr_offset),
+    }
+}
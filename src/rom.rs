@@ -58,11 +58,131 @@ impl Rom {
     }
 }
 
+// An NSF music file, which contains the code and data for a set of songs,
+// rather than a full game. See:
+// - https://wiki.nesdev.com/w/index.php/NSF
+#[derive(Debug, Clone)]
+pub struct Nsf {
+    pub header: NsfHeader,
+    pub data: Vec<u8>,
+}
+
+impl Nsf {
+    pub fn from_bytes(mut bytes: impl Iterator<Item = u8>) -> Result<Self, RomError> {
+        let mut bytes = &mut bytes;
+
+        let header = NsfHeader::from_bytes(&mut bytes)?;
+        if header.extra_sound_chips != 0 {
+            return Err(RomError::UnsupportedNsfSoundChips(header.extra_sound_chips));
+        }
+        if header.load_addr < 0x8000 {
+            return Err(RomError::UnsupportedNsfLoadAddr(header.load_addr));
+        }
+
+        let data: Vec<_> = bytes.collect();
+        if data.is_empty() {
+            return Err(RomError::UnexpectedEof);
+        }
+
+        Ok(Nsf { header, data })
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct NsfHeader {
+    pub version: u8,
+    pub total_songs: u8,
+    pub starting_song: u8,
+    pub load_addr: u16,
+    pub init_addr: u16,
+    pub play_addr: u16,
+    pub song_name: String,
+    pub artist: String,
+    pub copyright: String,
+    pub ntsc_play_speed: u16,
+    pub bankswitch_init: [u8; 8],
+    pub pal_play_speed: u16,
+    pub tv_system: TvSystem,
+    pub extra_sound_chips: u8,
+}
+
+impl NsfHeader {
+    fn from_bytes(mut bytes: impl Iterator<Item = u8>) -> Result<Self, RomError> {
+        let expected_magic_str = b"NESM\x1A".iter().cloned();
+        let actual_magic_str = (&mut bytes).take(5);
+        if expected_magic_str.ne(actual_magic_str) {
+            return Err(RomError::InvalidHeader);
+        }
+
+        let header: Vec<_> = bytes.take(0x80 - 5).collect();
+        if header.len() != 0x80 - 5 {
+            return Err(RomError::UnexpectedEof);
+        }
+
+        // Offsets are relative to the end of the magic string
+        let read_u16 = |offset: usize| header[offset] as u16 | ((header[offset + 1] as u16) << 8);
+        let read_str = |offset: usize| {
+            let field = &header[offset..offset + 32];
+            let len = field.iter().position(|&byte| byte == 0).unwrap_or(32);
+            String::from_utf8_lossy(&field[..len]).into_owned()
+        };
+
+        let version = header[0x00];
+        let total_songs = header[0x01];
+        let starting_song = header[0x02];
+        let load_addr = read_u16(0x03);
+        let init_addr = read_u16(0x05);
+        let play_addr = read_u16(0x07);
+        let song_name = read_str(0x09);
+        let artist = read_str(0x29);
+        let copyright = read_str(0x49);
+        let ntsc_play_speed = read_u16(0x69);
+        let mut bankswitch_init = [0; 8];
+        bankswitch_init.copy_from_slice(&header[0x6B..0x73]);
+        let pal_play_speed = read_u16(0x73);
+        let flag_tv_system = header[0x75];
+        let extra_sound_chips = header[0x76];
+
+        let tv_system = match (flag_tv_system & 0b_0000_0001, flag_tv_system & 0b_0000_0010) {
+            (0, 0) => TvSystem::Ntsc,
+            (_, 0) => TvSystem::Pal,
+            (_, _) => TvSystem::Dual,
+        };
+
+        Ok(NsfHeader {
+            version,
+            total_songs,
+            starting_song,
+            load_addr,
+            init_addr,
+            play_addr,
+            song_name,
+            artist,
+            copyright,
+            ntsc_play_speed,
+            bankswitch_init,
+            pal_play_speed,
+            tv_system,
+            extra_sound_chips,
+        })
+    }
+
+    // Returns true if the NSF uses bankswitching, which is determined by
+    // whether any of the initial bank values are non-zero
+    pub fn is_bankswitched(&self) -> bool {
+        self.bankswitch_init.iter().any(|&bank| bank != 0)
+    }
+}
+
 #[derive(Debug)]
 pub enum RomError {
     InvalidHeader,
     UnexpectedEof,
     ExpectedEof,
+
+    // Valid NSF files that use features we can't play yet
+    UnsupportedNsfSoundChips(u8),
+    UnsupportedNsfLoadAddr(u16),
 }
 
 #[derive(Debug, Clone, Copy)]
@@ -191,10 +311,22 @@ impl RomHeader {
     }
 }
 
-#[derive(Debug, Clone, Copy)]
+// How the PPU's four nametables ($2000, $2400, $2800, and $2C00) map onto
+// its 2KiB of VRAM. Headers only specify horizontal, vertical, or
+// four-screen mirroring, but some mappers can switch modes at runtime
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum MirrorMode {
+    // $2000 = $2400 and $2800 = $2C00 (for games that scroll vertically)
     Horizontal,
+
+    // $2000 = $2800 and $2400 = $2C00 (for games that scroll horizontally)
     Vertical,
+
+    // Every nametable maps to the first (A) or second (B) 1KiB of VRAM
+    SingleScreenA,
+    SingleScreenB,
+
+    // The cartridge has 2KiB of extra VRAM, so each nametable is separate
     FourScreenVram,
 }
 
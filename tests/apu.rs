@@ -0,0 +1,410 @@
+#![feature(generator_trait, exhaustive_patterns, never_type)]
+
+use std::ops::{Generator, GeneratorState};
+use std::pin::Pin;
+
+use lochnes::nes::apu::ApuChannel;
+use lochnes::nes::cpu::{CpuStep, IrqSources};
+use lochnes::nes::NesStep;
+use lochnes::{audio, input, nes, rom, video};
+
+mod common;
+
+use common::{idle_loop_rom, null_io, run_cpu_cycles, TestRom};
+
+#[test]
+fn apu_status_reports_length_counters() {
+    let io = null_io();
+    let nes = nes::Nes::new(&io, idle_loop_rom());
+
+    // Length counters can't be loaded while the channel is disabled
+    nes.write_u8(0x4003, 0b_0000_1000);
+    assert_eq!(nes.read_u8(0x4015) & 0b_0000_0001, 0);
+
+    nes.write_u8(0x4015, 0b_0000_0001);
+    nes.write_u8(0x4003, 0b_0000_1000);
+    assert_eq!(nes.read_u8(0x4015) & 0b_0000_0001, 0b_0000_0001);
+
+    // Disabling the channel clears its length counter
+    nes.write_u8(0x4015, 0b_0000_0000);
+    assert_eq!(nes.read_u8(0x4015) & 0b_0000_0001, 0);
+}
+
+#[test]
+fn apu_frame_interrupt_flag() {
+    let io = null_io();
+    let nes = nes::Nes::new(&io, idle_loop_rom());
+    let mut run_nes = nes.run();
+
+    // 4-step mode with IRQs enabled sets the frame interrupt flag once per
+    // frame, and reading $4015 clears it
+    nes.write_u8(0x4017, 0b_0000_0000);
+    run_cpu_cycles(&mut run_nes, 30_000);
+    assert_eq!(nes.read_u8(0x4015) & 0b_0100_0000, 0b_0100_0000);
+    assert_eq!(nes.read_u8(0x4015) & 0b_0100_0000, 0);
+
+    // The flag is never set when IRQs are inhibited
+    nes.write_u8(0x4017, 0b_0100_0000);
+    run_cpu_cycles(&mut run_nes, 30_000);
+    assert_eq!(nes.read_u8(0x4015) & 0b_0100_0000, 0);
+}
+
+#[test]
+fn apu_frame_counter_clocks_channels() {
+    let io = null_io();
+    let nes = nes::Nes::new(&io, idle_loop_rom());
+    let mut run_nes = nes.run();
+    let pulse = &nes.apu.pulse_1;
+
+    // Start pulse 1's envelope (with the shortest period), and load its
+    // length counter with 10
+    nes.write_u8(0x4015, 0b_0000_0001);
+    nes.write_u8(0x4000, 0b_0000_0000);
+    nes.write_u8(0x4003, 0b_0000_0000);
+    assert_eq!(pulse.length_counter.counter(), 10);
+
+    // The sequencer restarts a few cycles after $4017 is written. In
+    // 4-step mode, every quarter frame clocks the envelope (which starts
+    // at 15, then decays), and every other one also clocks the length
+    // counter
+    nes.write_u8(0x4017, 0b_0000_0000);
+    run_cpu_cycles(&mut run_nes, 10 + 7_457);
+    assert_eq!(pulse.envelope.volume(), 15);
+    assert_eq!(pulse.length_counter.counter(), 10);
+    run_cpu_cycles(&mut run_nes, 14_913 - 7_457);
+    assert_eq!(pulse.envelope.volume(), 14);
+    assert_eq!(pulse.length_counter.counter(), 9);
+    run_cpu_cycles(&mut run_nes, 29_830 - 14_913);
+    assert_eq!(pulse.envelope.volume(), 12);
+    assert_eq!(pulse.length_counter.counter(), 8);
+
+    // Switching to 5-step mode clocks a half frame straight away
+    nes.write_u8(0x4017, 0b_1000_0000);
+    run_cpu_cycles(&mut run_nes, 10);
+    assert_eq!(pulse.envelope.volume(), 11);
+    assert_eq!(pulse.length_counter.counter(), 7);
+
+    // The 5-step sequence is longer, with 4 quarter frames and 2 half
+    // frames
+    run_cpu_cycles(&mut run_nes, 37_282);
+    assert_eq!(pulse.envelope.volume(), 7);
+    assert_eq!(pulse.length_counter.counter(), 5);
+}
+
+// Run for `cycles` CPU cycles, collecting a channel's output after each one
+fn channel_outputs(
+    run_nes: &mut (impl Generator<Yield = NesStep, Return = !> + Unpin),
+    cycles: u32,
+    output: impl Fn() -> u8,
+) -> Vec<u8> {
+    (0..cycles)
+        .map(|_| {
+            run_cpu_cycles(run_nes, 1);
+            output()
+        })
+        .collect()
+}
+
+#[test]
+fn apu_pulse_registers() {
+    let io = null_io();
+    let nes = nes::Nes::new(&io, idle_loop_rom());
+    let mut run_nes = nes.run();
+    let pulse = &nes.apu.pulse_2;
+
+    // Play pulse 2 with a 25% duty cycle, a halted length counter, and a
+    // constant volume of 10. With a timer period of 100, each step of the
+    // sequence takes 202 CPU cycles
+    nes.write_u8(0x4015, 0b_0000_0010);
+    nes.write_u8(0x4004, 0b_0111_1010);
+    nes.write_u8(0x4006, 100);
+    nes.write_u8(0x4007, 0b_0000_1000);
+    assert_eq!(pulse.length_counter.counter(), 254);
+
+    let outputs = channel_outputs(&mut run_nes, 202 * 8 * 4, || pulse.output());
+    assert!(outputs.iter().all(|&output| output == 0 || output == 10));
+    let high_outputs = outputs.iter().filter(|&&output| output == 10).count();
+    assert!((202 * 2 * 4 - 202..=202 * 2 * 4 + 202).contains(&high_outputs));
+
+    // A halted length counter doesn't count down
+    run_cpu_cycles(&mut run_nes, 29_830 * 2);
+    assert_eq!(pulse.length_counter.counter(), 254);
+
+    // Otherwise, it counts down twice per frame
+    nes.write_u8(0x4004, 0b_0101_1010);
+    run_cpu_cycles(&mut run_nes, 29_830 * 2);
+    assert_eq!(pulse.length_counter.counter(), 250);
+
+    // Timer periods below 8 mute the channel
+    nes.write_u8(0x4006, 7);
+    let outputs = channel_outputs(&mut run_nes, 16 * 8, || pulse.output());
+    assert!(outputs.iter().all(|&output| output == 0));
+}
+
+#[test]
+fn apu_pulse_square_wave() {
+    const SAMPLE_RATE: u32 = 44_100;
+
+    let io = nes::NesIoWith {
+        video: video::NullVideo,
+        input: input::NullInput,
+        audio: audio::BufferedAudio::new(SAMPLE_RATE),
+    };
+    let nes = nes::Nes::new(&io, idle_loop_rom());
+    let mut run_nes = nes.run();
+
+    // Play pulse 1 with a 50% duty cycle at a constant volume, using a
+    // timer period of 253 (1_789_773 / (16 * (253 + 1)) = ~440Hz)
+    nes.write_u8(0x4015, 0b_0000_0001);
+    nes.write_u8(0x4000, 0b_1011_1111);
+    nes.write_u8(0x4002, 253);
+    nes.write_u8(0x4003, 0b_1111_1000);
+
+    // Run for about a second
+    run_cpu_cycles(&mut run_nes, 1_789_773);
+
+    let samples = io.audio.samples();
+    let mean = samples.iter().sum::<f32>() / samples.len() as f32;
+    // Skip the initial spike while the high-pass filters settle
+    let peak = samples[SAMPLE_RATE as usize / 10..]
+        .iter()
+        .map(|sample| (sample - mean).abs())
+        .fold(0.0, f32::max);
+
+    // Count rising edges with some hysteresis, so that ringing from the
+    // band-limited steps and droop from the high-pass filters don't count
+    // as extra edges
+    let mut is_high = false;
+    let mut rising_edges = 0;
+    for &sample in &samples {
+        if !is_high && sample > mean + peak / 2.0 {
+            is_high = true;
+            rising_edges += 1;
+        } else if is_high && sample < mean - peak / 2.0 {
+            is_high = false;
+        }
+    }
+
+    // The resampler holds back a few samples while it's still adding
+    // band-limited steps to them
+    assert!((SAMPLE_RATE - 20..=SAMPLE_RATE + 10).contains(&(samples.len() as u32)));
+    assert!((435..=445).contains(&rising_edges));
+}
+
+#[test]
+fn apu_channel_controls_only_affect_output() {
+    let io = nes::NesIoWith {
+        video: video::NullVideo,
+        input: input::NullInput,
+        audio: audio::BufferedAudio::new(44_100),
+    };
+    let nes = nes::Nes::new(&io, idle_loop_rom());
+    let mut run_nes = nes.run();
+
+    nes.write_u8(0x4015, 0b_0000_0001);
+    nes.write_u8(0x4000, 0b_1011_1111);
+    nes.write_u8(0x4002, 253);
+    nes.write_u8(0x4003, 0b_1111_1000);
+
+    let peak = |samples: Vec<f32>| {
+        samples
+            .iter()
+            .map(|sample| sample.abs())
+            .fold(0.0, f32::max)
+    };
+
+    // Skip the initial spike while the high-pass filters settle
+    run_cpu_cycles(&mut run_nes, 100_000);
+    io.audio.take_samples();
+    run_cpu_cycles(&mut run_nes, 100_000);
+    let full_peak = peak(io.audio.take_samples());
+
+    // Soloing another channel silences pulse 1
+    nes.apu.set_solo(ApuChannel::Triangle, true);
+    run_cpu_cycles(&mut run_nes, 100_000);
+    io.audio.take_samples();
+    run_cpu_cycles(&mut run_nes, 100_000);
+    assert!(peak(io.audio.take_samples()) < full_peak / 100.0);
+
+    // Muting pulse 1 silences it, but it keeps running
+    nes.apu.reset_channel_controls();
+    nes.apu.set_muted(ApuChannel::Pulse1, true);
+    run_cpu_cycles(&mut run_nes, 100_000);
+    io.audio.take_samples();
+    run_cpu_cycles(&mut run_nes, 100_000);
+    assert!(peak(io.audio.take_samples()) < full_peak / 100.0);
+    assert_eq!(nes.read_u8(0x4015) & 0b_0000_0001, 0b_0000_0001);
+
+    // Halving the volume roughly halves the output
+    nes.apu.set_muted(ApuChannel::Pulse1, false);
+    nes.apu.set_volume(ApuChannel::Pulse1, 0.5);
+    run_cpu_cycles(&mut run_nes, 100_000);
+    io.audio.take_samples();
+    run_cpu_cycles(&mut run_nes, 100_000);
+    let half_peak = peak(io.audio.take_samples());
+    assert!(half_peak > full_peak * 0.4 && half_peak < full_peak * 0.6);
+}
+
+#[test]
+fn apu_triangle_registers() {
+    let io = null_io();
+    let nes = nes::Nes::new(&io, idle_loop_rom());
+    let mut run_nes = nes.run();
+    let triangle = &nes.apu.triangle;
+
+    // Play the triangle with a halted length counter and the longest
+    // linear counter. With a timer period of 10, each step of the sequence
+    // takes 11 CPU cycles
+    nes.write_u8(0x4015, 0b_0000_0100);
+    nes.write_u8(0x4008, 0b_1111_1111);
+    nes.write_u8(0x400A, 10);
+    nes.write_u8(0x400B, 0b_0000_1000);
+    assert_eq!(triangle.length_counter.counter(), 254);
+
+    // The linear counter gets loaded on the next quarter frame. After
+    // that, the output steps through every level
+    run_cpu_cycles(&mut run_nes, 7_460);
+    let mut outputs = channel_outputs(&mut run_nes, 11 * 32, || triangle.output());
+    outputs.sort();
+    outputs.dedup();
+    assert_eq!(outputs, (0..=15).collect::<Vec<_>>());
+
+    // Reloading the linear counter with 0 freezes the sequencer, so the
+    // output holds its current level
+    nes.write_u8(0x4008, 0b_0000_0000);
+    nes.write_u8(0x400B, 0b_0000_1000);
+    run_cpu_cycles(&mut run_nes, 7_460);
+    let outputs = channel_outputs(&mut run_nes, 11 * 32, || triangle.output());
+    assert!(outputs.iter().all(|&output| output == outputs[0]));
+}
+
+#[test]
+fn apu_noise_registers() {
+    let io = null_io();
+    let nes = nes::Nes::new(&io, idle_loop_rom());
+    let mut run_nes = nes.run();
+    let noise = &nes.apu.noise;
+
+    // Play noise with a halted length counter, a constant volume of 5, and
+    // the shortest period (4 CPU cycles)
+    nes.write_u8(0x4015, 0b_0000_1000);
+    nes.write_u8(0x400C, 0b_0011_0101);
+    nes.write_u8(0x400E, 0b_0000_0000);
+    nes.write_u8(0x400F, 0b_0000_1000);
+    assert_eq!(noise.length_counter.counter(), 254);
+
+    let outputs = channel_outputs(&mut run_nes, 4 * 1_000, || noise.output());
+    assert!(outputs.iter().all(|&output| output == 0 || output == 5));
+    let high_outputs = outputs.iter().filter(|&&output| output == 5).count();
+    assert!((1_500..=2_500).contains(&high_outputs));
+
+    // In the normal mode, the sequence is 32,767 steps long, but in short
+    // mode it repeats after 93 steps (or 31, depending on where it starts)
+    let is_periodic = |outputs: &[u8]| {
+        let period = 4 * 93;
+        (0..period).all(|index| outputs[index] == outputs[index + period])
+    };
+    assert!(!is_periodic(&outputs));
+    nes.write_u8(0x400E, 0b_1000_0000);
+    run_cpu_cycles(&mut run_nes, 4 * 93);
+    let outputs = channel_outputs(&mut run_nes, 4 * 93 * 2, || noise.output());
+    assert!(outputs.contains(&5));
+    assert!(is_periodic(&outputs));
+
+    // Disabling the channel clears its length counter, silencing it
+    nes.write_u8(0x4015, 0b_0000_0000);
+    assert_eq!(noise.length_counter.counter(), 0);
+    let outputs = channel_outputs(&mut run_nes, 4 * 93, || noise.output());
+    assert!(outputs.iter().all(|&output| output == 0));
+}
+
+// A ROM that loops forever (like `idle_loop_rom`), with `sample` at $E000
+// for the DMC to play
+fn dmc_sample_rom(sample: &[u8]) -> rom::Rom {
+    let mut program = vec![0xEA; 0x2000 + sample.len()];
+    program[0x0000..0x0003].copy_from_slice(&[0x4C, 0x00, 0x80]);
+    program[0x2000..].copy_from_slice(sample);
+
+    TestRom::new(&program).build()
+}
+
+#[test]
+fn apu_dmc_fetches_and_plays_samples() {
+    let io = null_io();
+    let nes = nes::Nes::new(&io, dmc_sample_rom(&[0xFF]));
+    let mut run_nes = nes.run();
+
+    run_cpu_cycles(&mut run_nes, 100);
+
+    // Play the 1-byte sample at $E000 at the fastest rate, starting from
+    // an output level of 64
+    nes.write_u8(0x4010, 0b_0000_1111);
+    nes.write_u8(0x4011, 64);
+    nes.write_u8(0x4012, 0x80);
+    nes.write_u8(0x4013, 0x00);
+    nes.write_u8(0x4015, 0b_0001_0000);
+    assert_eq!(nes.read_u8(0x4015) & 0b_0001_0000, 0b_0001_0000);
+
+    // The sample buffer is empty, so the byte gets fetched straight away.
+    // The CPU is halted for 3 or 4 cycles in between two of the 3-cycle
+    // `JMP`s
+    let mut op_cycles = vec![];
+    while op_cycles.len() < 6 {
+        match Pin::new(&mut run_nes).resume(()) {
+            GeneratorState::Yielded(NesStep::Cpu(CpuStep::Op(op))) => {
+                op_cycles.push(op.cycle);
+            }
+            GeneratorState::Yielded(_) => {}
+        }
+    }
+    let mut durations: Vec<_> = op_cycles.windows(2).map(|ops| ops[1] - ops[0]).collect();
+    durations.sort();
+    assert_eq!(&durations[..4], &[3, 3, 3, 3]);
+    assert!((6..=7).contains(&durations[4]));
+    assert_eq!(nes.apu.dmc.bytes_remaining(), 0);
+    assert_eq!(nes.read_u8(0x4015) & 0b_0001_0000, 0);
+
+    // After the current (silent) output cycle, each of the sample's 8 bits
+    // raises the output level by 2
+    assert_eq!(nes.apu.dmc.output(), 64);
+    run_cpu_cycles(&mut run_nes, 54 * 8 * 3);
+    assert_eq!(nes.apu.dmc.output(), 80);
+}
+
+#[test]
+fn apu_dmc_irq_and_looping() {
+    let io = null_io();
+    let nes = nes::Nes::new(&io, dmc_sample_rom(&[0xFF; 17]));
+    let mut run_nes = nes.run();
+
+    // The DMC sets its interrupt flag after fetching the last byte of a
+    // sample, if IRQs are enabled
+    nes.write_u8(0x4010, 0b_1000_1111);
+    nes.write_u8(0x4012, 0x80);
+    nes.write_u8(0x4013, 0x00);
+    nes.write_u8(0x4015, 0b_0001_0000);
+    run_cpu_cycles(&mut run_nes, 10);
+    assert!(nes.cpu.irq.get().contains(IrqSources::APU_DMC));
+
+    // Reading $4015 doesn't clear the flag, but writing it does
+    assert_eq!(nes.read_u8(0x4015) & 0b_1001_0000, 0b_1000_0000);
+    assert_eq!(nes.read_u8(0x4015) & 0b_1001_0000, 0b_1000_0000);
+    nes.write_u8(0x4015, 0b_0000_0000);
+    assert_eq!(nes.read_u8(0x4015) & 0b_1001_0000, 0);
+    run_cpu_cycles(&mut run_nes, 1);
+    assert!(!nes.cpu.irq.get().contains(IrqSources::APU_DMC));
+
+    // A looping sample restarts instead, so it keeps playing without
+    // setting the interrupt flag
+    nes.write_u8(0x4010, 0b_1100_1111);
+    nes.write_u8(0x4011, 0);
+    nes.write_u8(0x4013, 0x01);
+    nes.write_u8(0x4015, 0b_0001_0000);
+    run_cpu_cycles(&mut run_nes, 54 * 8 * 100);
+    assert_eq!(nes.read_u8(0x4015) & 0b_1001_0000, 0b_0001_0000);
+    assert_eq!(nes.apu.dmc.output(), 126);
+
+    // Disabling the DMC clears its remaining bytes
+    nes.write_u8(0x4015, 0b_0000_0000);
+    assert_eq!(nes.read_u8(0x4015) & 0b_0001_0000, 0);
+}
@@ -0,0 +1,30 @@
+use std::io::Cursor;
+
+use lochnes::audio::{self, Audio};
+
+#[test]
+fn wav_recorder_writes_pcm_samples() {
+    let recorder = audio::WavRecorder::new(Cursor::new(vec![]), 48_000).unwrap();
+    recorder.queue_sample(0.0);
+    recorder.queue_sample(1.0);
+    recorder.flush().unwrap();
+    recorder.queue_sample(-2.0);
+    recorder.flush().unwrap();
+
+    let wav = recorder.into_inner().into_inner();
+    assert_eq!(wav.len(), 44 + 6);
+
+    assert_eq!(&wav[0..4], b"RIFF");
+    assert_eq!(&wav[4..8], &42_u32.to_le_bytes());
+    assert_eq!(&wav[8..16], b"WAVEfmt ");
+    assert_eq!(&wav[22..24], &1_u16.to_le_bytes());
+    assert_eq!(&wav[24..28], &48_000_u32.to_le_bytes());
+    assert_eq!(&wav[34..36], &16_u16.to_le_bytes());
+    assert_eq!(&wav[36..40], b"data");
+    assert_eq!(&wav[40..44], &6_u32.to_le_bytes());
+
+    // Samples are clamped to between -1.0 and 1.0
+    assert_eq!(&wav[44..46], &0_i16.to_le_bytes());
+    assert_eq!(&wav[46..48], &i16::max_value().to_le_bytes());
+    assert_eq!(&wav[48..50], &(-i16::max_value()).to_le_bytes());
+}
@@ -0,0 +1,152 @@
+// Helpers shared between the integration tests. Each test only uses some
+// of them, so the rest would be reported as dead code
+#![allow(dead_code)]
+
+use std::ops::{Generator, GeneratorState};
+use std::pin::Pin;
+
+use lochnes::nes::cpu::CpuStep;
+use lochnes::nes::NesStep;
+use lochnes::{audio, input, nes, rom, video};
+
+pub type NullIo = nes::NesIoWith<video::NullVideo, input::NullInput, audio::NullAudio>;
+
+pub fn null_io() -> NullIo {
+    nes::NesIoWith {
+        video: video::NullVideo,
+        input: input::NullInput,
+        audio: audio::NullAudio,
+    }
+}
+
+// Builds an NROM image with a program at $8000, which the reset and NMI
+// vectors point to. The IRQ vector points to $9000, which just returns
+// (with RTI) unless another IRQ handler is loaded there. Unused PRG-ROM
+// is filled with NOPs, and CHR-ROM is blank
+pub struct TestRom {
+    prg_rom: Vec<u8>,
+    chr_rom: Vec<u8>,
+    flags_6: u8,
+}
+
+impl TestRom {
+    pub fn new(program: &[u8]) -> Self {
+        let mut prg_rom = vec![0xEA; 0x4000];
+        prg_rom[0x0000..program.len()].copy_from_slice(program);
+        prg_rom[0x1000] = 0x40;
+        prg_rom[0x3FFA..0x4000].copy_from_slice(&[0x00, 0x80, 0x00, 0x80, 0x00, 0x90]);
+
+        TestRom {
+            prg_rom,
+            chr_rom: vec![0x00; 0x2000],
+            flags_6: 0,
+        }
+    }
+
+    pub fn with_irq_handler(mut self, irq_handler: &[u8]) -> Self {
+        self.prg_rom[0x1000..0x1000 + irq_handler.len()].copy_from_slice(irq_handler);
+        self
+    }
+
+    // Replace the 8KB of CHR-ROM
+    pub fn with_chr_rom(mut self, chr_rom: Vec<u8>) -> Self {
+        assert_eq!(chr_rom.len(), 0x2000);
+        self.chr_rom = chr_rom;
+        self
+    }
+
+    // Set the header's flags 6 byte (which holds the mirroring bits)
+    pub fn with_flags_6(mut self, flags_6: u8) -> Self {
+        self.flags_6 = flags_6;
+        self
+    }
+
+    pub fn build(self) -> rom::Rom {
+        let header = [
+            b'N',
+            b'E',
+            b'S',
+            0x1A,
+            1,
+            1,
+            self.flags_6,
+            0,
+            0,
+            0,
+            0,
+            0,
+            0,
+            0,
+            0,
+            0,
+        ];
+
+        let bytes = header
+            .iter()
+            .cloned()
+            .chain(self.prg_rom)
+            .chain(self.chr_rom);
+        rom::Rom::from_bytes(bytes).expect("Failed to build test ROM")
+    }
+}
+
+pub fn test_rom(program: &[u8]) -> rom::Rom {
+    TestRom::new(program).build()
+}
+
+// A ROM whose program just loops forever (`JMP $8000`), so that tests can
+// poke registers directly while the NES runs
+pub fn idle_loop_rom() -> rom::Rom {
+    test_rom(&[0x4C, 0x00, 0x80])
+}
+
+// Run until the CPU finishes its next instruction, returning the address
+// of that instruction
+pub fn run_instruction(run_nes: &mut (impl Generator<Yield = NesStep, Return = !> + Unpin)) -> u16 {
+    let (pc, _) = run_timed_instruction(run_nes);
+    pc
+}
+
+// Run until the CPU finishes its next instruction, returning the number of
+// cycles it took (including any interrupt sequence or DMA before it)
+pub fn count_instruction_cycles(
+    run_nes: &mut (impl Generator<Yield = NesStep, Return = !> + Unpin),
+) -> u32 {
+    let (_, cycles) = run_timed_instruction(run_nes);
+    cycles
+}
+
+// Run until the CPU finishes its next instruction, returning the address
+// of that instruction and the number of cycles it took (including any
+// interrupt sequence or DMA before it)
+pub fn run_timed_instruction(
+    run_nes: &mut (impl Generator<Yield = NesStep, Return = !> + Unpin),
+) -> (u16, u32) {
+    let mut cycles = 0;
+    loop {
+        match Pin::new(&mut *run_nes).resume(()) {
+            GeneratorState::Yielded(NesStep::Cpu(CpuStep::Cycle)) => {
+                cycles += 1;
+            }
+            GeneratorState::Yielded(NesStep::Cpu(CpuStep::Op(op))) => {
+                return (op.pc, cycles);
+            }
+            GeneratorState::Yielded(_) => {}
+        }
+    }
+}
+
+pub fn run_cpu_cycles(
+    run_nes: &mut (impl Generator<Yield = NesStep, Return = !> + Unpin),
+    cycles: u32,
+) {
+    let mut cycle = 0;
+    while cycle < cycles {
+        match Pin::new(&mut *run_nes).resume(()) {
+            GeneratorState::Yielded(NesStep::Cpu(CpuStep::Cycle)) => {
+                cycle += 1;
+            }
+            GeneratorState::Yielded(_) => {}
+        }
+    }
+}
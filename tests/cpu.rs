@@ -0,0 +1,394 @@
+#![feature(generator_trait, exhaustive_patterns, never_type)]
+
+use std::ops::{Generator, GeneratorState};
+use std::pin::Pin;
+
+use lochnes::nes;
+use lochnes::nes::cpu::{CpuStep, IrqSources};
+use lochnes::nes::NesStep;
+
+mod common;
+
+use common::{
+    count_instruction_cycles, null_io, run_cpu_cycles, run_instruction, test_rom, TestRom,
+};
+
+#[test]
+fn cpu_irq_after_cli_latency() {
+    let io = null_io();
+    let program = [
+        0x58, //             CLI
+        0xEA, //             NOP
+        0x4C, 0x02, 0x80, // JMP $8002
+    ];
+    let irq_handler = [
+        0x40, // RTI
+    ];
+    let nes = nes::Nes::new(
+        &io,
+        TestRom::new(&program)
+            .with_irq_handler(&irq_handler)
+            .build(),
+    );
+    let mut run_nes = nes.run();
+
+    nes.cpu.set_irq(IrqSources::MAPPER, true);
+
+    // The IRQ is only taken after the instruction following CLI
+    assert_eq!(run_instruction(&mut run_nes), 0x8000);
+    assert_eq!(run_instruction(&mut run_nes), 0x8001);
+    assert_eq!(run_instruction(&mut run_nes), 0x9000);
+
+    // The pushed status has `B` cleared and `U` set, and the return
+    // address points after the NOP
+    assert_eq!(nes.read_u8(0x01FB) & 0b_0011_0000, 0b_0010_0000);
+    assert_eq!(nes.read_u16(0x01FC), 0x8002);
+
+    // RTI restores `I`, so the IRQ fires again until it's released. The
+    // IRQ was already polled during the second RTI, so the handler runs
+    // once more after releasing it
+    assert_eq!(run_instruction(&mut run_nes), 0x9000);
+    nes.cpu.set_irq(IrqSources::MAPPER, false);
+    assert_eq!(run_instruction(&mut run_nes), 0x9000);
+    assert_eq!(run_instruction(&mut run_nes), 0x8002);
+    assert_eq!(run_instruction(&mut run_nes), 0x8002);
+}
+
+#[test]
+fn cpu_irq_sequence_takes_7_cycles() {
+    let io = null_io();
+    let program = [
+        0x58, //             CLI
+        0x4C, 0x01, 0x80, // JMP $8001
+    ];
+    let irq_handler = [
+        0x40, // RTI
+    ];
+    let nes = nes::Nes::new(
+        &io,
+        TestRom::new(&program)
+            .with_irq_handler(&irq_handler)
+            .build(),
+    );
+    let mut run_nes = nes.run();
+
+    assert_eq!(run_instruction(&mut run_nes), 0x8000);
+    nes.cpu.set_irq(IrqSources::MAPPER, true);
+    assert_eq!(run_instruction(&mut run_nes), 0x8001);
+
+    // 7 cycles for the interrupt sequence, then 6 cycles for RTI
+    assert_eq!(count_instruction_cycles(&mut run_nes), 13);
+}
+
+#[test]
+fn cpu_nmi_hijacks_brk() {
+    let io = null_io();
+    let program = [
+        0xEA, //       NOP
+        0x00, 0x00, // BRK
+    ];
+    let nes = nes::Nes::new(&io, test_rom(&program));
+    let mut run_nes = nes.run();
+
+    assert_eq!(run_instruction(&mut run_nes), 0x8000);
+
+    // Raise an NMI partway through BRK, before the status is pushed
+    run_cpu_cycles(&mut run_nes, 3);
+    nes.cpu.nmi.set(true);
+    assert_eq!(run_instruction(&mut run_nes), 0x8001);
+
+    // BRK jumps to the NMI handler (at $8000) instead of the IRQ handler,
+    // but still pushes the status with `B` set
+    assert_eq!(run_instruction(&mut run_nes), 0x8000);
+    assert_eq!(nes.read_u8(0x01FB) & 0b_0011_0000, 0b_0011_0000);
+    assert_eq!(nes.read_u16(0x01FC), 0x8003);
+    assert!(!nes.cpu.nmi.get());
+}
+
+#[test]
+fn cpu_irq_from_apu_frame_counter() {
+    let io = null_io();
+    let program = [
+        0xA9, 0x00, //       LDA #$00
+        0x8D, 0x17, 0x40, // STA $4017
+        0x58, //             CLI
+        0x4C, 0x06, 0x80, // JMP $8006
+    ];
+    let irq_handler = [
+        0xE6, 0x00, //       INC $00
+        0xAD, 0x15, 0x40, // LDA $4015
+        0x40, //             RTI
+    ];
+    let nes = nes::Nes::new(
+        &io,
+        TestRom::new(&program)
+            .with_irq_handler(&irq_handler)
+            .build(),
+    );
+    let mut run_nes = nes.run();
+
+    // Run for a bit over 3 frames. Reading $4015 acknowledges each IRQ
+    for _ in 0..35_000 {
+        run_instruction(&mut run_nes);
+    }
+
+    assert_eq!(nes.read_u8(0x0000), 3);
+}
+
+#[test]
+fn cpu_irq_ignored_when_interrupts_disabled() {
+    let io = null_io();
+    let program = [
+        0x78, //             SEI
+        0x4C, 0x01, 0x80, // JMP $8001
+    ];
+    let irq_handler = [
+        0x40, // RTI
+    ];
+    let nes = nes::Nes::new(
+        &io,
+        TestRom::new(&program)
+            .with_irq_handler(&irq_handler)
+            .build(),
+    );
+    let mut run_nes = nes.run();
+
+    nes.cpu.set_irq(IrqSources::MAPPER, true);
+
+    for _ in 0..100 {
+        assert_ne!(run_instruction(&mut run_nes), 0x9000);
+    }
+}
+
+#[test]
+fn cpu_reset_and_power_cycle() {
+    let io = null_io();
+    let program = [
+        0x58, //             CLI
+        0xE6, 0x00, //       INC $00
+        0x4C, 0x01, 0x80, // JMP $8001
+    ];
+    let nes = nes::Nes::new(&io, test_rom(&program));
+    let mut run_nes = nes.run();
+
+    for _ in 0..10 {
+        run_instruction(&mut run_nes);
+    }
+    assert_ne!(nes.read_u8(0x0000), 0);
+
+    // Reset decrements S by 3 and sets `I`, but leaves RAM alone
+    nes.reset();
+    assert_eq!(run_instruction(&mut run_nes), 0x8000);
+    assert_eq!(nes.cpu.s.get(), 0xFA);
+    assert_ne!(nes.read_u8(0x0000), 0);
+
+    // Power cycling clears RAM, and S ends up back at $FD
+    nes.power_cycle();
+    assert_eq!(nes.read_u8(0x0000), 0);
+    assert_eq!(run_instruction(&mut run_nes), 0x8000);
+    assert_eq!(nes.cpu.s.get(), 0xFD);
+}
+
+#[test]
+fn cpu_unofficial_unstable_writes() {
+    let io = null_io();
+    let program = [
+        0xA2, 0x05, //       LDX #$05
+        0xA0, 0x01, //       LDY #$01
+        0x9E, 0x10, 0x02, // SHX $0210,Y
+        0x9E, 0xFF, 0x02, // SHX $02FF,Y
+        0x02, //             JAM
+    ];
+    let nes = nes::Nes::new(&io, test_rom(&program));
+    let mut run_nes = nes.run();
+
+    for _ in 0..4 {
+        run_instruction(&mut run_nes);
+    }
+
+    // SHX writes (X & (H + 1)), where H is the high byte of the base address
+    assert_eq!(nes.read_u8(0x0211), 0x05 & 0x03);
+
+    // When the index crosses a page, the written value also replaces the
+    // high byte of the target address
+    assert_eq!(nes.read_u8(0x0300), 0x00);
+    assert_eq!(nes.read_u8(0x0100), 0x05 & 0x03);
+}
+
+#[test]
+fn cpu_jam_halts_until_reset() {
+    let io = null_io();
+    let program = [
+        0x58, //       CLI
+        0xE6, 0x00, // INC $00
+        0x02, //       JAM
+    ];
+    let nes = nes::Nes::new(&io, test_rom(&program));
+    let mut run_nes = nes.run();
+
+    assert_eq!(run_instruction(&mut run_nes), 0x8000);
+    assert_eq!(run_instruction(&mut run_nes), 0x8001);
+
+    // The CPU stays stuck, even when an IRQ is asserted (and enabled)
+    nes.cpu.set_irq(IrqSources::MAPPER, true);
+    run_cpu_cycles(&mut run_nes, 1_000);
+    assert_eq!(nes.cpu.pc.get(), 0x8004);
+    assert_eq!(nes.read_u8(0x0000), 1);
+
+    // Resetting runs the reset sequence straight away, without running
+    // the IRQ that was seen while the CPU was stuck
+    let s = nes.cpu.s.get();
+    nes.reset();
+    nes.cpu.set_irq(IrqSources::MAPPER, false);
+    assert_eq!(run_instruction(&mut run_nes), 0x8003);
+    assert_eq!(run_instruction(&mut run_nes), 0x8000);
+    assert_eq!(nes.cpu.s.get(), s.wrapping_sub(3));
+    assert_eq!(run_instruction(&mut run_nes), 0x8001);
+    assert_eq!(nes.read_u8(0x0000), 2);
+}
+
+#[test]
+fn cpu_cycle_counter() {
+    let io = null_io();
+    let program = [
+        0xA9, 0x01, //       LDA #$01
+        0x85, 0x00, //       STA $00
+        0xAD, 0x00, 0x02, // LDA $0200
+        0x4C, 0x00, 0x80, // JMP $8000
+    ];
+    let nes = nes::Nes::new(&io, test_rom(&program));
+    let mut run_nes = nes.run();
+
+    // Each instruction starts where the last one ended, after the 7 cycles
+    // of the reset sequence
+    let mut ops = vec![];
+    while ops.len() < 5 {
+        match Pin::new(&mut run_nes).resume(()) {
+            GeneratorState::Yielded(NesStep::Cpu(CpuStep::Op(op))) => {
+                ops.push((op.pc, op.cycle));
+            }
+            GeneratorState::Yielded(_) => {}
+        }
+    }
+    assert_eq!(
+        ops,
+        [
+            (0x8000, 7),
+            (0x8002, 9),
+            (0x8004, 12),
+            (0x8007, 16),
+            (0x8000, 19),
+        ]
+    );
+    assert_eq!(nes.cpu.cycles.get(), 21);
+
+    // The PPU runs 3 dots per CPU cycle
+    assert_eq!(nes.ppu.frame.get(), 0);
+    assert_eq!(nes.ppu.scanline.get(), 0);
+    assert_eq!(nes.ppu.dot.get(), 63);
+
+    // A frame is 262 scanlines of 341 dots. The PPU runs after each CPU
+    // cycle, so it has run 29,999 * 3 = 89,997 dots by now, which is 655
+    // dots into the second frame (which skips its first dot)
+    run_cpu_cycles(&mut run_nes, 30_000 - 21);
+    assert_eq!(nes.cpu.cycles.get(), 30_000);
+    assert_eq!(nes.ppu.frame.get(), 1);
+    assert_eq!(nes.ppu.scanline.get(), 1);
+    assert_eq!(nes.ppu.dot.get(), 315);
+}
+
+#[test]
+fn cpu_instruction_cycle_counts() {
+    let io = null_io();
+    let mut program = vec![0xEA; 0x0120];
+    let code = [
+        0xA2, 0x01, //       LDX #$01
+        0xBD, 0x00, 0x02, // LDA $0200,X
+        0xBD, 0xFF, 0x02, // LDA $02FF,X
+        0xA0, 0x01, //       LDY #$01
+        0xB1, 0x10, //       LDA ($10),Y
+        0xB1, 0x12, //       LDA ($12),Y
+        0x48, //             PHA
+        0x68, //             PLA
+        0xEE, 0x00, 0x02, // INC $0200
+        0x20, 0x00, 0x81, // JSR $8100
+        0xD0, 0x04, //       BNE $801C
+        0xEA, 0xEA, 0xEA, 0xEA, // NOP x4
+        0x4C, 0xFD, 0x80, // JMP $80FD
+    ];
+    program[..code.len()].copy_from_slice(&code);
+    program[0x0100] = 0x60; // RTS
+    program[0x00FD..0x00FF].copy_from_slice(&[0xD0, 0x10]); // BNE $810F
+
+    let nes = nes::Nes::new(&io, test_rom(&program));
+    let mut run_nes = nes.run();
+
+    nes.write_u8(0x0010, 0x00);
+    nes.write_u8(0x0011, 0x02);
+    nes.write_u8(0x0012, 0xFF);
+    nes.write_u8(0x0013, 0x02);
+
+    // The first instruction also includes the reset sequence
+    assert_eq!(count_instruction_cycles(&mut run_nes), 7 + 2);
+
+    let mut cycles = vec![];
+    for _ in 0..12 {
+        cycles.push(count_instruction_cycles(&mut run_nes));
+    }
+
+    assert_eq!(
+        cycles,
+        [
+            4, // LDA abs,X
+            5, // LDA abs,X (crossing a page)
+            2, // LDY imm
+            5, // LDA (ind),Y
+            6, // LDA (ind),Y (crossing a page)
+            3, // PHA
+            4, // PLA
+            6, // INC abs
+            6, // JSR
+            6, // RTS
+            3, // BNE (taken)
+            3, // JMP abs
+        ]
+    );
+
+    // BNE (taken, crossing a page)
+    assert_eq!(count_instruction_cycles(&mut run_nes), 4);
+    assert_eq!(nes.cpu.pc.get(), 0x810F);
+}
+
+#[test]
+fn cpu_dummy_reads_and_writes() {
+    let io = null_io();
+    let program = [
+        0xA9, 0x20, //       LDA #$20
+        0x8D, 0x06, 0x20, // STA $2006
+        0xA9, 0x00, //       LDA #$00
+        0x8D, 0x06, 0x20, // STA $2006
+        0xA2, 0x10, //       LDX #$10
+        0xBD, 0xF7, 0x20, // LDA $20F7,X
+        0xEE, 0x06, 0x20, // INC $2006
+    ];
+    let nes = nes::Nes::new(&io, test_rom(&program));
+    let mut run_nes = nes.run();
+
+    for _ in 0..5 {
+        run_instruction(&mut run_nes);
+    }
+    assert_eq!(nes.ppu.vram_addr.get(), 0x2000);
+
+    // Indexing crosses a page, so the CPU first does a dummy read from
+    // $2007 before reading from $2107 (a mirror of $2007). Both reads
+    // advance the PPU address
+    assert_eq!(run_instruction(&mut run_nes), 0x800C);
+    assert_eq!(nes.ppu.vram_addr.get(), 0x2002);
+
+    // Read-modify-write instructions write the original value back before
+    // writing the modified value. $2006 is write-only, so the read returns
+    // the PPU's latch ($00, from the last PPUDATA read), then $00 and $01
+    // both get written to PPUADDR
+    assert_eq!(run_instruction(&mut run_nes), 0x800F);
+    assert_eq!(nes.ppu.vram_addr.get(), 0x0001);
+}
@@ -0,0 +1,65 @@
+use lochnes::nes::cpu::{disassemble, CpuVariant, Instruction, OpArg};
+
+#[test]
+fn disasm_decodes_ops() {
+    let bytes = [
+        0xA9, 0x00, //       LDA #$00
+        0x9D, 0x00, 0x02, // STA $0200,X
+        0xE8, //             INX
+        0xD0, 0xF8, //       BNE $8000
+        0x02, //             JAM
+        0x4C, 0x00, //       JMP (truncated)
+    ];
+    let ops: Vec<_> = disassemble(&bytes, 0x8000).collect();
+
+    let addrs: Vec<_> = ops.iter().map(|op| op.addr).collect();
+    assert_eq!(addrs, vec![0x8000, 0x8002, 0x8005, 0x8006, 0x8008]);
+
+    assert_eq!(ops[1].bytes, vec![0x9D, 0x00, 0x02]);
+    assert_eq!(ops[1].op.instruction, Instruction::Sta);
+    assert_eq!(ops[1].op.arg, OpArg::AbsX { addr_base: 0x0200 });
+
+    assert_eq!(ops[3].op.arg, OpArg::Branch { addr_offset: -8 });
+    assert_eq!(ops[3].branch_target, Some(0x8000));
+    assert_eq!(ops[0].branch_target, None);
+
+    let lines: Vec<_> = ops.iter().map(|op| op.to_string()).collect();
+    assert_eq!(
+        lines,
+        vec![
+            "$8000  A9 00     LDA #$00",
+            "$8002  9D 00 02  STA $0200,X",
+            "$8005  E8        INX",
+            "$8006  D0 F8     BNE $8000",
+            "$8008  02        JAM",
+        ]
+    );
+}
+
+#[test]
+fn disasm_decodes_65c02_ops() {
+    let bytes = [
+        0xB2, 0x10, //       LDA ($10)
+        0x7C, 0x00, 0x03, // JMP ($0300,X)
+        0x80, 0xFE, //       BRA $8005
+        0x02, 0xEA, //       NOP #$EA
+    ];
+    let ops: Vec<_> = disassemble(&bytes, 0x8000)
+        .with_variant(CpuVariant::Cmos65C02)
+        .collect();
+
+    assert_eq!(ops[0].op.instruction, Instruction::Lda);
+    assert_eq!(ops[0].op.arg, OpArg::ZeroInd { target_addr: 0x10 });
+    assert_eq!(ops[2].branch_target, Some(0x8005));
+
+    let lines: Vec<_> = ops.iter().map(|op| op.to_string()).collect();
+    assert_eq!(
+        lines,
+        vec![
+            "$8000  B2 10     LDA ($10)",
+            "$8002  7C 00 03  JMP ($0300,X)",
+            "$8005  80 FE     BRA $8005",
+            "$8007  02 EA     NOP #$EA",
+        ]
+    );
+}
@@ -0,0 +1,76 @@
+#![feature(generator_trait, exhaustive_patterns, never_type)]
+
+use lochnes::nes;
+
+mod common;
+
+use common::{null_io, run_cpu_cycles, run_timed_instruction, test_rom};
+
+#[test]
+fn oam_dma_halts_cpu() {
+    let io = null_io();
+    let program = [
+        0xA9, 0x02, //       LDA #$02
+        0x8D, 0x14, 0x40, // STA $4014
+        0xEA, //             NOP
+        0x8D, 0x14, 0x40, // STA $4014
+        0xA6, 0x00, //       LDX $00
+        0x8D, 0x14, 0x40, // STA $4014
+        0x4C, 0x0F, 0x80, // JMP $800F
+    ];
+    let nes = nes::Nes::new(&io, test_rom(&program));
+    let mut run_nes = nes.run();
+
+    for index in 0x00..=0xFF {
+        nes.write_u8(0x0200 + index, index as u8 ^ 0xFF);
+    }
+    nes.ppu.oam_addr.set(0x10);
+
+    assert_eq!(run_timed_instruction(&mut run_nes).0, 0x8000);
+
+    // 4 cycles for STA, 1 cycle to halt the CPU, 0 or 1 cycles to line up
+    // with an even cycle, then 512 cycles of reads and writes
+    let (pc, cycles) = run_timed_instruction(&mut run_nes);
+    assert_eq!(pc, 0x8002);
+    assert!((517..=518).contains(&cycles));
+
+    // The DMA writes through $2004, starting at the current OAM address
+    let oam = nes.ppu.oam.get();
+    for index in 0x00..=0xFF {
+        assert_eq!(oam[(index + 0x10) % 0x100], index as u8 ^ 0xFF);
+    }
+    assert_eq!(nes.ppu.oam_addr.get(), 0x10);
+
+    // A transfer always ends with a write on an odd cycle, so the
+    // alignment of the next transfer depends on the instructions in between
+    assert_eq!(run_timed_instruction(&mut run_nes), (0x8005, 2));
+    assert_eq!(run_timed_instruction(&mut run_nes), (0x8006, 518));
+    assert_eq!(run_timed_instruction(&mut run_nes), (0x8009, 3));
+    assert_eq!(run_timed_instruction(&mut run_nes), (0x800B, 517));
+}
+
+#[test]
+fn dmc_dma_halts_cpu_on_next_cycle() {
+    let io = null_io();
+    let program = [
+        0x20, 0x03, 0x80, // JSR $8003
+        0x4C, 0x03, 0x80, // JMP $8003
+    ];
+    let nes = nes::Nes::new(&io, test_rom(&program));
+    let mut run_nes = nes.run();
+
+    // Run the reset sequence and the first 3 cycles of JSR, so that the
+    // CPU's next cycle pushes the high byte of the return address
+    run_cpu_cycles(&mut run_nes, 7 + 3);
+    nes.dma.start_dmc_dma(0xC000);
+
+    // The CPU gets halted straight away, even though its next cycle is a
+    // write (on hardware, the halt would wait for the next read)
+    run_cpu_cycles(&mut run_nes, 1);
+    assert_eq!(nes.read_u8(0x01FD), 0x00);
+
+    // 2 more cycles to finish the fetch (which lines up without an extra
+    // cycle here), then the last 3 cycles of JSR
+    assert_eq!(run_timed_instruction(&mut run_nes), (0x8000, 5));
+    assert_eq!(nes.read_u8(0x01FD), 0x80);
+}
@@ -0,0 +1,425 @@
+#![feature(generator_trait, exhaustive_patterns, never_type)]
+
+use std::cell::{Cell, RefCell};
+use std::ops::{Generator, GeneratorState};
+use std::pin::Pin;
+
+use lochnes::nes::cpu::{disassemble, Bus, Cpu, CpuStep, CpuVariant, Instruction, IrqSources};
+
+// A bus with 64KB of RAM and nothing else. Every write gets logged
+struct FlatBus {
+    ram: Vec<Cell<u8>>,
+    writes: RefCell<Vec<(u16, u8)>>,
+}
+
+impl FlatBus {
+    fn new(image: &[u8]) -> Self {
+        let mut ram = vec![Cell::new(0); 0x10000];
+        for (byte, &value) in ram.iter_mut().zip(image) {
+            byte.set(value);
+        }
+
+        FlatBus {
+            ram,
+            writes: RefCell::new(vec![]),
+        }
+    }
+}
+
+impl Bus for FlatBus {
+    fn read_u8(&self, addr: u16) -> u8 {
+        self.ram[addr as usize].get()
+    }
+
+    fn write_u8(&self, addr: u16, value: u8) {
+        self.writes.borrow_mut().push((addr, value));
+        self.ram[addr as usize].set(value);
+    }
+
+    fn peek_u8(&self, addr: u16) -> u8 {
+        self.ram[addr as usize].get()
+    }
+}
+
+// Run until the CPU gets stuck on an instruction that jumps to itself (or
+// until `max_cycles` have passed), returning the address it got stuck at
+fn run_until_trap(cpu: &Cpu, bus: &FlatBus, max_cycles: u64) -> u16 {
+    let mut run_cpu = Cpu::run(cpu, bus);
+    resume_until_trap(cpu, &mut run_cpu, max_cycles)
+}
+
+// Like `run_until_trap`, but keeps running the CPU from where `run_cpu`
+// left off
+fn resume_until_trap(
+    cpu: &Cpu,
+    run_cpu: &mut (impl Generator<Yield = CpuStep, Return = !> + Unpin),
+    max_cycles: u64,
+) -> u16 {
+    while cpu.cycles.get() < max_cycles {
+        match Pin::new(&mut *run_cpu).resume(()) {
+            GeneratorState::Yielded(CpuStep::Op(op)) => {
+                if cpu.pc.get() == op.pc {
+                    return op.pc;
+                }
+            }
+            GeneratorState::Yielded(_) => {}
+        }
+    }
+
+    panic!("CPU didn't trap after {} cycles", max_cycles);
+}
+
+// Run the CPU until it finishes its next instruction, returning the number
+// of cycles it took and the writes it made
+fn run_instruction_writes(
+    cpu: &Cpu,
+    run_cpu: &mut (impl Generator<Yield = CpuStep, Return = !> + Unpin),
+    bus: &FlatBus,
+) -> (u64, Vec<(u16, u8)>) {
+    let start_cycles = cpu.cycles.get();
+    bus.writes.borrow_mut().clear();
+
+    loop {
+        match Pin::new(&mut *run_cpu).resume(()) {
+            GeneratorState::Yielded(CpuStep::Op(_)) => {
+                let cycles = cpu.cycles.get() - start_cycles;
+                return (cycles, bus.writes.replace(vec![]));
+            }
+            GeneratorState::Yielded(_) => {}
+        }
+    }
+}
+
+// Every opcode runs as the same instruction that the disassembler decodes
+// it as. JAM is the only instruction that never finishes
+#[test]
+fn every_opcode_runs() {
+    let variants = [
+        CpuVariant::Ricoh2A03,
+        CpuVariant::Nmos6502,
+        CpuVariant::Cmos65C02,
+    ];
+    for &variant in variants.iter() {
+        for opcode in 0x00..=0xFF {
+            let bytes = [opcode, 0x00, 0x02];
+            let expected_op = disassemble(&bytes, 0x0200)
+                .with_variant(variant)
+                .next()
+                .unwrap()
+                .op;
+
+            let mut image = vec![0; 0x0200];
+            image.extend_from_slice(&bytes);
+            let bus = FlatBus::new(&image);
+            let cpu = Cpu::with_variant(variant);
+            cpu.pc.set(0x0200);
+
+            let mut run_cpu = Cpu::run(&cpu, &bus);
+            let mut op = None;
+            while op.is_none() && cpu.cycles.get() < 16 {
+                match Pin::new(&mut run_cpu).resume(()) {
+                    GeneratorState::Yielded(CpuStep::Op(step)) => {
+                        op = Some(step.op);
+                    }
+                    GeneratorState::Yielded(_) => {}
+                }
+            }
+
+            match expected_op.instruction {
+                Instruction::UnofficialJam => assert_eq!(op, None),
+                _ => assert_eq!(op, Some(expected_op), "{:?} ${:02X}", variant, opcode),
+            }
+        }
+    }
+}
+
+// Klaus Dormann's functional test runs through every official opcode,
+// trapping on failure. It's loaded at $0000 and starts at $0400. See:
+// - https://github.com/Klaus2m5/6502_65C02_functional_tests
+#[test]
+fn klaus_dormann_6502_functional_test() {
+    let bus = FlatBus::new(include_bytes!(
+        "./fixtures/6502_65C02_functional_tests/bin_files/6502_functional_test.bin"
+    ));
+    let cpu = Cpu::with_variant(CpuVariant::Nmos6502);
+    cpu.pc.set(0x0400);
+
+    const SUCCESS_ADDR: u16 = 0x3469;
+    let trap_addr = run_until_trap(&cpu, &bus, 100_000_000);
+    assert_eq!(
+        trap_addr,
+        SUCCESS_ADDR,
+        "Functional test failed at ${:04X} (test case ${:02X})",
+        trap_addr,
+        bus.read_u8(0x0200),
+    );
+}
+
+#[test]
+fn decimal_mode_adc_sbc() {
+    let program = [
+        0xF8, //       SED
+        0x18, //       CLC
+        0xA9, 0x19, // LDA #$19
+        0x69, 0x28, // ADC #$28
+        0x85, 0x00, // STA $00
+        0xA9, 0x99, // LDA #$99
+        0x69, 0x01, // ADC #$01
+        0x85, 0x01, // STA $01
+        0x08, //       PHP
+        0xA9, 0x42, // LDA #$42
+        0xE9, 0x09, // SBC #$09
+        0x85, 0x02, // STA $02
+        0xA9, 0x00, // LDA #$00
+        0xE9, 0x01, // SBC #$01
+        0x85, 0x03, // STA $03
+        0x08, //       PHP
+        0x4C, 0x1C, 0x02, // JMP $021C
+    ];
+    let mut image = vec![0; 0x0200];
+    image.extend_from_slice(&program);
+
+    let bus = FlatBus::new(&image);
+    let cpu = Cpu::with_variant(CpuVariant::Nmos6502);
+    cpu.pc.set(0x0200);
+
+    assert_eq!(run_until_trap(&cpu, &bus, 1_000), 0x021C);
+
+    // 19 + 28 = 47, then 99 + 01 = 00 with a carry (so the next
+    // subtraction doesn't borrow), 42 - 09 = 33, then 00 - 01 = 99 with a
+    // borrow
+    assert_eq!(bus.read_u8(0x0000), 0x47);
+    assert_eq!(bus.read_u8(0x0001), 0x00);
+    assert_eq!(bus.read_u8(0x0002), 0x33);
+    assert_eq!(bus.read_u8(0x0003), 0x99);
+
+    let carry_after_adc = bus.read_u8(0x01FD) & 0b_0000_0001;
+    let carry_after_sbc = bus.read_u8(0x01FC) & 0b_0000_0001;
+    assert_eq!(carry_after_adc, 1);
+    assert_eq!(carry_after_sbc, 0);
+}
+
+#[test]
+fn decimal_mode_disabled_on_2a03() {
+    let program = [
+        0xF8, //       SED
+        0x18, //       CLC
+        0xA9, 0x19, // LDA #$19
+        0x69, 0x28, // ADC #$28
+        0x85, 0x00, // STA $00
+        0x4C, 0x08, 0x02, // JMP $0208
+    ];
+    let mut image = vec![0; 0x0200];
+    image.extend_from_slice(&program);
+
+    let bus = FlatBus::new(&image);
+    let cpu = Cpu::new();
+    cpu.pc.set(0x0200);
+
+    assert_eq!(run_until_trap(&cpu, &bus, 1_000), 0x0208);
+    assert_eq!(bus.read_u8(0x0000), 0x41);
+}
+
+#[test]
+fn cmos_65c02_instructions() {
+    let program = [
+        0xA2, 0x42, //       LDX #$42
+        0xDA, //             PHX
+        0x7A, //             PLY
+        0x84, 0x00, //       STY $00
+        0xA9, 0xFF, //       LDA #$FF
+        0x85, 0x01, //       STA $01
+        0x64, 0x01, //       STZ $01
+        0xA9, 0xF0, //       LDA #$F0
+        0x85, 0x02, //       STA $02
+        0xA9, 0x0F, //       LDA #$0F
+        0x04, 0x02, //       TSB $02
+        0xA9, 0x3C, //       LDA #$3C
+        0x14, 0x02, //       TRB $02
+        0xA9, 0x00, //       LDA #$00
+        0x85, 0x04, //       STA $04
+        0xA9, 0x03, //       LDA #$03
+        0x85, 0x05, //       STA $05
+        0x1A, //             INC A
+        0x92, 0x04, //       STA ($04)
+        0x80, 0x01, //       BRA $0226
+        0x00, //             BRK
+        0xA2, 0x02, //       LDX #$02
+        0x7C, 0x00, 0x04, // JMP ($0400,X)
+        0x6C, 0xFF, 0x04, // JMP ($04FF)
+        0x4C, 0x2E, 0x02, // JMP $022E
+    ];
+    let mut image = vec![0; 0x0200];
+    image.extend_from_slice(&program);
+    image.resize(0x0501, 0);
+
+    // The jump table for JMP ($0400,X)
+    image[0x0402] = 0x2B;
+    image[0x0403] = 0x02;
+
+    // JMP ($04FF) reads its high byte from $0500 (the NMOS 6502 would
+    // read from $0400 instead)
+    image[0x04FF] = 0x2E;
+    image[0x0500] = 0x02;
+
+    let bus = FlatBus::new(&image);
+    let cpu = Cpu::with_variant(CpuVariant::Cmos65C02);
+    cpu.pc.set(0x0200);
+
+    assert_eq!(run_until_trap(&cpu, &bus, 1_000), 0x022E);
+    assert_eq!(bus.read_u8(0x0000), 0x42);
+    assert_eq!(bus.read_u8(0x0001), 0x00);
+    assert_eq!(bus.read_u8(0x0002), 0xC3);
+    assert_eq!(bus.read_u8(0x0300), 0x04);
+}
+
+#[test]
+fn cmos_65c02_decimal_flags() {
+    let program = [
+        0xF8, //       SED
+        0x18, //       CLC
+        0xA9, 0x99, // LDA #$99
+        0x69, 0x01, // ADC #$01
+        0x85, 0x00, // STA $00
+        0x08, //       PHP
+        0x00, 0x00, // BRK
+    ];
+    let mut image = vec![0; 0x0200];
+    image.extend_from_slice(&program);
+    image.resize(0x10000, 0);
+
+    // The BRK handler pushes the status again, then traps
+    image[0x0300..0x0304].copy_from_slice(&[
+        0x08, //             PHP
+        0x4C, 0x01, 0x03, // JMP $0301
+    ]);
+    image[0xFFFE] = 0x00;
+    image[0xFFFF] = 0x03;
+
+    const Z: u8 = 0b_0000_0010;
+    const D: u8 = 0b_0000_1000;
+
+    // On the NMOS 6502, `Z` comes from the binary sum ($9A), and BRK
+    // leaves decimal mode on
+    let bus = FlatBus::new(&image);
+    let cpu = Cpu::with_variant(CpuVariant::Nmos6502);
+    cpu.pc.set(0x0200);
+
+    assert_eq!(run_until_trap(&cpu, &bus, 1_000), 0x0301);
+    assert_eq!(bus.read_u8(0x0000), 0x00);
+    assert_eq!(bus.read_u8(0x01FD) & Z, 0);
+    assert_eq!(bus.read_u8(0x01F9) & D, D);
+
+    // The 65C02 sets `Z` from the decimal result, and BRK clears `D`
+    let bus = FlatBus::new(&image);
+    let cpu = Cpu::with_variant(CpuVariant::Cmos65C02);
+    cpu.pc.set(0x0200);
+
+    assert_eq!(run_until_trap(&cpu, &bus, 1_000), 0x0301);
+    assert_eq!(bus.read_u8(0x0000), 0x00);
+    assert_eq!(bus.read_u8(0x01FD) & Z, Z);
+    assert_eq!(bus.read_u8(0x01F9) & D, 0);
+}
+
+#[test]
+fn read_modify_write_bus_accesses() {
+    let program = [
+        0xA2, 0x01, //       LDX #$01
+        0xE6, 0x10, //       INC $10
+        0x1E, 0x00, 0x03, // ASL $0300,X
+        0x1E, 0xFF, 0x03, // ASL $03FF,X
+        0xFE, 0x00, 0x03, // INC $0300,X
+    ];
+    let mut image = vec![0; 0x0200];
+    image.extend_from_slice(&program);
+    image.resize(0x0401, 0);
+    image[0x0010] = 0x41;
+    image[0x0301] = 0x42;
+    image[0x0400] = 0x43;
+
+    // The NMOS 6502 writes the original value back before writing the
+    // modified value, and always spends a cycle fixing up ABS,X addresses
+    let bus = FlatBus::new(&image);
+    let cpu = Cpu::with_variant(CpuVariant::Nmos6502);
+    cpu.pc.set(0x0200);
+    let mut run_cpu = Cpu::run(&cpu, &bus);
+
+    run_instruction_writes(&cpu, &mut run_cpu, &bus);
+    assert_eq!(
+        run_instruction_writes(&cpu, &mut run_cpu, &bus),
+        (5, vec![(0x0010, 0x41), (0x0010, 0x42)])
+    );
+    assert_eq!(
+        run_instruction_writes(&cpu, &mut run_cpu, &bus),
+        (7, vec![(0x0301, 0x42), (0x0301, 0x84)])
+    );
+    assert_eq!(
+        run_instruction_writes(&cpu, &mut run_cpu, &bus),
+        (7, vec![(0x0400, 0x43), (0x0400, 0x86)])
+    );
+    assert_eq!(
+        run_instruction_writes(&cpu, &mut run_cpu, &bus),
+        (7, vec![(0x0301, 0x84), (0x0301, 0x85)])
+    );
+
+    // The 65C02 reads the address again instead of writing it, and skips
+    // the fix-up cycle when the page doesn't change (except for INC and
+    // DEC)
+    let bus = FlatBus::new(&image);
+    let cpu = Cpu::with_variant(CpuVariant::Cmos65C02);
+    cpu.pc.set(0x0200);
+    let mut run_cpu = Cpu::run(&cpu, &bus);
+
+    run_instruction_writes(&cpu, &mut run_cpu, &bus);
+    assert_eq!(
+        run_instruction_writes(&cpu, &mut run_cpu, &bus),
+        (5, vec![(0x0010, 0x42)])
+    );
+    assert_eq!(
+        run_instruction_writes(&cpu, &mut run_cpu, &bus),
+        (6, vec![(0x0301, 0x84)])
+    );
+    assert_eq!(
+        run_instruction_writes(&cpu, &mut run_cpu, &bus),
+        (7, vec![(0x0400, 0x86)])
+    );
+    assert_eq!(
+        run_instruction_writes(&cpu, &mut run_cpu, &bus),
+        (7, vec![(0x0301, 0x85)])
+    );
+}
+
+#[test]
+fn interrupts_on_any_bus() {
+    let mut image = vec![0; 0x10000];
+    image[0x0200..0x0204].copy_from_slice(&[
+        0x58, //             CLI
+        0x4C, 0x01, 0x02, // JMP $0201
+    ]);
+
+    // The IRQ and NMI handlers both trap
+    image[0x0300..0x0303].copy_from_slice(&[0x4C, 0x00, 0x03]);
+    image[0x0400..0x0403].copy_from_slice(&[0x4C, 0x00, 0x04]);
+    image[0xFFFA..0xFFFC].copy_from_slice(&[0x00, 0x03]);
+    image[0xFFFE..0x10000].copy_from_slice(&[0x00, 0x04]);
+
+    let bus = FlatBus::new(&image);
+    let cpu = Cpu::new();
+    cpu.pc.set(0x0200);
+    let mut run_cpu = Cpu::run(&cpu, &bus);
+
+    assert_eq!(resume_until_trap(&cpu, &mut run_cpu, 100), 0x0201);
+
+    // The CPU polls its interrupt lines on its own, so interrupts work on
+    // any bus. The CPU finishes the current `JMP` before taking the IRQ
+    cpu.set_irq(IrqSources::MAPPER, true);
+    assert_eq!(resume_until_trap(&cpu, &mut run_cpu, 200), 0x0201);
+    assert_eq!(resume_until_trap(&cpu, &mut run_cpu, 200), 0x0400);
+    assert_eq!(bus.read_u8(0x01FD), 0x02);
+
+    // NMI ignores the `I` flag that the IRQ set
+    cpu.nmi.set(true);
+    assert_eq!(resume_until_trap(&cpu, &mut run_cpu, 300), 0x0400);
+    assert_eq!(resume_until_trap(&cpu, &mut run_cpu, 300), 0x0300);
+    assert_eq!(bus.read_u8(0x01FA), 0x04);
+}
@@ -0,0 +1,103 @@
+#![feature(generator_trait, exhaustive_patterns, never_type)]
+
+use lochnes::{nes, rom};
+
+mod common;
+
+use common::{null_io, run_cpu_cycles};
+
+// Build an NSF whose INIT routine stores the song number and TV system in
+// $00 and $01, and whose PLAY routine increments $02
+fn counter_nsf_bytes() -> Vec<u8> {
+    let mut header = vec![0; 0x80];
+    header[0x00..0x05].copy_from_slice(b"NESM\x1A");
+    header[0x05] = 1; // Version
+    header[0x06] = 3; // Total songs
+    header[0x07] = 1; // Starting song
+    header[0x08..0x0A].copy_from_slice(&0x8000_u16.to_le_bytes()); // Load
+    header[0x0A..0x0C].copy_from_slice(&0x8000_u16.to_le_bytes()); // Init
+    header[0x0C..0x0E].copy_from_slice(&0x8005_u16.to_le_bytes()); // Play
+    header[0x0E..0x13].copy_from_slice(b"Count");
+    header[0x6E..0x70].copy_from_slice(&16_666_u16.to_le_bytes()); // NTSC speed
+
+    let data = vec![
+        0x85, 0x00, // STA $00
+        0x86, 0x01, // STX $01
+        0x60, //       RTS
+        0xE6, 0x02, // INC $02
+        0x60, //       RTS
+    ];
+
+    header.into_iter().chain(data).collect()
+}
+
+fn counter_nsf() -> rom::Nsf {
+    let bytes = counter_nsf_bytes();
+    rom::Nsf::from_bytes(bytes.into_iter()).expect("Failed to build test NSF")
+}
+
+#[test]
+fn nsf_header() {
+    let nsf = counter_nsf();
+
+    assert_eq!(nsf.header.total_songs, 3);
+    assert_eq!(nsf.header.song_name, "Count");
+    assert_eq!(nsf.header.play_addr, 0x8005);
+    assert!(!nsf.header.is_bankswitched());
+}
+
+#[test]
+fn nsf_unsupported_features() {
+    let mut bytes = counter_nsf_bytes();
+    bytes[0x7B] = 0b_0000_0001; // VRC6
+    match rom::Nsf::from_bytes(bytes.into_iter()) {
+        Err(rom::RomError::UnsupportedNsfSoundChips(0b_0000_0001)) => {}
+        result => panic!("Expected unsupported sound chips: {:?}", result),
+    }
+
+    let mut bytes = counter_nsf_bytes();
+    bytes[0x08..0x0A].copy_from_slice(&0x6000_u16.to_le_bytes()); // Load
+    match rom::Nsf::from_bytes(bytes.into_iter()) {
+        Err(rom::RomError::UnsupportedNsfLoadAddr(0x6000)) => {}
+        result => panic!("Expected unsupported load address: {:?}", result),
+    }
+}
+
+#[test]
+fn nsf_calls_init_then_play_at_play_rate() {
+    let io = null_io();
+    let nes = nes::Nes::from_nsf(&io, counter_nsf(), 2);
+    let mut run_nes = nes.run();
+
+    // Run for about a second
+    run_cpu_cycles(&mut run_nes, 1_789_773);
+
+    assert_eq!(nes.read_u8(0x0000), 2);
+    assert_eq!(nes.read_u8(0x0001), 0);
+
+    let play_calls = nes.read_u8(0x0002);
+    assert!((59..=61).contains(&play_calls));
+}
+
+#[test]
+fn nsf_reset_clears_ram_before_init() {
+    let io = null_io();
+    let nes = nes::Nes::from_nsf(&io, counter_nsf(), 1);
+    let mut run_nes = nes.run();
+
+    run_cpu_cycles(&mut run_nes, 1_789_773 / 2);
+    nes.write_u8(0x0300, 0xAA);
+    nes.write_u8(0x6000, 0xBB);
+
+    // Resetting calls INIT again, with the internal RAM and work RAM
+    // cleared, so the count of PLAY calls starts over
+    nes.reset();
+    run_cpu_cycles(&mut run_nes, 1_789_773 / 4);
+
+    assert_eq!(nes.read_u8(0x0000), 1);
+    assert_eq!(nes.read_u8(0x0300), 0x00);
+    assert_eq!(nes.read_u8(0x6000), 0x00);
+
+    let play_calls = nes.read_u8(0x0002);
+    assert!((14..=16).contains(&play_calls));
+}
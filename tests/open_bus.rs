@@ -0,0 +1,84 @@
+#![feature(generator_trait, exhaustive_patterns, never_type)]
+
+use lochnes::nes;
+
+mod common;
+
+use common::{idle_loop_rom, null_io, run_instruction, test_rom};
+
+#[test]
+fn open_bus_reads_last_bus_value() {
+    let io = null_io();
+    let program = [
+        0xAD, 0x00, 0x50, // LDA $5000
+        0x85, 0x00, //       STA $00
+        0xAD, 0xFF, 0x5F, // LDA $5FFF
+        0x8D, 0x01, 0x08, // STA $0801
+        0xAD, 0x16, 0x40, // LDA $4016
+        0x85, 0x02, //       STA $02
+        0xAD, 0x17, 0x40, // LDA $4017
+        0x8D, 0x03, 0x18, // STA $1803
+        0x4C, 0x16, 0x80, // JMP $8016
+    ];
+    let nes = nes::Nes::new(&io, test_rom(&program));
+    let mut run_nes = nes.run();
+
+    while run_instruction(&mut run_nes) != 0x8016 {}
+
+    // An absolute read from an unmapped address returns the high byte of
+    // the address, since that's the last byte the CPU read
+    assert_eq!(nes.read_u8(0x0000), 0x50);
+    assert_eq!(nes.read_u8(0x0001), 0x5F);
+
+    // The controller ports only drive the low bits
+    assert_eq!(nes.read_u8(0x0002), 0x40);
+    assert_eq!(nes.read_u8(0x0003), 0x40);
+
+    // Writes go on the bus too
+    nes.write_u8(0x4018, 0xA5);
+    assert_eq!(nes.read_u8(0x6000 - 1), 0xA5);
+
+    // Bit 5 of $4015 is open bus, but reading it doesn't change the value
+    // on the bus
+    nes.write_u8(0x0000, 0xFF);
+    assert_eq!(nes.read_u8(0x4015) & 0b_0010_0000, 0b_0010_0000);
+    assert_eq!(nes.open_bus.get(), 0xFF);
+}
+
+#[test]
+fn ppu_registers_read_ppu_latch() {
+    let io = null_io();
+    let nes = nes::Nes::new(&io, idle_loop_rom());
+
+    // Write-only PPU registers (and their mirrors) return the last value
+    // written to any PPU register, not the value on the CPU's bus
+    nes.write_u8(0x2003, 0x10);
+    nes.write_u8(0x4018, 0xA5);
+    assert_eq!(nes.read_u8(0x2000), 0x10);
+    assert_eq!(nes.read_u8(0x3FF9), 0x10);
+    assert_eq!(nes.read_u8(0x2005), 0x10);
+
+    // OAMDATA is readable, and the unused attribute bits read as 0
+    nes.write_u8(0x2004, 0x5A);
+    nes.write_u8(0x2004, 0x6B);
+    nes.write_u8(0x2004, 0xFF);
+    nes.write_u8(0x2003, 0x10);
+    assert_eq!(nes.read_u8(0x2004), 0x5A);
+    nes.write_u8(0x2003, 0x12);
+    assert_eq!(nes.read_u8(0x2004), 0xE3);
+
+    // Reads refresh the latch, and PPUSTATUS fills its low bits from it
+    assert_eq!(nes.read_u8(0x2001), 0xE3);
+    assert_eq!(nes.read_u8(0x2002), 0x03);
+    assert_eq!(nes.read_u8(0x2006), 0x03);
+}
+
+#[test]
+fn ppu_addresses_mirror_below_4000() {
+    let io = null_io();
+    let nes = nes::Nes::new(&io, idle_loop_rom());
+
+    nes.write_ppu_u8(0x7F01, 0x2A);
+    assert_eq!(nes.read_ppu_u8(0x3F01), 0x2A);
+    assert_eq!(nes.read_ppu_u8(0xFF01), 0x2A);
+}
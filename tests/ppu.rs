@@ -0,0 +1,213 @@
+#![feature(generator_trait, exhaustive_patterns, never_type)]
+
+use std::cell::RefCell;
+use std::ops::{Generator, GeneratorState};
+use std::pin::Pin;
+
+use lochnes::nes::ppu::PpuStep;
+use lochnes::nes::NesStep;
+use lochnes::rom::MirrorMode;
+use lochnes::video::{Color, Point, Video};
+use lochnes::{audio, input, nes, rom};
+
+mod common;
+
+use common::{null_io, TestRom};
+
+// Build an NROM image with `program` at $8000, where tile 1 of the pattern
+// table is filled with color 1 and every other tile is blank. `flags_6`
+// sets the header's mirroring bits
+fn tile_rom(flags_6: u8, program: &[u8]) -> rom::Rom {
+    let mut chr_rom = vec![0x00; 0x2000];
+    chr_rom[0x0010..0x0018].copy_from_slice(&[0xFF; 8]);
+
+    TestRom::new(program)
+        .with_flags_6(flags_6)
+        .with_chr_rom(chr_rom)
+        .build()
+}
+
+const HORIZONTAL_MIRRORING: u8 = 0b_0000_0000;
+const VERTICAL_MIRRORING: u8 = 0b_0000_0001;
+const FOUR_SCREEN_VRAM: u8 = 0b_0000_1000;
+
+// Records which pixels of the frame weren't drawn as black
+struct LitPixelsVideo {
+    pixels: RefCell<Vec<bool>>,
+}
+
+impl LitPixelsVideo {
+    fn new() -> Self {
+        LitPixelsVideo {
+            pixels: RefCell::new(vec![false; 256 * 240]),
+        }
+    }
+
+    fn is_lit(&self, x: usize, y: usize) -> bool {
+        self.pixels.borrow()[y * 256 + x]
+    }
+}
+
+impl Video for LitPixelsVideo {
+    fn draw_point(&self, point: Point, color: Color) {
+        let is_lit = color.r != 0 || color.g != 0 || color.b != 0;
+        self.pixels.borrow_mut()[point.y as usize * 256 + point.x as usize] = is_lit;
+    }
+
+    fn present(&self) {}
+
+    fn clear(&self) {}
+}
+
+#[test]
+fn ppu_scroll_registers() {
+    let io = null_io();
+    let nes = nes::Nes::new(&io, tile_rom(VERTICAL_MIRRORING, &[]));
+    let ppu = &nes.ppu;
+
+    // PPUCTRL sets the nametable bits
+    nes.write_u8(0x2000, 0b_0000_0011);
+    assert_eq!(ppu.temp_vram_addr.get(), 0x0C00);
+
+    // The first PPUSCROLL write sets coarse X and fine X, and the second
+    // sets coarse Y and fine Y
+    nes.write_u8(0x2005, 0x7D);
+    assert_eq!(ppu.temp_vram_addr.get(), 0x0C0F);
+    assert_eq!(ppu.fine_x_scroll.get(), 5);
+    nes.write_u8(0x2005, 0x5E);
+    assert_eq!(ppu.temp_vram_addr.get(), 0x6D6F);
+    assert_eq!(ppu.vram_addr.get(), 0x0000);
+
+    // PPUADDR shares the same registers: the first write sets the high
+    // byte of t (and clears the top bit), and the second sets the low byte
+    // and copies t to v
+    nes.write_u8(0x2006, 0x3D);
+    assert_eq!(ppu.temp_vram_addr.get(), 0x3D6F);
+    nes.write_u8(0x2006, 0xF0);
+    assert_eq!(ppu.temp_vram_addr.get(), 0x3DF0);
+    assert_eq!(ppu.vram_addr.get(), 0x3DF0);
+
+    // Reading PPUSTATUS resets the write latch
+    nes.write_u8(0x2005, 0x08);
+    nes.read_u8(0x2002);
+    nes.write_u8(0x2005, 0x10);
+    assert_eq!(ppu.temp_vram_addr.get(), 0x3DE2);
+    assert_eq!(ppu.fine_x_scroll.get(), 0);
+}
+
+#[test]
+fn ppu_renders_with_fine_scroll() {
+    let io = nes::NesIoWith {
+        video: LitPixelsVideo::new(),
+        input: input::NullInput,
+        audio: audio::NullAudio,
+    };
+    let program = [
+        0xA9, 0x3F, //       LDA #$3F
+        0x8D, 0x06, 0x20, // STA $2006
+        0xA9, 0x00, //       LDA #$00
+        0x8D, 0x06, 0x20, // STA $2006
+        0xA9, 0x0F, //       LDA #$0F
+        0x8D, 0x07, 0x20, // STA $2007
+        0xA9, 0x30, //       LDA #$30
+        0x8D, 0x07, 0x20, // STA $2007
+        0xA9, 0x20, //       LDA #$20
+        0x8D, 0x06, 0x20, // STA $2006
+        0xA9, 0x00, //       LDA #$00
+        0x8D, 0x06, 0x20, // STA $2006
+        0xA9, 0x01, //       LDA #$01
+        0x8D, 0x07, 0x20, // STA $2007
+        0xA9, 0x04, //       LDA #$04
+        0x8D, 0x05, 0x20, // STA $2005
+        0x8D, 0x05, 0x20, // STA $2005
+        0xA9, 0x00, //       LDA #$00
+        0x8D, 0x00, 0x20, // STA $2000
+        0xA9, 0x08, //       LDA #$08
+        0x8D, 0x01, 0x20, // STA $2001
+        0x4C, 0x35, 0x80, // JMP $8035
+    ];
+    let nes = nes::Nes::new(&io, tile_rom(VERTICAL_MIRRORING, &program));
+    let mut run_nes = nes.run();
+
+    // Let the program finish during the first frame, then render a
+    // full frame
+    let mut vblanks = 0;
+    while vblanks < 3 {
+        match Pin::new(&mut run_nes).resume(()) {
+            GeneratorState::Yielded(NesStep::Ppu(PpuStep::Vblank)) => {
+                vblanks += 1;
+            }
+            GeneratorState::Yielded(_) => {}
+        }
+    }
+
+    // The only non-blank tile is the top-left tile of the first nametable.
+    // Scrolling by (4, 4) leaves 4x4 pixels of it in the top-left corner.
+    // The bottom of the screen wraps around to the third nametable (a
+    // mirror of the first), but the right side wraps to the second
+    let video = &nes.io.video;
+    let lit_pixels: Vec<_> = (0..240)
+        .flat_map(|y| (0..256).map(move |x| (x, y)))
+        .filter(|&(x, y)| video.is_lit(x, y))
+        .collect();
+    let expected: Vec<_> = (0..4)
+        .flat_map(|y| (0..4).map(move |x| (x, y)))
+        .chain((236..240).flat_map(|y| (0..4).map(move |x| (x, y))))
+        .collect();
+    assert_eq!(lit_pixels, expected);
+}
+
+// Write 1, 2, 3, and 4 to the first byte of each nametable, then read
+// them back
+fn nametable_writes(nes: &nes::Nes<impl nes::NesIo>) -> [u8; 4] {
+    let nametable_addrs = [0x2000, 0x2400, 0x2800, 0x2C00];
+    for (value, &addr) in (1..).zip(&nametable_addrs) {
+        nes.write_ppu_u8(addr, value);
+    }
+
+    nametable_reads(nes)
+}
+
+fn nametable_reads(nes: &nes::Nes<impl nes::NesIo>) -> [u8; 4] {
+    [
+        nes.read_ppu_u8(0x2000),
+        nes.read_ppu_u8(0x2400),
+        nes.read_ppu_u8(0x2800),
+        nes.read_ppu_u8(0x2C00),
+    ]
+}
+
+#[test]
+fn ppu_nametable_mirroring() {
+    let io = null_io();
+
+    let nes = nes::Nes::new(&io, tile_rom(HORIZONTAL_MIRRORING, &[]));
+    assert_eq!(nes.mapper.mirror_mode(), MirrorMode::Horizontal);
+    assert_eq!(nametable_writes(&nes), [2, 2, 4, 4]);
+
+    let nes = nes::Nes::new(&io, tile_rom(FOUR_SCREEN_VRAM, &[]));
+    assert_eq!(nametable_writes(&nes), [1, 2, 3, 4]);
+    assert_eq!(nes.ppu.ppu_ram()[0x0400].get(), 2);
+
+    // $3000-$3EFF mirrors the nametables
+    assert_eq!(nes.read_ppu_u8(0x3800), 3);
+    nes.write_ppu_u8(0x3C01, 5);
+    assert_eq!(nes.read_ppu_u8(0x2C01), 5);
+
+    // Mappers can switch the mirroring mode at runtime
+    let nes = nes::Nes::new(&io, tile_rom(VERTICAL_MIRRORING, &[]));
+    assert_eq!(nametable_writes(&nes), [3, 4, 3, 4]);
+    nes.mapper.set_mirror_mode(MirrorMode::SingleScreenB);
+    assert_eq!(nametable_writes(&nes), [4, 4, 4, 4]);
+    nes.mapper.set_mirror_mode(MirrorMode::SingleScreenA);
+    assert_eq!(nametable_reads(&nes), [3, 3, 3, 3]);
+    nes.mapper.set_mirror_mode(MirrorMode::Vertical);
+    assert_eq!(nametable_reads(&nes), [3, 4, 3, 4]);
+
+    // That includes four-screen mirroring, even when the header didn't
+    // ask for extra VRAM
+    nes.mapper.set_mirror_mode(MirrorMode::FourScreenVram);
+    assert_eq!(nametable_writes(&nes), [1, 2, 3, 4]);
+    nes.mapper.set_mirror_mode(MirrorMode::Horizontal);
+    assert_eq!(nametable_reads(&nes), [1, 1, 2, 2]);
+}
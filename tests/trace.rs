@@ -0,0 +1,55 @@
+#![feature(generator_trait, exhaustive_patterns, never_type)]
+
+use std::ops::{Generator, GeneratorState};
+use std::pin::Pin;
+
+use lochnes::nes;
+use lochnes::nes::cpu::CpuStep;
+use lochnes::nes::trace::trace_line;
+use lochnes::nes::NesStep;
+
+mod common;
+
+use common::{null_io, test_rom};
+
+#[test]
+fn trace_matches_nintendulator_format() {
+    let io = null_io();
+    let program = [
+        0xA2, 0x05, //       LDX #$05
+        0xA0, 0x01, //       LDY #$01
+        0x9D, 0x00, 0x02, // STA $0200,X
+        0xA1, 0x0B, //       LDA ($0B,X)
+        0x04, 0x20, //       NOP $20
+        0xD0, 0xF3, //       BNE $8000
+    ];
+    let nes = nes::Nes::new(&io, test_rom(&program));
+    let mut run_nes = nes.run();
+
+    nes.write_u8(0x0010, 0x00);
+    nes.write_u8(0x0011, 0x02);
+    nes.write_u8(0x0200, 0x42);
+
+    let mut lines = vec![];
+    while lines.len() < 7 {
+        match Pin::new(&mut run_nes).resume(()) {
+            GeneratorState::Yielded(NesStep::Cpu(CpuStep::Start)) => {
+                lines.push(trace_line(&nes));
+            }
+            GeneratorState::Yielded(_) => {}
+        }
+    }
+
+    assert_eq!(
+        lines,
+        vec![
+            "8000  A2 05     LDX #$05                        A:00 X:00 Y:00 P:24 SP:FD PPU:  0, 21 CYC:7",
+            "8002  A0 01     LDY #$01                        A:00 X:05 Y:00 P:24 SP:FD PPU:  0, 27 CYC:9",
+            "8004  9D 00 02  STA $0200,X @ 0205 = 00         A:00 X:05 Y:01 P:24 SP:FD PPU:  0, 33 CYC:11",
+            "8007  A1 0B     LDA ($0B,X) @ 10 = 0200 = 42    A:00 X:05 Y:01 P:24 SP:FD PPU:  0, 48 CYC:16",
+            "8009  04 20    *NOP $20 = 00                    A:42 X:05 Y:01 P:24 SP:FD PPU:  0, 66 CYC:22",
+            "800B  D0 F3     BNE $8000                       A:42 X:05 Y:01 P:24 SP:FD PPU:  0, 75 CYC:25",
+            "8000  A2 05     LDX #$05                        A:42 X:05 Y:01 P:24 SP:FD PPU:  0, 84 CYC:28",
+        ]
+    );
+}
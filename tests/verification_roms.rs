@@ -5,7 +5,7 @@ use std::pin::Pin;
 
 use lochnes::nes::ppu::PpuStep;
 use lochnes::nes::NesStep;
-use lochnes::{input, nes, rom, video};
+use lochnes::{audio, input, nes, rom, video};
 
 fn run_blargg_instr_test_with_expected_result(
     test_name: &str,
@@ -18,10 +18,18 @@ fn run_blargg_instr_test_with_expected_result(
 
     let video = video::NullVideo;
     let input = input::NullInput;
-    let io = nes::NesIoWith { video, input };
+    let audio = audio::NullAudio;
+    let io = nes::NesIoWith {
+        video,
+        input,
+        audio,
+    };
     let nes = nes::Nes::new(&io, rom.clone());
     let mut run_nes = nes.run();
 
+    // The frame where the test ROM first asked to be reset
+    let mut needs_reset_frame = None;
+
     // Run for a max of 240 frames, just in case the test ROM never completes
     for frame in 0..240 {
         loop {
@@ -41,7 +49,13 @@ fn run_blargg_instr_test_with_expected_result(
             (_, 0) => {} // Ignore status on first frame
             (STATUS_TEST_IS_RUNNING, _) => {}
             (STATUS_TEST_NEEDS_RESET, _) => {
-                unimplemented!("Verification ROM requested a reset!");
+                // The test ROM expects the reset button to be pressed
+                // at least 100ms after asking for it
+                let needs_reset_since = *needs_reset_frame.get_or_insert(frame);
+                if frame - needs_reset_since >= 6 {
+                    nes.reset();
+                    needs_reset_frame = None;
+                }
             }
             _ => {
                 break;
@@ -116,24 +130,11 @@ fn rom_blargg_instr_test_abs() {
     );
 }
 
-// NOTE: The absolute-indexed test ROM fails with the below message in other
-// emulators. For now, we assume that the test is wrong, and verify that we
-// also see the below message.
-const ABS_XY_EXPECTED_FAILURE: &str = r##"9C SYA abs,X
-9E SXA abs,Y
-
-06-abs_xy
-
-Failed
-"##;
-
 #[test]
 fn rom_blargg_instr_test_abs_indexed() {
-    run_blargg_instr_test_with_expected_result(
+    run_blargg_instr_test(
         "06-abs_xy",
         include_bytes!("./fixtures/nes-test-roms/nes_instr_test/rom_singles/06-abs_xy.nes"),
-        1,
-        &ABS_XY_EXPECTED_FAILURE,
     );
 }
 